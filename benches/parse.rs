@@ -0,0 +1,108 @@
+// Parse time and heap usage on a large generated C file.
+//
+//     cargo bench --bench parse [-- <functions>]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rast_parse_test::{lex, parse};
+
+// Counts live and peak heap bytes and the number of allocations
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        System.dealloc(p, layout);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, p: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        let new = System.realloc(p, layout, size);
+        if !new.is_null() {
+            if size > layout.size() {
+                let live = LIVE.fetch_add(size - layout.size(), Ordering::Relaxed) + size - layout.size();
+                PEAK.fetch_max(live, Ordering::Relaxed);
+            } else {
+                LIVE.fetch_sub(layout.size() - size, Ordering::Relaxed);
+            }
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// A translation unit exercising declarations, statements and expressions of every kind
+fn generate(functions: usize) -> String {
+    let mut s = String::new();
+    s.push_str("struct node { int val; struct node *next; double w[4]; };\n");
+    s.push_str("typedef struct node Node;\n");
+    s.push_str("enum color { RED, GREEN = 5, BLUE };\n");
+    s.push_str("int printf(const char *fmt, ...);\n");
+    for i in 0..functions {
+        writeln!(s, "static int table{i}[] = {{ 1, 2, 3, [7] = {i} }};").unwrap();
+        writeln!(s, "int f{i}(Node *n, int a, unsigned long b) {{").unwrap();
+        s.push_str("    int x = a * 2 + (int)b % 7, y[8];\n");
+        s.push_str("    struct node local = { .val = 1, .next = 0 };\n");
+        s.push_str("    for (int j = 0, k = 8; j < k && x != BLUE; j++, k--) {\n");
+        s.push_str("        y[j] = n->next->val + local.w[j % 4] * -x;\n");
+        s.push_str("        if (y[j] >= 10 || !x) continue; else if (y[j] == 3) break; else x += sizeof(Node) / 2;\n");
+        s.push_str("    }\n");
+        s.push_str("    while (x > 0) x -= 3;\n");
+        s.push_str("    switch (a) { case RED: x = 1; break; case GREEN + 1: x = 2; default: x = 3; }\n");
+        writeln!(s, "    if (x < 0) goto out;\n    printf(\"%d\\n\", table{i}[a], x);").unwrap();
+        s.push_str("out:\n    return x;\n");
+        s.push_str("}\n");
+    }
+    s
+}
+
+fn main() {
+    let functions = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(5000);
+    let src = generate(functions);
+    let toks = lex(&src).unwrap();
+
+    // Heap held by the finished AST and the peak reached while building it
+    let before = LIVE.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    ALLOCS.store(0, Ordering::Relaxed);
+    let ast = parse(&toks).unwrap();
+    let retained = LIVE.load(Ordering::Relaxed) - before;
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    drop(black_box(ast));
+
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while times.len() < 5 || start.elapsed() < Duration::from_secs(3) {
+        let t = Instant::now();
+        black_box(parse(black_box(&toks)).unwrap());
+        times.push(t.elapsed());
+    }
+    times.sort();
+
+    println!("input:     {} functions, {} KiB, {} tokens", functions, src.len() / 1024, toks.len());
+    println!("parse:     {:?} median, {:?} best of {} runs", times[times.len() / 2], times[0], times.len());
+    println!("retained:  {} KiB", retained / 1024);
+    println!("peak:      {} KiB", peak / 1024);
+    println!("allocs:    {}", allocs);
+}
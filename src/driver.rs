@@ -0,0 +1,330 @@
+//! Command-line driver, taking the options of `cc` for the stages this compiler has.
+//!
+//! Every input is compiled on its own and the outputs are written one after another, to `-o` or stdout.
+//! Errors and warnings go to stderr as `file:line:col: error: message`, and nothing is written if there was an error.
+//! Exit codes: 0 on success, 1 if an input has an error or the output can't be produced, 2 for a bad command line.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+
+use crate::lex::lexer::{lex, line_col};
+use crate::lex::preprocessor::Preprocessor;
+use crate::lex::source::Buffered;
+use crate::parse::{dump, printer};
+use crate::parse::parser::parse_from;
+use crate::sema::diag::{has_errors, Diagnostic};
+use crate::sema::resolve::resolve;
+use crate::sema::types::check;
+use crate::sema::typed::annotate;
+use crate::Error;
+
+pub const USAGE: &str = "\
+usage: rast_parse_test [options] [file.c ...]
+
+Reads the files in order, or stdin for `-` or if there are none.
+
+  -o <path>                 write the output there instead of stdout
+  --emit=<stage>            tokens, ast (the default), ir, asm, obj or exe
+  -c, -S                    same as --emit=obj and --emit=asm
+  --dump=<format>           the AST as tree (the default), sexpr, json, dot, c or debug
+  -I <dir>                  add an include directory, unused as #include is not supported yet
+  -D <name>[=<value>]       define a macro, as 1 if there is no value
+  -std=<standard>           c89, c90, c99, c11 (the default), c17 or c18, or the gnu ones
+  -O<level>                 0 (the default), 1, 2, 3 or s, -O is -O1
+  -h, --help                show this
+";
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Asm,
+    Obj,
+    Exe,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Options {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub emit: Emit,
+    /// format of --emit=ast
+    pub dump: String,
+    /// -I, kept for #include, which is an error until it is supported
+    pub include_dirs: Vec<String>,
+    pub defines: Vec<(String, String)>,
+    pub std: String,
+    pub opt_level: String,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            inputs: Vec::new(), output: None, emit: Emit::Ast, dump: "tree".to_string(),
+            include_dirs: Vec::new(), defines: Vec::new(), std: "c11".to_string(), opt_level: "0".to_string(),
+            help: false,
+        }
+    }
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // `-o out` and `-oout` alike
+        let mut value = |flag: &str| match &arg[flag.len()..] {
+            "" => args.next().ok_or(format!("missing argument to '{}'", flag)),
+            v => Ok(v.to_string()),
+        };
+        match arg.as_str() {
+            "-h" | "--help" => opts.help = true,
+            "-c" => opts.emit = Emit::Obj,
+            "-S" => opts.emit = Emit::Asm,
+            "-" => opts.inputs.push(arg),
+            _ if arg.starts_with("-o") => opts.output = Some(value("-o")?),
+            _ if arg.starts_with("-I") => opts.include_dirs.push(value("-I")?),
+            _ if arg.starts_with("-D") => {
+                let def = value("-D")?;
+                let (name, val) = def.split_once('=').unwrap_or((&def, "1"));
+                if !is_identifier(name) {
+                    return Err(format!("macro name '{}' is not an identifier", name));
+                }
+                if let Err(e) = lex(val) {
+                    return Err(format!("bad value for macro '{}': {}", name, e));
+                }
+                opts.defines.push((name.to_string(), val.to_string()));
+            },
+            _ if arg.starts_with("--emit=") => opts.emit = match &arg["--emit=".len()..] {
+                "tokens" => Emit::Tokens,
+                "ast" => Emit::Ast,
+                "ir" => Emit::Ir,
+                "asm" => Emit::Asm,
+                "obj" => Emit::Obj,
+                "exe" => Emit::Exe,
+                s => return Err(format!("unknown --emit stage '{}', expected tokens, ast, ir, asm, obj or exe", s)),
+            },
+            _ if arg.starts_with("--dump=") => opts.dump = match &arg["--dump=".len()..] {
+                s @ ("tree" | "sexpr" | "json" | "dot" | "c" | "debug") => s.to_string(),
+                s => return Err(format!("unknown --dump format '{}', expected tree, sexpr, json, dot, c or debug", s)),
+            },
+            _ if arg.starts_with("-std=") => opts.std = match &arg["-std=".len()..] {
+                s if ["89", "90", "99", "11", "17", "18"].iter().any(|v| s == format!("c{}", v) || s == format!("gnu{}", v)) => s.to_string(),
+                s => return Err(format!("unknown standard '{}'", s)),
+            },
+            _ if arg.starts_with("-O") => opts.opt_level = match &arg["-O".len()..] {
+                "" => "1".to_string(),
+                s @ ("0" | "1" | "2" | "3" | "s") => s.to_string(),
+                s => return Err(format!("unknown optimization level '{}'", s)),
+            },
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => opts.inputs.push(arg),
+        }
+    }
+    Ok(opts)
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|ch: char| ch.is_alphabetic() || ch == '_') && s.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Runs the driver on the arguments after the program name, returning the exit code
+pub fn main(args: impl IntoIterator<Item = String>) -> i32 {
+    match parse_args(args) {
+        Ok(opts) if opts.help => {
+            print!("{}", USAGE);
+            0
+        },
+        Ok(opts) => run(&opts),
+        Err(msg) => {
+            eprintln!("error: {}", msg);
+            eprintln!("try '--help' for the options");
+            2
+        },
+    }
+}
+
+pub fn run(opts: &Options) -> i32 {
+    let stage = match opts.emit {
+        Emit::Tokens | Emit::Ast => None,
+        Emit::Ir => Some("ir"),
+        Emit::Asm => Some("asm"),
+        Emit::Obj => Some("obj"),
+        Emit::Exe => Some("exe"),
+    };
+    if let Some(stage) = stage {
+        eprintln!("error: --emit={} needs a code generator, which this compiler doesn't have yet", stage);
+        return 1;
+    }
+
+    let stdin = ["-".to_string()];
+    let inputs = if opts.inputs.is_empty() { &stdin[..] } else { &opts.inputs[..] };
+    let mut out = String::new();
+    let mut failed = false;
+    for path in inputs {
+        let name = if path == "-" { "<stdin>" } else { path };
+        let src = match read(path) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("error: {}: {}", name, e);
+                failed = true;
+                continue;
+            },
+        };
+        match emit(&src, opts) {
+            Ok((s, diags)) => {
+                report(name, &src, &diags);
+                match has_errors(&diags) {
+                    true => failed = true,
+                    false => out.push_str(&s),
+                }
+            },
+            Err(e) => {
+                report(name, &src, &[Diagnostic::error(e.to_string(), e.span())]);
+                failed = true;
+            },
+        }
+    }
+    if failed {
+        return 1;
+    }
+
+    let written = match &opts.output {
+        Some(path) => fs::write(path, out),
+        None => io::stdout().write_all(out.as_bytes()),
+    };
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}: {}", opts.output.as_deref().unwrap_or("<stdout>"), e);
+            1
+        },
+    }
+}
+
+fn report(name: &str, src: &str, diags: &[Diagnostic]) {
+    for d in diags {
+        let (line, col) = line_col(src, d.span.lo);
+        eprintln!("{}:{}:{}: {}: {}", name, line, col, d.level, d);
+        if let Some((msg, span)) = &d.note {
+            let (line, col) = line_col(src, span.lo);
+            eprintln!("{}:{}:{}: note: {}", name, line, col, msg);
+        }
+    }
+}
+
+fn read(path: &str) -> io::Result<String> {
+    match path {
+        "-" => {
+            let mut s = String::new();
+            io::stdin().read_to_string(&mut s)?;
+            Ok(s)
+        },
+        _ => fs::read_to_string(path),
+    }
+}
+
+// The preprocessor with the macros of the command line: -D ones, and those -std and -O imply
+fn preprocessor<'a>(src: &'a str, opts: &Options) -> Result<Preprocessor<'a>, Error> {
+    let mut pp = Preprocessor::new(src);
+    let version = match &opts.std[opts.std.len() - 2..] {
+        "99" => Some("199901"),
+        "11" => Some("201112"),
+        "17" | "18" => Some("201710"),
+        _ => None,
+    };
+    if let Some(version) = version {
+        pp.define("__STDC_VERSION__", version)?;
+    }
+    if opts.opt_level != "0" {
+        pp.define("__OPTIMIZE__", "1")?;
+    }
+    for (name, value) in &opts.defines {
+        pp.define(name, value)?;
+    }
+    Ok(pp)
+}
+
+// The output for one input, and what the checks after parsing found
+fn emit(src: &str, opts: &Options) -> Result<(String, Vec<Diagnostic>), Error> {
+    let pp = preprocessor(src, opts)?;
+    if opts.emit == Emit::Tokens {
+        let mut out = String::new();
+        for tok in pp {
+            let (t, span) = tok?;
+            let (line, col) = line_col(src, span.lo);
+            writeln!(out, "{}:{}: {:?}", line, col, t).unwrap();
+        }
+        return Ok((out, Vec::new()));
+    }
+
+    let tu = parse_from(&mut Buffered::new(pp))?;
+    let res = resolve(&tu);
+    let mut typing = check(&tu, &res);
+    let diags: Vec<_> = res.diags.iter().cloned().chain(std::mem::take(&mut typing.diags)).collect();
+    // the tree is dumped with its implicit conversions once it has no errors
+    let tu = match has_errors(&diags) {
+        true => tu,
+        false => annotate(tu, &res, typing).tu,
+    };
+    let out = match opts.dump.as_str() {
+        "tree" => dump::tree(&tu, src),
+        "sexpr" => dump::sexpr(&tu),
+        "json" => dump::json(&tu),
+        "dot" => dump::dot(&tu, src),
+        "c" => printer::print(&tu),
+        "debug" => format!("{:?}\n", tu),
+        _ => unreachable!(),
+    };
+    Ok((out, diags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Options, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn options() {
+        let opts = args("-o out.txt --emit=tokens -Iinc -I /usr/include -DDEBUG -D N=10 -std=gnu99 -O a.c - b.c").unwrap();
+        assert_eq!(opts, Options {
+            inputs: vec!["a.c".to_string(), "-".to_string(), "b.c".to_string()],
+            output: Some("out.txt".to_string()),
+            emit: Emit::Tokens,
+            include_dirs: vec!["inc".to_string(), "/usr/include".to_string()],
+            defines: vec![("DEBUG".to_string(), "1".to_string()), ("N".to_string(), "10".to_string())],
+            std: "gnu99".to_string(),
+            opt_level: "1".to_string(),
+            ..Options::default()
+        });
+        assert_eq!(args("-c x.c").unwrap().emit, Emit::Obj);
+        assert_eq!(args("--dump=json").unwrap().dump, "json");
+    }
+
+    #[test]
+    fn include_dirs() {
+        let opts = args("-Iinc").unwrap();
+        assert_eq!(emit("int x;\n", &opts).map(|(_, diags)| diags.len()), Ok(0));
+        let e = emit("#include <stdio.h>\n", &opts).unwrap_err();
+        assert_eq!(e.to_string(), "#include is not supported");
+    }
+
+    #[test]
+    fn bad_options() {
+        assert_eq!(args("-o"), Err("missing argument to '-o'".to_string()));
+        assert_eq!(args("-D1X"), Err("macro name '1X' is not an identifier".to_string()));
+        assert_eq!(args("-DX='a"), Err("bad value for macro 'X': missing terminating ' character".to_string()));
+        assert_eq!(args("-std=c2x"), Err("unknown standard 'c2x'".to_string()));
+        assert_eq!(args("-O4"), Err("unknown optimization level '4'".to_string()));
+        assert_eq!(args("--verbose"), Err("unknown option '--verbose'".to_string()));
+        assert!(args("--emit=llvm").is_err());
+    }
+}
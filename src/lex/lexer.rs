@@ -1,8 +1,13 @@
+//! Tokens of C source text, each with the byte range it was written at.
+
+use std::fmt;
+
 #[derive(Debug)]
+#[derive(Clone)]
 #[derive(PartialEq)]
 pub enum Token {
     LP, RP, LC, RC, LB, RB,
-    Inum { n: i32 }, Fnum { n: f64 }, Cnum { n: u8 },
+    Inum { n: i32 }, Fnum { n: f64 }, Cnum { n: u8 }, Str { s: String },
     Id { id: String },
     Plus, Minus, Star, Slash, Percent,
     Bigger, Lesser, Equal, NotEqual,
@@ -10,124 +15,328 @@ pub enum Token {
     Assignment, 
     AddAssign, SubAssign, MulAssign, DivAssign, ModAssign,
     Inc, Dec,
-    Semicolon, Comma, Dot, Ampersand,
+    Sizeof, Alignof,
+    Semicolon, Colon, Comma, Dot, Ellipsis, Arrow, Ampersand,
     And, Or, Negation,
     If, Else, For, While, Break, Continue, Return, 
+    Switch, Case, Default, Goto,
     IntT, FloatT, CharT, VoidT,
+    ShortT, LongT, DoubleT, BoolT, Signed, Unsigned,
+    Struct, Union, Enum, Typedef,
+    Const, Volatile, Restrict,
+    Extern, Static, Auto, Register, Inline, ThreadLocal,
 }
 
-pub fn lex(s: &String) -> Vec<Token> {
-    let mut toks: Vec<Token> = Vec::new();
-    let mut chs = s.chars().peekable();
-    while let Some(ch) = chs.next() {
-        if ch.is_whitespace() { continue; }
-        match ch {
-            '(' => toks.push(Token::LP),
-            ')' => toks.push(Token::RP),
-            '{' => toks.push(Token::LC),
-            '}' => toks.push(Token::RC),
-            '[' => toks.push(Token::LB),
-            ']' => toks.push(Token::RB),
-            ';' => toks.push(Token::Semicolon),
-            ',' => toks.push(Token::Comma),
-            '.' => toks.push(Token::Dot),
-            '>' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::BiggerEqual); chs.next(); },
-                _ => toks.push(Token::Bigger), },
-            '<' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::LesserEqual); chs.next(); },
-                _ => toks.push(Token::Lesser), },
-            '*' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::MulAssign); chs.next(); },
-                _ => toks.push(Token::Star), },
-            '/' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::DivAssign); chs.next(); },
-                Some(&'/') => loop { if chs.next() == Some('\n') { break; } },
-                _ => toks.push(Token::Slash), },
-            '%' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::ModAssign); chs.next(); },
-                _ => toks.push(Token::Percent), },
-            '-' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::SubAssign); chs.next(); },
-                Some(&'-') => { toks.push(Token::Dec); chs.next(); },
-                _ => toks.push(Token::Minus), },
-            '+' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::AddAssign); chs.next(); },
-                Some(&'+') => { toks.push(Token::Inc); chs.next(); },
-                _ => toks.push(Token::Plus), },
-            '&' => match chs.peek() {
-                Some(&'&') => { toks.push(Token::And); chs.next(); },
-                _ => toks.push(Token::Ampersand), },
-            '=' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::Equal); chs.next(); },
-                _ => toks.push(Token::Assignment), },
-            '!' => match chs.peek() {
-                Some(&'=') => { toks.push(Token::NotEqual); chs.next(); },
-                _ => toks.push(Token::Negation), },
-            '|' if chs.peek() == Some(&'|') => {
-                chs.next();
-                toks.push(Token::Or); 
-            },
-            _ if ch.is_alphabetic() => {
-                let mut lex = String::new(); lex.push(ch);
-                while let Some(&nch) = chs.peek() {
-                    match nch {
-                        _ if nch.is_alphanumeric() 
-                          || nch == '_' => lex.push(nch),
-                        _ => break
-                    } chs.next();
-                }
+// Tokens as written in the source, for error messages
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Token::Inum { n } => return write!(f, "{}", n),
+            Token::Fnum { n } => return write!(f, "{:?}", n),
+            Token::Cnum { n } => return write!(f, "{:?}", *n as char),
+            Token::Str { s } => return write!(f, "{:?}", s),
+            Token::Id { id } => id,
+            Token::LP => "(", Token::RP => ")", Token::LC => "{", Token::RC => "}", Token::LB => "[", Token::RB => "]",
+            Token::Plus => "+", Token::Minus => "-", Token::Star => "*", Token::Slash => "/", Token::Percent => "%",
+            Token::Bigger => ">", Token::Lesser => "<", Token::Equal => "==", Token::NotEqual => "!=",
+            Token::BiggerEqual => ">=", Token::LesserEqual => "<=",
+            Token::Assignment => "=",
+            Token::AddAssign => "+=", Token::SubAssign => "-=", Token::MulAssign => "*=", Token::DivAssign => "/=", Token::ModAssign => "%=",
+            Token::Inc => "++", Token::Dec => "--",
+            Token::Sizeof => "sizeof", Token::Alignof => "_Alignof",
+            Token::Semicolon => ";", Token::Colon => ":", Token::Comma => ",", Token::Dot => ".",
+            Token::Ellipsis => "...", Token::Arrow => "->", Token::Ampersand => "&",
+            Token::And => "&&", Token::Or => "||", Token::Negation => "!",
+            Token::If => "if", Token::Else => "else", Token::For => "for", Token::While => "while",
+            Token::Break => "break", Token::Continue => "continue", Token::Return => "return",
+            Token::Switch => "switch", Token::Case => "case", Token::Default => "default", Token::Goto => "goto",
+            Token::IntT => "int", Token::FloatT => "float", Token::CharT => "char", Token::VoidT => "void",
+            Token::ShortT => "short", Token::LongT => "long", Token::DoubleT => "double", Token::BoolT => "_Bool",
+            Token::Signed => "signed", Token::Unsigned => "unsigned",
+            Token::Struct => "struct", Token::Union => "union", Token::Enum => "enum", Token::Typedef => "typedef",
+            Token::Const => "const", Token::Volatile => "volatile", Token::Restrict => "restrict",
+            Token::Extern => "extern", Token::Static => "static", Token::Auto => "auto", Token::Register => "register",
+            Token::Inline => "inline", Token::ThreadLocal => "_Thread_local",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Byte range of a token or node in the source, `hi` excluded
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+}
+
+/// 1-based line and column of a byte offset, for showing spans to people
+pub fn line_col(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset.min(s.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+// Peekable chars that also know their byte offset in the source
+struct Cursor<'a> {
+    chs: std::iter::Peekable<std::str::CharIndices<'a>>,
+    len: usize,
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+    fn next(&mut self) -> Option<char> {
+        self.chs.next().map(|(_, ch)| ch)
+    }
+}
+
+impl Cursor<'_> {
+    fn peek(&mut self) -> Option<&char> {
+        self.chs.peek().map(|(_, ch)| ch)
+    }
+
+    fn pos(&mut self) -> usize {
+        self.chs.peek().map_or(self.len, |(i, _)| *i)
+    }
+}
 
-                match lex.as_str() {
-                    "if" => toks.push(Token::If),
-                    "else" => toks.push(Token::Else),
-                    "for" => toks.push(Token::For),
-                    "while" => toks.push(Token::While),
-                    "break" => toks.push(Token::Break),
-                    "continue" => toks.push(Token::Continue),
-                    "return" => toks.push(Token::Return),
-                    "int" => toks.push(Token::IntT),
-                    "float" => toks.push(Token::FloatT),
-                    "char" => toks.push(Token::CharT),
-                    "void" => toks.push(Token::VoidT),
-                    _ => toks.push(Token::Id { id: lex })
+/// Error in the source text at the characters of `span`. Lexing stops at the first one.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct LexError {
+    pub msg: String,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Tokens of the source one at a time, each with its span. After an error it yields nothing more.
+pub struct Lexer<'a> {
+    chs: Cursor<'a>,
+    failed: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(s: &'a str) -> Lexer<'a> {
+        Lexer { chs: Cursor { chs: s.char_indices().peekable(), len: s.len() }, failed: false }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<(Token, Span), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let lo = self.chs.pos();
+            let ch = self.chs.next()?;
+            let span = |chs: &mut Cursor| Span { lo, hi: chs.pos() };
+            match token(ch, &mut self.chs) {
+                Ok(Some(t)) => return Some(Ok((t, span(&mut self.chs)))),
+                Ok(None) => continue,
+                Err(msg) => {
+                    self.failed = true;
+                    return Some(Err(LexError { msg, span: span(&mut self.chs) }));
+                },
+            }
+        }
+    }
+}
+
+pub fn lex(s: &str) -> Result<Vec<Token>, LexError> {
+    Ok(lex_with_spans(s)?.0)
+}
+
+/// Tokens along with the span of each one
+pub fn lex_with_spans(s: &str) -> Result<(Vec<Token>, Vec<Span>), LexError> {
+    Lexer::new(s).collect::<Result<Vec<_>, _>>().map(|v| v.into_iter().unzip())
+}
+
+// The token starting with `ch`, None for whitespace and comments
+fn token(ch: char, chs: &mut Cursor) -> Result<Option<Token>, String> {
+    let t = match ch {
+        _ if ch.is_whitespace() => return Ok(None),
+        '(' => Token::LP,
+        ')' => Token::RP,
+        '{' => Token::LC,
+        '}' => Token::RC,
+        '[' => Token::LB,
+        ']' => Token::RB,
+        ';' => Token::Semicolon,
+        ':' => Token::Colon,
+        ',' => Token::Comma,
+        '.' => match chs.peek() {
+            Some(&'.') => { 
+                chs.next();
+                match chs.next() {
+                    Some('.') => Token::Ellipsis,
+                    _ => return Err("expected '...'".to_string()),
                 }
             },
-            _ if ch.is_numeric() => {
-                let mut points: u8 = 0;
-                let mut lex = String::new(); lex.push(ch);
-                while let Some(&nch) = chs.peek() {
-                    match nch {
-                        _ if nch.is_numeric() => lex.push(nch),
-                        '.' => { points += 1; lex.push(nch); },
-                        _ => break
-                    } chs.next();
-                }
-            
-                match points {
-                    0 => match lex.parse::<i32>() {
-                        Ok(num) => toks.push(Token::Inum { n: num }),
-                        Err(_) => panic!()
-                    },
-                    1 => match lex.parse::<f64>() {
-                        Ok(num) => toks.push(Token::Fnum { n: num }),
-                        Err(_) => panic!()
-                    },
-                    _ => panic!()
-                }
+            _ => Token::Dot, },
+        '>' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::BiggerEqual },
+            _ => Token::Bigger, },
+        '<' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::LesserEqual },
+            _ => Token::Lesser, },
+        '*' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::MulAssign },
+            _ => Token::Star, },
+        '/' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::DivAssign },
+            Some(&'/') => { 
+                while !matches!(chs.next(), Some('\n') | None) {}
+                return Ok(None);
             },
-            _ if ch == '\'' => {
-                let lex = chs.next();
+            _ => Token::Slash, },
+        '%' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::ModAssign },
+            _ => Token::Percent, },
+        '-' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::SubAssign },
+            Some(&'-') => { chs.next(); Token::Dec },
+            Some(&'>') => { chs.next(); Token::Arrow },
+            _ => Token::Minus, },
+        '+' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::AddAssign },
+            Some(&'+') => { chs.next(); Token::Inc },
+            _ => Token::Plus, },
+        '&' => match chs.peek() {
+            Some(&'&') => { chs.next(); Token::And },
+            _ => Token::Ampersand, },
+        '=' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::Equal },
+            _ => Token::Assignment, },
+        '!' => match chs.peek() {
+            Some(&'=') => { chs.next(); Token::NotEqual },
+            _ => Token::Negation, },
+        '|' if chs.peek() == Some(&'|') => {
+            chs.next();
+            Token::Or
+        },
+        _ if ch.is_alphabetic() || ch == '_' => {
+            let mut lex = String::new(); lex.push(ch);
+            while let Some(&nch) = chs.peek() {
+                match nch {
+                    _ if nch.is_alphanumeric() 
+                      || nch == '_' => lex.push(nch),
+                    _ => break
+                } chs.next();
+            }
+
+            match lex.as_str() {
+                "if" => Token::If,
+                "else" => Token::Else,
+                "for" => Token::For,
+                "while" => Token::While,
+                "break" => Token::Break,
+                "continue" => Token::Continue,
+                "return" => Token::Return,
+                "switch" => Token::Switch,
+                "case" => Token::Case,
+                "default" => Token::Default,
+                "goto" => Token::Goto,
+                "sizeof" => Token::Sizeof,
+                "_Alignof" => Token::Alignof,
+                "int" => Token::IntT,
+                "float" => Token::FloatT,
+                "char" => Token::CharT,
+                "void" => Token::VoidT,
+                "short" => Token::ShortT,
+                "long" => Token::LongT,
+                "double" => Token::DoubleT,
+                "_Bool" => Token::BoolT,
+                "signed" => Token::Signed,
+                "unsigned" => Token::Unsigned,
+                "struct" => Token::Struct,
+                "union" => Token::Union,
+                "enum" => Token::Enum,
+                "typedef" => Token::Typedef,
+                "const" => Token::Const,
+                "volatile" => Token::Volatile,
+                "restrict" => Token::Restrict,
+                "extern" => Token::Extern,
+                "static" => Token::Static,
+                "auto" => Token::Auto,
+                "register" => Token::Register,
+                "inline" => Token::Inline,
+                "_Thread_local" => Token::ThreadLocal,
+                _ => Token::Id { id: lex }
+            }
+        },
+        _ if ch.is_numeric() => {
+            let mut points: u8 = 0;
+            let mut lex = String::new(); lex.push(ch);
+            while let Some(&nch) = chs.peek() {
+                match nch {
+                    _ if nch.is_numeric() => lex.push(nch),
+                    '.' => { points += 1; lex.push(nch); },
+                    _ => break
+                } chs.next();
+            }
+        
+            match points {
+                0 => match lex.parse::<i32>() {
+                    Ok(num) => Token::Inum { n: num },
+                    Err(_) => return Err(format!("integer constant '{}' is too large", lex)),
+                },
+                1 => match lex.parse::<f64>() {
+                    Ok(num) => Token::Fnum { n: num },
+                    Err(_) => return Err(format!("invalid floating constant '{}'", lex)),
+                },
+                _ => return Err(format!("too many decimal points in number '{}'", lex)),
+            }
+        },
+        _ if ch == '\'' => {
+            let lex = match chs.next() {
+                Some('\\') => escape(chs)?,
+                Some('\'') => return Err("empty character constant".to_string()),
+                Some(ch) => ch,
+                None => return Err("missing terminating ' character".to_string()),
+            };
+            match chs.next() {
+                Some('\'') => Token::Cnum { n: lex as u8 },
+                _ => return Err("missing terminating ' character".to_string()),
+            }
+        },
+        _ if ch == '"' => {
+            let mut lex = String::new();
+            loop {
                 match chs.next() {
-                    Some('\'') => match lex.is_some() {
-                        true => toks.push(Token::Cnum { n: lex.unwrap() as u8 }),
-                        false => panic!()
-                    },
-                    _ => panic!()
+                    Some('"') => break,
+                    Some('\\') => lex.push(escape(chs)?),
+                    Some('\n') | None => return Err("missing terminating '\"' character".to_string()),
+                    Some(ch) => lex.push(ch),
                 }
-            },
-            _ => panic!("'{}' ..?", ch)
-        }
+            }
+            Token::Str { s: lex }
+        },
+        _ => return Err(format!("unexpected character '{}'", ch)),
+    };
+    Ok(Some(t))
+}
+
+// Reads the rest of an escape sequence after a backslash in a char or string literal
+fn escape(chs: &mut Cursor) -> Result<char, String> {
+    match chs.next() {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('0') => Ok('\0'),
+        Some(ch @ ('\\' | '\'' | '"' | '?')) => Ok(ch),
+        Some(ch) => Err(format!("unknown escape sequence '\\{}'", ch)),
+        None => Err("unfinished escape sequence".to_string()),
     }
-    toks
 }
@@ -0,0 +1,446 @@
+//! Macro-expanding preprocessor over the lexer. A line whose first character other than whitespace is `#`
+//! is a directive, of which it knows
+//!
+//! ```text
+//!   #define NAME tokens        #define NAME(a, b) tokens        #undef NAME
+//!   #if expr    #ifdef NAME    #ifndef NAME    #elif expr    #else    #endif
+//!   #error message
+//! ```
+//!
+//! `#include`, the `#` and `##` operators, variadic macros and line continuations are not supported.
+//! Like the tokens of the lexer, the ones of the preprocessor come one at a time, a run of lines between
+//! two directives at once. Tokens written in a macro body have the span of the invocation, from the name
+//! up to the closing parenthesis; tokens of arguments keep their own.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::lex::lexer::{LexError, Lexer, Span, Token};
+use crate::parse::parser::{const_eval, parse_expression, ConstError};
+
+#[derive(Debug)]
+#[derive(Clone)]
+enum Macro {
+    Object   { body: Vec<Token> },
+    Function { params: Vec<String>, body: Vec<Token> },
+}
+
+// An `#if` whose `#endif` is still to come
+struct Cond {
+    // whether lines of the current branch are kept
+    active: bool,
+    // whether a branch was taken, the later ones are skipped then
+    taken: bool,
+    // whether the `#if` itself is in kept lines
+    outer: bool,
+    else_seen: bool,
+    // of the `#if`, for when the `#endif` is missing
+    span: Span,
+}
+
+// Tokens with their spans, as the lexer yields them
+type Tokens = Vec<(Token, Span)>;
+
+// Input of macro expansion: tokens, and the end of the expansion of a macro, which can be expanded again after it
+enum Item {
+    Tok(Token, Span),
+    End(String),
+}
+
+pub struct Preprocessor<'a> {
+    src: &'a str,
+    /// offset of the next line to read
+    offset: usize,
+    macros: HashMap<String, Macro>,
+    conds: Vec<Cond>,
+    out: VecDeque<(Token, Span)>,
+    failed: bool,
+}
+
+impl<'a> Preprocessor<'a> {
+    pub fn new(src: &'a str) -> Preprocessor<'a> {
+        let mut pp = Preprocessor {
+            src, offset: 0, macros: HashMap::new(), conds: Vec::new(), out: VecDeque::new(), failed: false,
+        };
+        pp.macros.insert("__STDC__".to_string(), Macro::Object { body: vec![Token::Inum { n: 1 }] });
+        pp
+    }
+
+    /// Defines an object-like macro, as `-D name=value` does
+    pub fn define(&mut self, name: &str, value: &str) -> Result<(), LexError> {
+        let body = Lexer::new(value).map(|t| t.map(|(t, _)| t)).collect::<Result<_, _>>()?;
+        self.macros.insert(name.to_string(), Macro::Object { body });
+        Ok(())
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    fn active(&self) -> bool {
+        self.conds.last().is_none_or(|c| c.active)
+    }
+
+    // The next line with its offset, newline included
+    fn next_line(&mut self) -> Option<(usize, &'a str)> {
+        if self.offset >= self.src.len() {
+            return None;
+        }
+        let start = self.offset;
+        self.offset = self.src[start..].find('\n').map_or(self.src.len(), |i| start + i + 1);
+        Some((start, &self.src[start..self.offset]))
+    }
+
+    // Reads lines up to and including the next directive, queueing the tokens before it.
+    // Returns false at the end of the source.
+    fn fill(&mut self) -> Result<bool, LexError> {
+        let mut text = Vec::new();
+        while let Some((start, line)) = self.next_line() {
+            let trimmed = line.trim_start();
+            if let Some(rest) = trimmed.strip_prefix('#') {
+                let expanded = self.expand(text, &[])?;
+                self.out.extend(expanded);
+                let hash = start + line.len() - trimmed.len();
+                let end = start + line.trim_end().len();
+                self.directive(rest.trim_end(), hash + 1, Span { lo: hash, hi: end })?;
+                return Ok(true);
+            }
+            if self.active() {
+                text.extend(lex_at(line, start)?);
+            }
+        }
+        if let Some(c) = self.conds.last() {
+            return Err(LexError { msg: "unterminated conditional directive".to_string(), span: c.span });
+        }
+        let more = !text.is_empty();
+        let expanded = self.expand(text, &[])?;
+        self.out.extend(expanded);
+        Ok(more)
+    }
+
+    // `line` is what follows the `#`, starting at `offset`, `span` is the whole directive
+    fn directive(&mut self, line: &str, offset: usize, span: Span) -> Result<(), LexError> {
+        let rest = line.trim_start();
+        let offset = offset + line.len() - rest.len();
+        let len = rest.find(|ch: char| !(ch.is_alphanumeric() || ch == '_')).unwrap_or(rest.len());
+        let (name, args) = rest.split_at(len);
+        let error = |msg: String| Err(LexError { msg, span });
+        // skipped lines need not even be tokens, only conditionals are looked at there
+        if !self.active() && !matches!(name, "if" | "ifdef" | "ifndef" | "elif" | "else" | "endif") {
+            return Ok(());
+        }
+        let offset = offset + len;
+        match name {
+            "" if args.is_empty() => Ok(()),
+            "define" => self.parse_define(lex_at(args, offset)?, span),
+            "undef" => {
+                let name = macro_name(lex_at(args, offset)?, span)?;
+                self.macros.remove(&name);
+                Ok(())
+            },
+            "ifdef" | "ifndef" => {
+                let outer = self.active();
+                let value = outer && self.is_defined(&macro_name(lex_at(args, offset)?, span)?) == (name == "ifdef");
+                self.conds.push(Cond { active: value, taken: value, outer, else_seen: false, span });
+                Ok(())
+            },
+            "if" => {
+                let outer = self.active();
+                let value = outer && self.eval(lex_at(args, offset)?, span)?;
+                self.conds.push(Cond { active: value, taken: value, outer, else_seen: false, span });
+                Ok(())
+            },
+            "elif" => {
+                let c = match self.conds.last() {
+                    Some(c) if c.else_seen => return error("#elif after #else".to_string()),
+                    Some(c) => c,
+                    None => return error("#elif without #if".to_string()),
+                };
+                let value = c.outer && !c.taken && self.eval(lex_at(args, offset)?, span)?;
+                let c = self.conds.last_mut().unwrap();
+                c.active = value;
+                c.taken |= value;
+                Ok(())
+            },
+            "else" => match self.conds.last_mut() {
+                Some(c) if c.else_seen => error("#else after #else".to_string()),
+                Some(c) => {
+                    c.active = c.outer && !c.taken;
+                    c.taken = true;
+                    c.else_seen = true;
+                    Ok(())
+                },
+                None => error("#else without #if".to_string()),
+            },
+            "endif" => match self.conds.pop() {
+                Some(_) => Ok(()),
+                None => error("#endif without #if".to_string()),
+            },
+            "error" => error(format!("#error {}", args.trim())),
+            "include" => error("#include is not supported".to_string()),
+            _ => error(format!("invalid preprocessing directive '#{}'", name)),
+        }
+    }
+
+    fn parse_define(&mut self, toks: Tokens, span: Span) -> Result<(), LexError> {
+        let error = |msg: &str| Err(LexError { msg: msg.to_string(), span });
+        let mut toks = toks.into_iter().peekable();
+        let (name, name_span) = match toks.next() {
+            Some((Token::Id { id }, s)) => (id, s),
+            _ => return error("macro name must be an identifier"),
+        };
+        // a parenthesis right after the name, without a space, makes a function-like macro
+        let params = match toks.peek() {
+            Some((Token::LP, s)) if s.lo == name_span.hi => {
+                toks.next();
+                let mut params = Vec::new();
+                loop {
+                    match toks.next() {
+                        Some((Token::RP, _)) if params.is_empty() => break,
+                        Some((Token::Id { id }, _)) if !params.contains(&id) => params.push(id),
+                        Some((Token::Ellipsis, _)) => return error("variadic macros are not supported"),
+                        _ => return error("expected parameter name in macro parameter list"),
+                    }
+                    match toks.next() {
+                        Some((Token::Comma, _)) => continue,
+                        Some((Token::RP, _)) => break,
+                        _ => return error("expected ',' or ')' in macro parameter list"),
+                    }
+                }
+                Some(params)
+            },
+            _ => None,
+        };
+        let body = toks.map(|(t, _)| t).collect();
+        let m = match params {
+            Some(params) => Macro::Function { params, body },
+            None => Macro::Object { body },
+        };
+        self.macros.insert(name, m);
+        Ok(())
+    }
+
+    // Value of the condition of `#if` or `#elif`
+    fn eval(&self, toks: Tokens, span: Span) -> Result<bool, LexError> {
+        // `defined X` and `defined(X)` go first, so X isn't expanded
+        let mut toks = toks.into_iter();
+        let mut replaced = Vec::new();
+        while let Some((t, s)) = toks.next() {
+            if t != (Token::Id { id: "defined".to_string() }) {
+                replaced.push((t, s));
+                continue;
+            }
+            let name = match toks.next() {
+                Some((Token::Id { id }, _)) => Some(id),
+                Some((Token::LP, _)) => match (toks.next(), toks.next()) {
+                    (Some((Token::Id { id }, _)), Some((Token::RP, _))) => Some(id),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match name {
+                Some(name) => replaced.push((Token::Inum { n: self.is_defined(&name) as i32 }, s)),
+                None => return Err(LexError { msg: "macro name missing after 'defined'".to_string(), span }),
+            }
+        }
+        // names that are left aren't macros, and count as 0
+        let toks: Vec<Token> = self.expand(replaced, &[])?.into_iter()
+            .map(|(t, _)| match t {
+                Token::Id { .. } => Token::Inum { n: 0 },
+                t => t,
+            })
+            .collect();
+        let e = parse_expression(&toks, &[])
+            .map_err(|e| LexError { msg: format!("invalid preprocessor expression: {}", e.msg), span })?;
+        match const_eval(&e.ast, e.node) {
+            Ok(n) => Ok(n != 0),
+            Err(ConstError::Overflow) => Err(LexError { msg: "integer overflow in preprocessor expression".to_string(), span }),
+            Err(ConstError::NotConstant) => Err(LexError { msg: "preprocessor expression is not an integer constant".to_string(), span }),
+        }
+    }
+
+    // Expands the macros in `toks`, except for those in `disabled`
+    fn expand(&self, toks: Tokens, disabled: &[String]) -> Result<Tokens, LexError> {
+        let mut input: VecDeque<Item> = toks.into_iter().map(|(t, s)| Item::Tok(t, s)).collect();
+        // macros being expanded, which aren't again inside their own expansion
+        let mut disabled = disabled.to_vec();
+        let mut out = Vec::new();
+        while let Some(item) = input.pop_front() {
+            let (t, span) = match item {
+                Item::Tok(t, span) => (t, span),
+                Item::End(name) => {
+                    enable(&mut disabled, &name);
+                    continue;
+                },
+            };
+            let m = match &t {
+                Token::Id { id } if !disabled.contains(id) => self.macros.get(id),
+                _ => None,
+            };
+            let (name, m) = match (t, m) {
+                (Token::Id { id }, Some(m)) => (id, m),
+                (t, _) => {
+                    out.push((t, span));
+                    continue;
+                },
+            };
+            let (args, span) = match m {
+                Macro::Object { .. } => (Vec::new(), span),
+                Macro::Function { params, .. } => {
+                    // a function-like macro's name without arguments is just a name
+                    let call = input.iter().find_map(|item| match item {
+                        Item::Tok(t, _) => Some(*t == Token::LP),
+                        Item::End(_) => None,
+                    });
+                    if call != Some(true) {
+                        out.push((Token::Id { id: name }, span));
+                        continue;
+                    }
+                    let (args, end) = take_args(&mut input, &mut disabled, span)?;
+                    let args = match (params.len(), args.len()) {
+                        (0, 1) if args[0].is_empty() => Vec::new(),
+                        (n, given) if n == given => args,
+                        (n, given) => return Err(LexError {
+                            msg: format!("macro '{}' takes {} arguments, {} given", name, n, given),
+                            span: Span { lo: span.lo, hi: end.hi },
+                        }),
+                    };
+                    let args = args.into_iter().map(|a| self.expand(a, &disabled)).collect::<Result<Vec<_>, _>>()?;
+                    (args, Span { lo: span.lo, hi: end.hi })
+                },
+            };
+            let (params, body) = match m {
+                Macro::Object { body } => (&[][..], body),
+                Macro::Function { params, body } => (&params[..], body),
+            };
+            input.push_front(Item::End(name.clone()));
+            for t in body.iter().rev() {
+                let param = match t {
+                    Token::Id { id } => params.iter().position(|p| p == id),
+                    _ => None,
+                };
+                match param {
+                    Some(i) => args[i].iter().rev().for_each(|(t, s)| input.push_front(Item::Tok(t.clone(), *s))),
+                    None => input.push_front(Item::Tok(t.clone(), span)),
+                }
+            }
+            disabled.push(name);
+        }
+        Ok(out)
+    }
+}
+
+impl Iterator for Preprocessor<'_> {
+    type Item = Result<(Token, Span), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(t) = self.out.pop_front() {
+                return Some(Ok(t));
+            }
+            if self.failed {
+                return None;
+            }
+            match self.fill() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+    }
+}
+
+// Tokens of a piece of the source that starts at `offset`
+fn lex_at(s: &str, offset: usize) -> Result<Tokens, LexError> {
+    let shift = |span: Span| Span { lo: span.lo + offset, hi: span.hi + offset };
+    Lexer::new(s)
+        .map(|t| match t {
+            Ok((t, span)) => Ok((t, shift(span))),
+            Err(e) => Err(LexError { msg: e.msg, span: shift(e.span) }),
+        })
+        .collect()
+}
+
+// The single identifier after `#undef`, `#ifdef` and `#ifndef`
+fn macro_name(toks: Tokens, span: Span) -> Result<String, LexError> {
+    let mut toks = toks.into_iter();
+    match (toks.next(), toks.next()) {
+        (Some((Token::Id { id }, _)), None) => Ok(id),
+        _ => Err(LexError { msg: "expected a single macro name".to_string(), span }),
+    }
+}
+
+fn enable(disabled: &mut Vec<String>, name: &str) {
+    if let Some(i) = disabled.iter().rposition(|d| d == name) {
+        disabled.remove(i);
+    }
+}
+
+// The arguments of a function-like macro whose name was just taken, split at the commas outside parentheses,
+// and the span of the closing parenthesis
+fn take_args(input: &mut VecDeque<Item>, disabled: &mut Vec<String>, name: Span) -> Result<(Vec<Tokens>, Span), LexError> {
+    let mut args = vec![Vec::new()];
+    let mut depth = 0;
+    let mut opened = false;
+    loop {
+        let (t, span) = match input.pop_front() {
+            Some(Item::Tok(t, span)) => (t, span),
+            Some(Item::End(m)) => {
+                enable(disabled, &m);
+                continue;
+            },
+            None => return Err(LexError { msg: "unterminated macro invocation".to_string(), span: name }),
+        };
+        match t {
+            Token::LP if !opened => opened = true,
+            Token::RP if depth == 0 => return Ok((args, span)),
+            Token::Comma if depth == 0 => args.push(Vec::new()),
+            t => {
+                depth += (t == Token::LP) as i32 - (t == Token::RP) as i32;
+                args.last_mut().unwrap().push((t, span));
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pp(src: &str) -> Result<String, LexError> {
+        let toks = Preprocessor::new(src).map(|t| t.map(|(t, _)| t.to_string())).collect::<Result<Vec<_>, _>>()?;
+        Ok(toks.join(" "))
+    }
+
+    #[test]
+    fn macros() {
+        assert_eq!(pp("#define N 10\nint a[N];").unwrap(), "int a [ 10 ] ;");
+        assert_eq!(pp("#define SUM(a, b) ((a) + (b))\nSUM(x, f(1, 2))").unwrap(), "( ( x ) + ( f ( 1 , 2 ) ) )");
+        // no recursion, and a function-like name alone stays
+        assert_eq!(pp("#define x x + 1\n#define f(a) a\nx f").unwrap(), "x + 1 f");
+        assert_eq!(pp("#define A B\n#define B A\nA").unwrap(), "A");
+        assert_eq!(pp("#define N 1\n#undef N\nN").unwrap(), "N");
+        assert_eq!(pp("#define SQ(x) x * x\n#define TWO 2\nSQ(TWO)").unwrap(), "2 * 2");
+        assert_eq!(pp("__STDC__").unwrap(), "1");
+
+        let src = "#define ONE 1\nint x = ONE;";
+        let spans: Vec<Span> = Preprocessor::new(src).map(|t| t.unwrap().1).collect();
+        assert_eq!(&src[spans[3].lo..spans[3].hi], "ONE");
+    }
+
+    #[test]
+    fn conditionals() {
+        let src = "#define A 2\n#if A > 1 && !defined(B)\nyes\n#elif 1\nno\n#else\nno\n#endif\n#ifdef B\nno\n#endif";
+        assert_eq!(pp(src).unwrap(), "yes");
+        assert_eq!(pp("#ifndef A\n#if 0\n#bogus 'x\n#else\nb\n#endif\n#endif").unwrap(), "b");
+
+        let e = pp("int x;\n#if 1\n").unwrap_err();
+        assert_eq!((e.msg.as_str(), e.span), ("unterminated conditional directive", Span { lo: 7, hi: 12 }));
+        assert_eq!(pp("#error no way").unwrap_err().msg, "#error no way");
+        assert_eq!(pp("#endif").unwrap_err().msg, "#endif without #if");
+        assert_eq!(pp("#define f(a, b) a\nf(1)").unwrap_err().msg, "macro 'f' takes 2 arguments, 1 given");
+        assert_eq!(pp("#if 65536 * 65536\n#endif").unwrap_err().msg, "integer overflow in preprocessor expression");
+    }
+}
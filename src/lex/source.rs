@@ -0,0 +1,136 @@
+//! Token streams the parser reads from, with lookahead and checkpoints to go back to.
+
+use crate::lex::lexer::{LexError, Span, Token};
+
+/// Where the parser takes its tokens from. Tokens are numbered from 0 in the order they come,
+/// and the number of the next one is a checkpoint that `rewind` can go back to.
+pub trait TokenSource {
+    /// The token `n` places after the next one, without taking anything
+    fn peek_nth(&mut self, n: usize) -> Option<&Token>;
+
+    /// Takes the next token
+    fn next(&mut self) -> Option<&Token>;
+
+    /// Number of the next token
+    fn pos(&self) -> usize;
+
+    /// Goes back to an earlier `pos`, so the tokens from there come again
+    fn rewind(&mut self, pos: usize);
+
+    /// Span of a token that was already peeked or taken, None if it wasn't or the source has no spans
+    fn span(&self, i: usize) -> Option<Span>;
+
+    /// Why the tokens ended early, if they did
+    fn error(&self) -> Option<&LexError> {
+        None
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+}
+
+/// Tokens that are all there already, with their spans or none at all
+pub struct SliceTokens<'a> {
+    toks: &'a [Token],
+    spans: &'a [Span],
+    pos: usize,
+}
+
+impl<'a> SliceTokens<'a> {
+    pub fn new(toks: &'a [Token], spans: &'a [Span]) -> SliceTokens<'a> {
+        SliceTokens { toks, spans, pos: 0 }
+    }
+}
+
+impl TokenSource for SliceTokens<'_> {
+    fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        self.toks.get(self.pos + n)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        let t = self.toks.get(self.pos)?;
+        self.pos += 1;
+        Some(t)
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn rewind(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    fn span(&self, i: usize) -> Option<Span> {
+        self.spans.get(i).copied()
+    }
+}
+
+/// Tokens read from a `Lexer`, a `Preprocessor` or anything else yielding them with spans, as far as
+/// they are looked at. Everything read is kept, so any checkpoint can be rewound to.
+pub struct Buffered<I> {
+    iter: I,
+    toks: Vec<Token>,
+    spans: Vec<Span>,
+    pos: usize,
+    done: bool,
+    error: Option<LexError>,
+}
+
+impl<I: Iterator<Item = Result<(Token, Span), LexError>>> Buffered<I> {
+    pub fn new(iter: I) -> Buffered<I> {
+        Buffered { iter, toks: Vec::new(), spans: Vec::new(), pos: 0, done: false, error: None }
+    }
+
+    // Reads until token `i` is there, returning whether it is
+    fn fill(&mut self, i: usize) -> bool {
+        while self.toks.len() <= i && !self.done {
+            match self.iter.next() {
+                Some(Ok((t, span))) => {
+                    self.toks.push(t);
+                    self.spans.push(span);
+                },
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    self.done = true;
+                },
+                None => self.done = true,
+            }
+        }
+        i < self.toks.len()
+    }
+}
+
+impl<I: Iterator<Item = Result<(Token, Span), LexError>>> TokenSource for Buffered<I> {
+    fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        match self.fill(self.pos + n) {
+            true => Some(&self.toks[self.pos + n]),
+            false => None,
+        }
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        if !self.fill(self.pos) {
+            return None;
+        }
+        self.pos += 1;
+        Some(&self.toks[self.pos - 1])
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn rewind(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    fn span(&self, i: usize) -> Option<Span> {
+        self.spans.get(i).copied()
+    }
+
+    fn error(&self) -> Option<&LexError> {
+        self.error.as_ref()
+    }
+}
@@ -0,0 +1,184 @@
+//! Lexer and parser for a subset of C.
+//!
+//! Source text goes through [`Lexer`] (or [`lex()`]) into tokens and through [`parse()`] into a
+//! [`TranslationUnit`]: an arena of expressions, statements and declarations addressed by
+//! [`ExprId`], [`StmtId`] and [`DeclId`], the external declarations in source order, and the
+//! source range of every node when the tokens came with spans. [`parse_source`] does both steps.
+//! [`parse_from`] takes the tokens from any [`TokenSource`] as it goes: a slice ([`SliceTokens`]),
+//! or the [`Lexer`] or the macro-expanding [`Preprocessor`] through [`Buffered`].
+//! [`parse_expression`], [`parse_statement`], [`parse_type_name`] and [`parse_declaration`] parse
+//! a single piece instead of a whole file, into a [`Fragment`]; [`parse_str`] lexes for them.
+//!
+//! Neither stage panics on bad input. The lexer stops at the first malformed token with a
+//! [`LexError`], the parser at the first syntax or constraint violation with a [`ParseError`];
+//! each carries a message and the byte [`Span`] it refers to, which [`line_col`] turns into a
+//! position for people. Nothing is recovered after an error, so there is at most one per run.
+//!
+//! Semantic passes run on a tree that parsed and report everything they find as [`Diagnostic`]s,
+//! errors and warnings alike. [`resolve`] links identifiers to their declarations, [`check`] gives every
+//! expression its type by C's conversion rules and rejects operands and conversions C doesn't allow,
+//! and [`annotate`] turns a tree that passed both into a [`TypedUnit`] where every implicit conversion
+//! is an [`Expr::ImplicitCast`] node.
+//!
+//! The [`parse::printer`] and [`parse::dump`] modules turn a tree back into C or into dumps for
+//! people and tools, [`parse::visit`] has traits for walking and rewriting it. [`driver`] is the
+//! command-line compiler built on all of them.
+
+/// Turning source text into tokens: the lexer, token sources for the parser and the preprocessor
+pub mod lex {
+    pub mod lexer;
+    pub mod source;
+    pub mod preprocessor;
+}
+
+/// The syntax tree: the parser, and printing, dumping and traversing what it makes
+pub mod parse {
+    pub mod parser;
+    pub mod printer;
+    pub mod visit;
+    pub mod dump;
+}
+
+/// Semantic passes over a tree that parsed
+pub mod sema {
+    pub mod diag;
+    pub mod resolve;
+    pub mod types;
+    pub mod typed;
+}
+
+/// The command-line compiler
+pub mod driver;
+
+use std::fmt;
+
+pub use crate::lex::lexer::{lex, lex_with_spans, line_col, LexError, Lexer, Span, Token};
+pub use crate::lex::source::{TokenSource, SliceTokens, Buffered};
+pub use crate::lex::preprocessor::Preprocessor;
+pub use crate::sema::diag::{Diagnostic, Level};
+pub use crate::sema::resolve::{resolve, Binding, Resolution};
+pub use crate::sema::types::{check, Typing};
+pub use crate::sema::typed::{annotate, TypedUnit};
+pub use crate::parse::parser::{
+    parse, parse_with_spans, parse_from, parse_expression, parse_statement, parse_type_name, parse_declaration, Fragment,
+    const_eval, ConstError, ParseError,
+    Ast, TranslationUnit, Spans, ExprId, StmtId, DeclId,
+    Expr, BinOp, UnOp, CastKind, Stmt, ForInit, Decl, DeclSpec, Storage, Init, InitItem, Designator,
+    Type, Quals, Record, Member, Enum, Enumerator,
+};
+
+/// Either stage failing, for callers that go from source text straight to a tree
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Error {
+    Lex(LexError),
+    Parse(ParseError),
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Lex(e) => e.span,
+            Error::Parse(e) => e.span,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Lex(e) => e.fmt(f),
+            Error::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<LexError> for Error {
+    fn from(e: LexError) -> Error {
+        Error::Lex(e)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::Parse(e)
+    }
+}
+
+/// Lexes and parses a whole source file, keeping the spans. It isn't preprocessed.
+pub fn parse_source(s: &str) -> Result<TranslationUnit, Error> {
+    parse_from(&mut Buffered::new(Lexer::new(s)))
+}
+
+/// Lexes `s` and parses it with one of the parser's entry points, as in `parse_str("a + b", parse_expression)`
+pub fn parse_str<T>(s: &str, f: impl FnOnce(&[Token], &[Span]) -> Result<T, ParseError>) -> Result<T, Error> {
+    let (toks, spans) = lex_with_spans(s)?;
+    Ok(f(&toks, &spans)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(src: &str) -> (String, (usize, usize)) {
+        let e = parse_source(src).unwrap_err();
+        (e.to_string(), line_col(src, e.span().lo))
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("int f() {\n    x = 1 $ 2;\n}"), ("unexpected character '$'".to_string(), (2, 11)));
+        assert_eq!(error("int f() {\n    return 1\n}"), ("expected ';', found '}'".to_string(), (3, 1)));
+        assert_eq!(error("int f(int x"), ("expected ',' or ')', found end of input".to_string(), (1, 12)));
+        assert_eq!(error("enum { A };\nint A;"), ("redeclaration of enumerator 'A'".to_string(), (2, 5)));
+        assert!(matches!(parse_source("int x = 'a"), Err(Error::Lex(_))));
+        assert!(matches!(parse_source("int x = ;"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn fragments() {
+        let f = parse_str("a = b + 1, c", parse_expression).unwrap();
+        assert!(matches!(f.ast[f.node], Expr::BinOp { o: BinOp::Comma { .. } }));
+        assert_eq!(f.spans.expr(f.node), Span { lo: 0, hi: 12 });
+
+        let f = parse_str("{ typedef int T; T x; x++; }", parse_statement).unwrap();
+        assert!(matches!(&f.ast[f.node], Stmt::Block { v } if v.len() == 3));
+
+        let f = parse_str("const char *[4]", parse_type_name).unwrap();
+        let ptr = Type::Ptr { t: Box::new(Type::Char.qualify(Quals { is_const: true, ..Quals::default() })) };
+        assert!(matches!(f.node, Type::Array { t, n: Some(_) } if *t == ptr));
+
+        let f = parse_str("static int a = 1, *b;", parse_declaration).unwrap();
+        assert_eq!(f.node.len(), 2);
+
+        let e = parse_str("a + b;", parse_expression).unwrap_err();
+        assert_eq!((e.to_string(), e.span()), ("unexpected ';' after the expression".to_string(), Span { lo: 5, hi: 6 }));
+        assert_eq!(parse_str("T x", parse_type_name).unwrap_err().to_string(), "expected type specifier, found 'T'");
+    }
+
+    #[test]
+    fn token_sources() {
+        let mut toks = Buffered::new(Lexer::new("a = b;"));
+        assert_eq!(toks.peek_nth(2), Some(&Token::Id { id: "b".to_string() }));
+        let start = toks.pos();
+        toks.next();
+        toks.next();
+        assert_eq!((toks.pos(), toks.span(1)), (2, Some(Span { lo: 2, hi: 3 })));
+        toks.rewind(start);
+        assert_eq!(toks.next(), Some(&Token::Id { id: "a".to_string() }));
+
+        let src = "#define T int\n#define ZERO(x) 0\nT x = ZERO(y);";
+        let tu = parse_from(&mut Buffered::new(Preprocessor::new(src))).unwrap();
+        let init = match &tu.ast[tu.items[0]] {
+            Decl::Var { val: Some(Init::Expr { e }), .. } => *e,
+            d => panic!("{:?}", d),
+        };
+        assert_eq!(&src[tu.spans.expr(init).lo..tu.spans.expr(init).hi], "ZERO(y)");
+
+        let e = parse_from(&mut Buffered::new(Preprocessor::new("int x;\n#include <stdio.h>"))).unwrap_err();
+        assert_eq!((e.to_string(), line_col("int x;\n#include", e.span().lo)), ("#include is not supported".to_string(), (2, 1)));
+    }
+}
@@ -0,0 +1,534 @@
+//! AST dumps for people and tools. The tree is first turned into uniform nodes, which are then written out as
+//!
+//! ```text
+//!   tree   an indented tree with source ranges, like clang's `-ast-dump`
+//!   sexpr  S-expressions without ranges, compact enough to compare in tests
+//!   json   for external tools, in the schema below
+//!   dot    a Graphviz graph, `dot -Tsvg` draws it
+//! ```
+//!
+//! JSON schema. Every node is an object
+//!
+//! ```text
+//!   {
+//!     "kind": string,              what the node is, listed below
+//!     "field": string,             role of a child that its position doesn't tell, absent otherwise
+//!     "span": [lo, hi] | null,     byte range in the source with `hi` excluded, null for nodes not written there
+//!     "attrs": {string: string},   properties of the node itself, every value is a string
+//!     "children": [node]           in source order
+//!   }
+//! ```
+//!
+//! Kinds with their attrs and children, `?` marking optional ones:
+//!
+//! ```text
+//!   TranslationUnit                          Record and Enum for every tag type, then the external declarations
+//!   Record      keyword, tag?, id, incomplete?  Member for each member
+//!   Member      name?, type
+//!   Enum        tag?, id, incomplete?        Enumerator for each constant
+//!   Enumerator  name, value
+//!
+//!   Func        name, type, storage?         Arg for each parameter, "body" Block for a definition
+//!   Arg         name?, type, storage?
+//!   Var         name, type, storage?         "init" initializer
+//!   Tag         type
+//!   Typedef     name, type
+//!
+//!   Block                                    statements
+//!   DeclStmt                                 the declaration
+//!   ExprStmt                                 the expression
+//!   If                                       "cond", "then", "else"?
+//!   For                                      "init"? (one per declaration), "cond"?, "step"?, "body"
+//!   While                                    "cond", "body"
+//!   Switch      cases, default               "cond", "body"
+//!   Case                                     "value", "body"
+//!   Default, Label (name)                    "body"
+//!   Goto        label
+//!   Return                                   "value"?
+//!   Break, Continue, Empty
+//!
+//!   Id          name
+//!   IntN, FloatN, CharN                      value, the character code for CharN
+//!   StrN        value
+//!   EnumN       name, value
+//!   CompoundLit type                         initializer items as in InitList
+//!   InitList                                 items, an initializer or a Designated
+//!   Designated  designators, like `.x[2]`    the initializer
+//!   binary operators, named as in `BinOp`    left and right operand
+//!   unary operators, named as in `UnOp`      operands in source order, the callee first for FuncCall;
+//!                                            Member and PtrMember have `member`, TypeCast, SizeOfType, AlignOf and VaArg `type`
+//!   ImplicitCast type, cast                  the operand; cast is a `CastKind`, like `LValueToRValue`
+//! ```
+//!
+//! Types are written as in a cast, `struct <anonymous #N>` standing for the record or enum with index N.
+
+use crate::lex::lexer::{line_col, Span};
+use crate::parse::parser::*;
+use crate::parse::printer::{storage, type_name};
+
+struct Node {
+    kind: &'static str,
+    field: &'static str,
+    span: Option<Span>,
+    attrs: Vec<(&'static str, String)>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: &'static str, span: Span) -> Node {
+        let span = if span == Span::default() { None } else { Some(span) };
+        Node { kind, field: "", span, attrs: Vec::new(), children: Vec::new() }
+    }
+
+    fn attr(mut self, key: &'static str, val: impl ToString) -> Node {
+        self.attrs.push((key, val.to_string()));
+        self
+    }
+
+    fn child(mut self, n: Node) -> Node {
+        self.children.push(n);
+        self
+    }
+
+    fn field(mut self, field: &'static str) -> Node {
+        self.field = field;
+        self
+    }
+}
+
+fn unit(tu: &TranslationUnit) -> Node {
+    let ast = &tu.ast;
+    let mut n = Node::new("TranslationUnit", Span::default());
+    for (id, r) in ast.records.iter().enumerate() {
+        let mut rec = Node::new("Record", Span::default()).attr("keyword", if r.union { "union" } else { "struct" });
+        if let Some(tag) = &r.tag {
+            rec = rec.attr("tag", tag);
+        }
+        rec = rec.attr("id", id);
+        match &r.members {
+            Some(members) => for m in members {
+                let mut member = Node::new("Member", Span::default());
+                if let Some(id) = &m.id {
+                    member = member.attr("name", id);
+                }
+                rec = rec.child(member.attr("type", type_name(ast, &m.t)));
+            },
+            None => rec = rec.attr("incomplete", true),
+        }
+        n = n.child(rec);
+    }
+    for (id, e) in ast.enums.iter().enumerate() {
+        let mut en = Node::new("Enum", Span::default());
+        if let Some(tag) = &e.tag {
+            en = en.attr("tag", tag);
+        }
+        en = en.attr("id", id);
+        match &e.consts {
+            Some(consts) => for c in consts {
+                en = en.child(Node::new("Enumerator", Span::default()).attr("name", &c.id).attr("value", c.val));
+            },
+            None => en = en.attr("incomplete", true),
+        }
+        n = n.child(en);
+    }
+    for d in &tu.items {
+        n = n.child(decl(tu, *d));
+    }
+    n
+}
+
+fn decl(tu: &TranslationUnit, id: DeclId) -> Node {
+    let ast = &tu.ast;
+    let span = tu.spans.decl(id);
+    let with_storage = |n: Node, spec: &DeclSpec| match storage(spec) {
+        s if s.is_empty() => n,
+        s => n.attr("storage", s.trim_end()),
+    };
+    match &ast[id] {
+        Decl::Func { t, id, args, block, spec } => {
+            let mut n = with_storage(Node::new("Func", span).attr("name", id).attr("type", type_name(ast, t)), spec);
+            for arg in args {
+                n = n.child(decl(tu, *arg));
+            }
+            match block {
+                Some(block) => n.child(stmt(tu, *block).field("body")),
+                None => n,
+            }
+        },
+        Decl::Arg { t, id, spec } => {
+            let mut n = Node::new("Arg", span);
+            if let Some(id) = id {
+                n = n.attr("name", id);
+            }
+            with_storage(n.attr("type", type_name(ast, t)), spec)
+        },
+        Decl::Var { t, id, val, spec } => {
+            let n = with_storage(Node::new("Var", span).attr("name", id).attr("type", type_name(ast, t)), spec);
+            match val {
+                Some(val) => n.child(init(tu, val).field("init")),
+                None => n,
+            }
+        },
+        Decl::Tag { t } => Node::new("Tag", span).attr("type", type_name(ast, t)),
+        Decl::Typedef { t, id, spec: _ } => Node::new("Typedef", span).attr("name", id).attr("type", type_name(ast, t)),
+    }
+}
+
+fn stmt(tu: &TranslationUnit, id: StmtId) -> Node {
+    let ast = &tu.ast;
+    let n = Node::new("", tu.spans.stmt(id));
+    match &ast[id] {
+        Stmt::Block { v } => v.iter().fold(Node { kind: "Block", ..n }, |n, s| n.child(stmt(tu, *s))),
+        Stmt::Decl { d } => Node { kind: "DeclStmt", ..n }.child(decl(tu, *d)),
+        Stmt::Expr { e } => Node { kind: "ExprStmt", ..n }.child(expr(tu, *e)),
+
+        Stmt::If { cond, block, next } => {
+            let n = Node { kind: "If", ..n }
+                .child(expr(tu, *cond).field("cond"))
+                .child(stmt(tu, *block).field("then"));
+            match next {
+                Some(next) => n.child(stmt(tu, *next).field("else")),
+                None => n,
+            }
+        },
+        Stmt::For { var, cond, e, block } => {
+            let mut n = Node { kind: "For", ..n };
+            match var {
+                Some(ForInit::Decl { v }) => for d in v {
+                    n = n.child(decl(tu, *d).field("init"));
+                },
+                Some(ForInit::Expr { e }) => n = n.child(expr(tu, *e).field("init")),
+                None => {},
+            }
+            if let Some(cond) = cond {
+                n = n.child(expr(tu, *cond).field("cond"));
+            }
+            if let Some(e) = e {
+                n = n.child(expr(tu, *e).field("step"));
+            }
+            n.child(stmt(tu, *block).field("body"))
+        },
+        Stmt::While { cond, block } => Node { kind: "While", ..n }
+            .child(expr(tu, *cond).field("cond"))
+            .child(stmt(tu, *block).field("body")),
+
+        Stmt::Switch { cond, block, cases, default } => Node { kind: "Switch", ..n }
+            .attr("cases", cases.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", "))
+            .attr("default", default)
+            .child(expr(tu, *cond).field("cond"))
+            .child(stmt(tu, *block).field("body")),
+        Stmt::Case { e, block } => Node { kind: "Case", ..n }
+            .child(expr(tu, *e).field("value"))
+            .child(stmt(tu, *block).field("body")),
+        Stmt::Default { block } => Node { kind: "Default", ..n }.child(stmt(tu, *block).field("body")),
+
+        Stmt::Label { id, block } => Node { kind: "Label", ..n }.attr("name", id).child(stmt(tu, *block).field("body")),
+        Stmt::Goto { id } => Node { kind: "Goto", ..n }.attr("label", id),
+
+        Stmt::Break => Node { kind: "Break", ..n },
+        Stmt::Continue => Node { kind: "Continue", ..n },
+        Stmt::Return { e } => match e {
+            Some(e) => Node { kind: "Return", ..n }.child(expr(tu, *e).field("value")),
+            None => Node { kind: "Return", ..n },
+        },
+
+        Stmt::Empty => Node { kind: "Empty", ..n },
+    }
+}
+
+fn expr(tu: &TranslationUnit, id: ExprId) -> Node {
+    let ast = &tu.ast;
+    let n = Node::new("", tu.spans.expr(id));
+    let operands = |n: Node, v: &[ExprId]| v.iter().fold(n, |n, e| n.child(expr(tu, *e)));
+    match &ast[id] {
+        Expr::Id { s } => Node { kind: "Id", ..n }.attr("name", s),
+        Expr::IntN { num } => Node { kind: "IntN", ..n }.attr("value", num),
+        Expr::FloatN { num } => Node { kind: "FloatN", ..n }.attr("value", num),
+        Expr::CharN { num } => Node { kind: "CharN", ..n }.attr("value", num),
+        Expr::StrN { s, n: _ } => Node { kind: "StrN", ..n }.attr("value", s),
+        Expr::EnumN { s, num } => Node { kind: "EnumN", ..n }.attr("name", s).attr("value", num),
+
+        Expr::CompoundLit { t, v } => init_items(tu, Node { kind: "CompoundLit", ..n }.attr("type", type_name(ast, t)), v),
+
+        Expr::BinOp { o } => {
+            let (le, re) = o.operands();
+            operands(Node { kind: binop(o), ..n }, &[le, re])
+        },
+        Expr::UnOp { o } => match o {
+            UnOp::UnPlus { e } => operands(Node { kind: "UnPlus", ..n }, &[*e]),
+            UnOp::UnMinus { e } => operands(Node { kind: "UnMinus", ..n }, &[*e]),
+            UnOp::PreInc { e } => operands(Node { kind: "PreInc", ..n }, &[*e]),
+            UnOp::PostInc { e } => operands(Node { kind: "PostInc", ..n }, &[*e]),
+            UnOp::PreDec { e } => operands(Node { kind: "PreDec", ..n }, &[*e]),
+            UnOp::PostDec { e } => operands(Node { kind: "PostDec", ..n }, &[*e]),
+            UnOp::LogNot { e } => operands(Node { kind: "LogNot", ..n }, &[*e]),
+            UnOp::Indirection { e } => operands(Node { kind: "Indirection", ..n }, &[*e]),
+            UnOp::AddressOf { e } => operands(Node { kind: "AddressOf", ..n }, &[*e]),
+
+            UnOp::Subscript { le, re } => operands(Node { kind: "Subscript", ..n }, &[*le, *re]),
+            UnOp::FuncCall { e, args } => operands(operands(Node { kind: "FuncCall", ..n }, &[*e]), args),
+            UnOp::Member { e, id } => operands(Node { kind: "Member", ..n }.attr("member", id), &[*e]),
+            UnOp::PtrMember { e, id } => operands(Node { kind: "PtrMember", ..n }.attr("member", id), &[*e]),
+
+            UnOp::TypeCast { t, e } => operands(Node { kind: "TypeCast", ..n }.attr("type", type_name(ast, t)), &[*e]),
+
+            UnOp::SizeOf { e } => operands(Node { kind: "SizeOf", ..n }, &[*e]),
+            UnOp::SizeOfType { t } => Node { kind: "SizeOfType", ..n }.attr("type", type_name(ast, t)),
+            UnOp::AlignOf { t } => Node { kind: "AlignOf", ..n }.attr("type", type_name(ast, t)),
+
+            UnOp::VaStart { e, last } => operands(Node { kind: "VaStart", ..n }, &[*e, *last]),
+            UnOp::VaArg { e, t } => operands(Node { kind: "VaArg", ..n }.attr("type", type_name(ast, t)), &[*e]),
+            UnOp::VaEnd { e } => operands(Node { kind: "VaEnd", ..n }, &[*e]),
+            UnOp::VaCopy { le, re } => operands(Node { kind: "VaCopy", ..n }, &[*le, *re]),
+        },
+        Expr::ImplicitCast { kind, t, e } => {
+            operands(Node { kind: "ImplicitCast", ..n }.attr("type", type_name(ast, t)).attr("cast", format!("{:?}", kind)), &[*e])
+        },
+    }
+}
+
+fn binop(o: &BinOp) -> &'static str {
+    match o {
+        BinOp::Comma { .. } => "Comma",
+        BinOp::Assign { .. } => "Assign",
+        BinOp::AddAssign { .. } => "AddAssign",
+        BinOp::SubAssign { .. } => "SubAssign",
+        BinOp::MulAssign { .. } => "MulAssign",
+        BinOp::DivAssign { .. } => "DivAssign",
+        BinOp::ModAssign { .. } => "ModAssign",
+        BinOp::LogOr { .. } => "LogOr",
+        BinOp::LogAnd { .. } => "LogAnd",
+        BinOp::Equal { .. } => "Equal",
+        BinOp::NotEqual { .. } => "NotEqual",
+        BinOp::Bigger { .. } => "Bigger",
+        BinOp::Lesser { .. } => "Lesser",
+        BinOp::BiggerEqual { .. } => "BiggerEqual",
+        BinOp::LesserEqual { .. } => "LesserEqual",
+        BinOp::Add { .. } => "Add",
+        BinOp::Sub { .. } => "Sub",
+        BinOp::Mul { .. } => "Mul",
+        BinOp::Div { .. } => "Div",
+        BinOp::Mod { .. } => "Mod",
+    }
+}
+
+fn init(tu: &TranslationUnit, init: &Init) -> Node {
+    match init {
+        Init::Expr { e } => expr(tu, *e),
+        Init::List { v } => init_items(tu, Node::new("InitList", Span::default()), v),
+    }
+}
+
+fn init_items(tu: &TranslationUnit, n: Node, v: &[InitItem]) -> Node {
+    v.iter().fold(n, |n, item| {
+        if item.d.is_empty() {
+            return n.child(init(tu, &item.init));
+        }
+        let designators: String = item.d.iter().map(|d| match d {
+            Designator::Member { id } => format!(".{}", id),
+            Designator::Index { e: _, n } => format!("[{}]", n),
+        }).collect();
+        n.child(Node::new("Designated", Span::default()).attr("designators", designators).child(init(tu, &item.init)))
+    })
+}
+
+// Attributes shown without their key in the tree and S-expressions
+fn positional(key: &str) -> bool {
+    matches!(key, "keyword" | "tag" | "name" | "type" | "value" | "member" | "label" | "cast")
+}
+
+fn range(src: &str, span: Span) -> String {
+    let (l1, c1) = line_col(src, span.lo);
+    let (l2, c2) = line_col(src, span.hi - 1);
+    format!("<{}:{}, {}:{}>", l1, c1, l2, c2)
+}
+
+// `Kind <1:1, 2:3> name 'type' key=value`, the line a node gets in the tree and in the graph
+fn describe(n: &Node, src: &str) -> String {
+    let mut s = n.kind.to_string();
+    if let Some(span) = n.span {
+        s.push(' ');
+        s.push_str(&range(src, span));
+    }
+    for (key, val) in &n.attrs {
+        s.push(' ');
+        match *key {
+            "type" => s.push_str(&format!("'{}'", val)),
+            "value" if n.kind == "StrN" => s.push_str(&format!("{:?}", val)),
+            key if positional(key) => s.push_str(val),
+            key => s.push_str(&format!("{}={}", key, val)),
+        }
+    }
+    s
+}
+
+pub fn tree(tu: &TranslationUnit, src: &str) -> String {
+    fn write(n: &Node, src: &str, first: &str, rest: &str, out: &mut String) {
+        out.push_str(first);
+        if !n.field.is_empty() {
+            out.push_str(n.field);
+            out.push_str(": ");
+        }
+        out.push_str(&describe(n, src));
+        out.push('\n');
+        for (i, c) in n.children.iter().enumerate() {
+            match i == n.children.len() - 1 {
+                true => write(c, src, &format!("{}`-", rest), &format!("{}  ", rest), out),
+                false => write(c, src, &format!("{}|-", rest), &format!("{}| ", rest), out),
+            }
+        }
+    }
+
+    let mut out = String::new();
+    write(&unit(tu), src, "", "", &mut out);
+    out
+}
+
+pub fn sexpr(tu: &TranslationUnit) -> String {
+    fn atom(s: &str) -> String {
+        match !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || "_.-+[]".contains(c)) {
+            true => s.to_string(),
+            false => format!("{:?}", s),
+        }
+    }
+
+    fn write(n: &Node, out: &mut String) {
+        out.push('(');
+        out.push_str(n.kind);
+        for (key, val) in &n.attrs {
+            out.push(' ');
+            if !positional(key) {
+                out.push_str(key);
+                out.push('=');
+            }
+            out.push_str(&atom(val));
+        }
+        for c in &n.children {
+            out.push(' ');
+            if !c.field.is_empty() {
+                out.push(':');
+                out.push_str(c.field);
+                out.push(' ');
+            }
+            write(c, out);
+        }
+        out.push(')');
+    }
+
+    // one line per record, enum and external declaration
+    let root = unit(tu);
+    let mut out = String::from("(TranslationUnit");
+    for c in &root.children {
+        out.push_str("\n  ");
+        write(c, &mut out);
+    }
+    out.push_str(")\n");
+    out
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn json(tu: &TranslationUnit) -> String {
+    fn write(n: &Node, indent: usize, out: &mut String) {
+        let pad = "  ".repeat(indent + 1);
+        out.push_str("{\n");
+        out.push_str(&format!("{}\"kind\": {},\n", pad, json_str(n.kind)));
+        if !n.field.is_empty() {
+            out.push_str(&format!("{}\"field\": {},\n", pad, json_str(n.field)));
+        }
+        match n.span {
+            Some(span) => out.push_str(&format!("{}\"span\": [{}, {}],\n", pad, span.lo, span.hi)),
+            None => out.push_str(&format!("{}\"span\": null,\n", pad)),
+        }
+        let attrs: Vec<String> = n.attrs.iter().map(|(key, val)| format!("{}: {}", json_str(key), json_str(val))).collect();
+        out.push_str(&format!("{}\"attrs\": {{{}}},\n", pad, attrs.join(", ")));
+        out.push_str(&format!("{}\"children\": [", pad));
+        for (i, c) in n.children.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str(&"  ".repeat(indent + 2));
+            write(c, indent + 2, out);
+        }
+        if !n.children.is_empty() {
+            out.push('\n');
+            out.push_str(&pad);
+        }
+        out.push_str("]\n");
+        out.push_str(&"  ".repeat(indent));
+        out.push('}');
+    }
+
+    let mut out = String::new();
+    write(&unit(tu), 0, &mut out);
+    out.push('\n');
+    out
+}
+
+pub fn dot(tu: &TranslationUnit, src: &str) -> String {
+    fn write(n: &Node, src: &str, next: &mut usize, out: &mut String) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = describe(n, src).replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("    n{} [label=\"{}\"];\n", id, label));
+        for c in &n.children {
+            let child = write(c, src, next, out);
+            match c.field {
+                "" => out.push_str(&format!("    n{} -> n{};\n", id, child)),
+                field => out.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", id, child, field)),
+            }
+        }
+        id
+    }
+
+    let mut out = String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n");
+    write(&unit(tu), src, &mut 0, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_source;
+
+    fn unit(src: &str) -> TranslationUnit {
+        parse_source(src).unwrap()
+    }
+
+    #[test]
+    fn sexpr_statements() {
+        let src = "int f(int n) { if (n) return -n; for (int i = 0; i < n; i++) ; }";
+        let expected = "\
+(TranslationUnit
+  (Func f \"int (int)\" (Arg n int) :body (Block (If :cond (Id n) :then (Return :value (UnMinus (Id n)))) (For :init (Var i int :init (IntN 0)) :cond (Lesser (Id i) (Id n)) :step (PostInc (Id i)) :body (Empty)))))
+";
+        assert_eq!(sexpr(&unit(src)), expected);
+    }
+
+    #[test]
+    fn tree_spans() {
+        let src = "int x = 1;\nchar *s = \"a\\n\";\n";
+        let expected = "\
+TranslationUnit
+|-Var <1:1, 1:9> x 'int'
+| `-init: IntN <1:9, 1:9> 1
+`-Var <2:1, 2:15> s 'char *'
+  `-init: StrN <2:11, 2:15> \"a\\n\"
+";
+        assert_eq!(tree(&unit(src), src), expected);
+    }
+}
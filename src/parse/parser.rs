@@ -1,446 +1,2299 @@
-use std::rc::Rc;
-use core::cell::RefCell;
+//! Recursive-descent parser for declarations, statements and expressions, building the arena AST.
 
-use crate::lex::lexer::Token;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+
+use crate::lex::lexer::{Span, Token};
+use crate::lex::source::{SliceTokens, TokenSource};
+use crate::Error;
 
 #[derive(Debug)]
 #[derive(Clone)]
+#[derive(PartialEq)]
 pub enum Type {
-    Int,
-    Float,
-    Char,
+    Bool,
+    Char, SChar, UChar,
+    Short, UShort,
+    Int, UInt,
+    Long, ULong,
+    LongLong, ULongLong,
+    Float, Double, LongDouble,
+
+    Ptr      { t: Box<Type> },
+    Array    { t: Box<Type>, n: Option<ExprId> },
+    /// `prototype` is false for `()`, which says nothing about the parameters, and true for `(void)`
+    Function { ret: Box<Type>, params: Vec<Type>, variadic: bool, prototype: bool },
 
-    Ptr   { t: Box<Type> },
-    Array { t: Box<Type>, n: Box<Node> },
+    Struct { tag: Option<String>, id: usize },
+    Union  { tag: Option<String>, id: usize },
+    Enum   { tag: Option<String>, id: usize },
+
+    Qualified { t: Box<Type>, q: Quals },
 
     Void, 
+    /// Builtin type behind `va_list`
+    VaList,
 }
 
 #[derive(Debug)]
 #[derive(Clone)]
-pub enum UnOp {
-    UnPlus  { e: Box<Node> },
-    UnMinus { e: Box<Node> },
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Quals {
+    pub is_const: bool,
+    pub is_volatile: bool,
+    pub is_restrict: bool,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Storage {
+    Typedef,
+    Extern,
+    Static,
+    Auto,
+    Register,
+}
 
-    PreInc  { e: Box<Node> },
-    PostInc { e: Box<Node> },
-    PreDec  { e: Box<Node> },
-    PostDec { e: Box<Node> },
+/// Storage class and function specifiers of a declaration
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct DeclSpec {
+    pub storage: Option<Storage>,
+    pub thread_local: bool,
+    pub inline: bool,
+    /// typedef name the type was written with and the type it stands for, so it can be printed back
+    pub typedef: Option<(String, Type)>,
+}
 
-    LogNot { e: Box<Node> },
+impl Type {
+    /// Wraps the type in qualifiers, merging them with the ones it already has
+    pub fn qualify(self, q: Quals) -> Type {
+        if q == Quals::default() {
+            return self;
+        }
+        match self {
+            Type::Qualified { t, q: old } => Type::Qualified { 
+                t,
+                q: Quals { 
+                    is_const: old.is_const || q.is_const,
+                    is_volatile: old.is_volatile || q.is_volatile,
+                    is_restrict: old.is_restrict || q.is_restrict,
+                },
+            },
+            t => Type::Qualified { t: Box::new(t), q },
+        }
+    }
 
-    Indirection { e: Box<Node>}, 
-    AddressOf   { e:Box<Node> },
+    pub fn is_integer(&self) -> bool {
+        matches!(self.unqualified(), 
+            Type::Bool | Type::Char | Type::SChar | Type::UChar | Type::Short | Type::UShort | Type::Int | Type::UInt |
+            Type::Long | Type::ULong | Type::LongLong | Type::ULongLong | Type::Enum { .. })
+    }
 
-    Subscript { le: Box<Node> , re: Box<Node> },
-    FuncCall  { id: String, args: Box<Node> },
+    pub fn is_floating(&self) -> bool {
+        matches!(self.unqualified(), Type::Float | Type::Double | Type::LongDouble)
+    }
 
-    // TypeCast { t: Type, e: Box<Node> }, TODO:
+    pub fn unqualified(&self) -> &Type {
+        match self {
+            Type::Qualified { t, q: _ } => t,
+            t => t,
+        }
+    }
 }
 
+/// Struct and union types refer to a record by its index in `Ast::records`,
+/// so forward declarations and self-referencing records share one definition.
 #[derive(Debug)]
 #[derive(Clone)]
-pub enum BinOp {
-    Assign    { le: Box<Node> , re: Box<Node> },
-    AddAssign { le: Box<Node> , re: Box<Node> },
-    SubAssign { le: Box<Node> , re: Box<Node> },
-    MulAssign { le: Box<Node> , re: Box<Node> },
-    DivAssign { le: Box<Node> , re: Box<Node> },
-    ModAssign { le: Box<Node> , re: Box<Node> },
-
-    LogOr  { le: Box<Node> , re: Box<Node> },
-    LogAnd { le: Box<Node> , re: Box<Node> },
-
-    Equal       { le: Box<Node> , re: Box<Node> },
-    NotEqual    { le: Box<Node> , re: Box<Node> },
-    Bigger      { le: Box<Node> , re: Box<Node> },
-    Lesser      { le: Box<Node> , re: Box<Node> },
-    BiggerEqual { le: Box<Node> , re: Box<Node> },
-    LesserEqual { le: Box<Node> , re: Box<Node> },
-
-    Add { le: Box<Node> , re: Box<Node> },
-    Sub { le: Box<Node> , re: Box<Node> },
-    Mul { le: Box<Node> , re: Box<Node> }, 
-    Div { le: Box<Node> , re: Box<Node> },
-    Mod { le: Box<Node> , re: Box<Node> },
+pub struct Record {
+    pub union: bool,
+    pub tag: Option<String>,
+    pub members: Option<Vec<Member>>,
 }
 
 #[derive(Debug)]
 #[derive(Clone)]
-pub enum Node {
-    Id { s: String },
+pub struct Member {
+    pub t: Type,
+    pub id: Option<String>,
+}
 
-    Block { v: Vec<Rc<RefCell<Node>>> },
+/// Enum types refer to `Ast::enums` the same way records do.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Enum {
+    pub tag: Option<String>,
+    pub consts: Option<Vec<Enumerator>>,
+}
 
-    FuncDecl { t: Type, id: String, args: Box<Node>, block: Box<Node> },
-    ArgDecl  { t: Type, id: String },
-    Return   { e: Box<Node> },
-    Arg      { e: Box<Node> },
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Enumerator {
+    pub id: String,
+    pub val: i64,
+}
 
-    VarDecl { t: Type, id: Box<Node>, val: Box<Node> },
+/// Designators are kept as written; array indices are folded so a later stage
+/// can place every initializer without re-evaluating them.
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Designator {
+    Member { id: String },
+    Index  { e: ExprId, n: i64 },
+}
 
-    If   { cond: Box<Node>, block: Box<Node>, next: Box<Node> },
-    Else { block: Box<Node> },
-    
-    For { var: Box<Node>, cond: Box<Node>, e: Box<Node>, block: Box<Node> },
-    
-    While { cond: Box<Node>, block: Box<Node> },
-    
-    Break,
-    Continue,
-    
-    Expr   { e: Box<Node> },
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum UnOp {
+    UnPlus  { e: ExprId },
+    UnMinus { e: ExprId },
+
+    PreInc  { e: ExprId },
+    PostInc { e: ExprId },
+    PreDec  { e: ExprId },
+    PostDec { e: ExprId },
+
+    LogNot { e: ExprId },
+
+    Indirection { e: ExprId }, 
+    AddressOf   { e: ExprId },
+
+    Subscript { le: ExprId, re: ExprId },
+    FuncCall  { e: ExprId, args: Vec<ExprId> },
+    Member    { e: ExprId, id: String },
+    PtrMember { e: ExprId, id: String },
+
+    TypeCast { t: Type, e: ExprId },
+
+    SizeOf     { e: ExprId },
+    SizeOfType { t: Type },
+    AlignOf    { t: Type },
+
+    VaStart { e: ExprId, last: ExprId },
+    VaArg   { e: ExprId, t: Type },
+    VaEnd   { e: ExprId },
+    VaCopy  { le: ExprId, re: ExprId },
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum BinOp {
+    Comma { le: ExprId, re: ExprId },
+
+    Assign    { le: ExprId, re: ExprId },
+    AddAssign { le: ExprId, re: ExprId },
+    SubAssign { le: ExprId, re: ExprId },
+    MulAssign { le: ExprId, re: ExprId },
+    DivAssign { le: ExprId, re: ExprId },
+    ModAssign { le: ExprId, re: ExprId },
+
+    LogOr  { le: ExprId, re: ExprId },
+    LogAnd { le: ExprId, re: ExprId },
+
+    Equal       { le: ExprId, re: ExprId },
+    NotEqual    { le: ExprId, re: ExprId },
+    Bigger      { le: ExprId, re: ExprId },
+    Lesser      { le: ExprId, re: ExprId },
+    BiggerEqual { le: ExprId, re: ExprId },
+    LesserEqual { le: ExprId, re: ExprId },
+
+    Add { le: ExprId, re: ExprId },
+    Sub { le: ExprId, re: ExprId },
+    Mul { le: ExprId, re: ExprId }, 
+    Div { le: ExprId, re: ExprId },
+    Mod { le: ExprId, re: ExprId },
+}
+
+impl BinOp {
+    pub fn operands(&self) -> (ExprId, ExprId) {
+        match self {
+            BinOp::Comma { le, re } |
+            BinOp::Assign { le, re } | BinOp::AddAssign { le, re } | BinOp::SubAssign { le, re } |
+            BinOp::MulAssign { le, re } | BinOp::DivAssign { le, re } | BinOp::ModAssign { le, re } |
+            BinOp::LogOr { le, re } | BinOp::LogAnd { le, re } |
+            BinOp::Equal { le, re } | BinOp::NotEqual { le, re } |
+            BinOp::Bigger { le, re } | BinOp::Lesser { le, re } | BinOp::BiggerEqual { le, re } | BinOp::LesserEqual { le, re } |
+            BinOp::Add { le, re } | BinOp::Sub { le, re } | BinOp::Mul { le, re } | BinOp::Div { le, re } | BinOp::Mod { le, re } => (*le, *re),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> (&mut ExprId, &mut ExprId) {
+        match self {
+            BinOp::Comma { le, re } |
+            BinOp::Assign { le, re } | BinOp::AddAssign { le, re } | BinOp::SubAssign { le, re } |
+            BinOp::MulAssign { le, re } | BinOp::DivAssign { le, re } | BinOp::ModAssign { le, re } |
+            BinOp::LogOr { le, re } | BinOp::LogAnd { le, re } |
+            BinOp::Equal { le, re } | BinOp::NotEqual { le, re } |
+            BinOp::Bigger { le, re } | BinOp::Lesser { le, re } | BinOp::BiggerEqual { le, re } | BinOp::LesserEqual { le, re } |
+            BinOp::Add { le, re } | BinOp::Sub { le, re } | BinOp::Mul { le, re } | BinOp::Div { le, re } | BinOp::Mod { le, re } => (le, re),
+        }
+    }
+}
+
+/// Nodes refer to their children by id into the arenas of `Ast`
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Expr {
+    Id     { s: String },
     IntN   { num: i32 },
     FloatN { num: f64 },
     CharN  { num: u8 },
+    /// `n` is the size of the array it is, an implicit node counting the terminating null
+    StrN   { s: String, n: ExprId },
+    EnumN  { s: String, num: i64 },
+
+    CompoundLit { t: Type, v: Vec<InitItem> },
 
     BinOp { o: BinOp },
-    UnOp { o: UnOp },
+    UnOp  { o: UnOp },
+
+    /// A conversion C does without a cast in the source, to type `t`. The parser never makes them,
+    /// semantic analysis puts them in so that later stages find every conversion in the tree.
+    ImplicitCast { kind: CastKind, t: Type, e: ExprId },
+}
+
+/// What an implicit conversion does, named as in clang's `CastKind`
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum CastKind {
+    /// reads the value of an object
+    LValueToRValue,
+    ArrayToPointerDecay,
+    FunctionToPointerDecay,
+    /// between integer types, promotions included
+    IntegralCast,
+    IntegralToFloating,
+    FloatingToIntegral,
+    FloatingCast,
+    IntegralToBoolean,
+    FloatingToBoolean,
+    PointerToBoolean,
+    /// a null pointer constant to a pointer
+    NullToPointer,
+    /// between pointers to different types, to or from `void *`
+    BitCast,
+    /// adds qualifiers to what a pointer points to
+    NoOp,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Stmt {
+    Block { v: Vec<StmtId> },
+    Decl  { d: DeclId },
+    Expr  { e: ExprId },
+
+    /// `next` is the else branch, another If for `else if`
+    If    { cond: ExprId, block: StmtId, next: Option<StmtId> },
+    For   { var: Option<ForInit>, cond: Option<ExprId>, e: Option<ExprId>, block: StmtId },
+    While { cond: ExprId, block: StmtId },
+
+    Switch  { cond: ExprId, block: StmtId, cases: Vec<i64>, default: bool },
+    Case    { e: ExprId, block: StmtId },
+    Default { block: StmtId },
+
+    Label { id: String, block: StmtId },
+    Goto  { id: String },
+
+    Break,
+    Continue,
+    Return { e: Option<ExprId> },
+
+    Empty,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Decl {
+    Func    { t: Type, id: String, args: Vec<DeclId>, block: Option<StmtId>, spec: DeclSpec },
+    Arg     { t: Type, id: Option<String>, spec: DeclSpec },
+    Var     { t: Type, id: String, val: Option<Init>, spec: DeclSpec },
+    Tag     { t: Type },
+    Typedef { t: Type, id: String, spec: DeclSpec },
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum ForInit {
+    Decl { v: Vec<DeclId> },
+    Expr { e: ExprId },
+}
+
+/// Initializers are not expressions: only a declaration or a compound literal can hold a list
+#[derive(Debug)]
+#[derive(Clone)]
+pub enum Init {
+    Expr { e: ExprId },
+    List { v: Vec<InitItem> },
+}
 
-    None,
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct InitItem {
+    pub d: Vec<Designator>,
+    pub init: Init,
 }
 
-impl Node {
-    fn get_vec_mut(&mut self) -> &mut Vec<Rc<RefCell<Node>>> {
-        if let Node::Block { v } = self {
-            return v;
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct ExprId(u32);
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct StmtId(u32);
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct DeclId(u32);
+
+impl ExprId {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+impl StmtId {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+impl DeclId {
+    pub fn index(self) -> usize { self.0 as usize }
+}
+
+/// Nodes of each kind are allocated contiguously and ids are dense indices into their arena,
+/// so later passes can keep types, spans or resolutions in plain vectors indexed by the same ids.
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Ast {
+    pub exprs: Vec<Expr>,
+    pub stmts: Vec<Stmt>,
+    pub decls: Vec<Decl>,
+    pub records: Vec<Record>,
+    pub enums: Vec<Enum>,
+}
+
+impl Ast {
+    pub fn add_expr(&mut self, e: Expr) -> ExprId {
+        self.exprs.push(e);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn add_stmt(&mut self, s: Stmt) -> StmtId {
+        self.stmts.push(s);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    pub fn add_decl(&mut self, d: Decl) -> DeclId {
+        self.decls.push(d);
+        DeclId(self.decls.len() as u32 - 1)
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+    fn index(&self, id: ExprId) -> &Expr { &self.exprs[id.index()] }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+    fn index(&self, id: StmtId) -> &Stmt { &self.stmts[id.index()] }
+}
+
+impl Index<DeclId> for Ast {
+    type Output = Decl;
+    fn index(&self, id: DeclId) -> &Decl { &self.decls[id.index()] }
+}
+
+/// Source ranges of the nodes, a side table indexed by the same ids as the arenas.
+/// Nodes added after parsing, or parsed without spans, have an empty one.
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Spans {
+    pub exprs: Vec<Span>,
+    pub stmts: Vec<Span>,
+    pub decls: Vec<Span>,
+    /// of the identifier a declaration declares, for messages about the name
+    pub names: Vec<Span>,
+    /// of the names of each enum's enumerators, indexed like `Ast::enums` and then `Enum::consts`
+    pub enumerators: Vec<Vec<Span>>,
+}
+
+impl Spans {
+    pub fn expr(&self, id: ExprId) -> Span {
+        self.exprs.get(id.index()).copied().unwrap_or_default()
+    }
+
+    pub fn stmt(&self, id: StmtId) -> Span {
+        self.stmts.get(id.index()).copied().unwrap_or_default()
+    }
+
+    pub fn decl(&self, id: DeclId) -> Span {
+        self.decls.get(id.index()).copied().unwrap_or_default()
+    }
+
+    /// Falls back to the whole declaration if it has no name
+    pub fn name(&self, id: DeclId) -> Span {
+        match self.names.get(id.index()) {
+            Some(span) if *span != Span::default() => *span,
+            _ => self.decl(id),
         }
-        panic!();
     }
+
+    pub fn enumerator(&self, id: usize, index: usize) -> Span {
+        self.enumerators.get(id).and_then(|v| v.get(index)).copied().unwrap_or_default()
+    }
+}
+
+/// A whole source file: its external declarations in source order and the arena they live in
+#[derive(Debug)]
+pub struct TranslationUnit {
+    pub ast: Ast,
+    pub items: Vec<DeclId>,
+    pub spans: Spans,
 }
 
-// Program ::= [ FuncDecl ]
+// Program ::= [ ExtDecl ]
 
-// FuncDecl  ::= Type ID ArgsDecl Block
-// ArgsDecl  ::= "(" _ ")" | "(" ArgDecl ")" | "(" [ ArgDecl "," ] ArgDecl ")"
+// ExtDecl   ::= FuncDecl | Decl
+// FuncDecl  ::= Specs Declarator Block
+// ArgsDecl  ::= "(" _ ")" | "(" "void" ")" | "(" [ ArgDecl "," ] ArgDecl { "," "..." } ")"
 // Args      ::= "(" _ ")" | "(" Arg ")" | "(" [ Arg "," ] Arg ")"
-// ArgDecl   ::= Type ID
+// ArgDecl   ::= Specs Declarator | Specs AbstractDeclarator
 // Arg       ::= Expr
 
-// VarDecl ::= Type ID ";" | ...TODO...
+// Decl    ::= Specs VarDecl [ "," VarDecl ] ";" | Specs ";"
+// VarDecl ::= Declarator | Declarator "=" Init
 
-// If     ::= "if" "(" Expr ")" Block [ ElseIf ] { Else }
+// Init       ::= Expr | InitList
+// InitList   ::= "{" _ "}" | "{" [ InitItem "," ] InitItem { "," } "}"
+// InitItem   ::= Init | Designator [ Designator ] "=" Init
+// Designator ::= "." ID | "[" Terma "]"
+
+// If     ::= "if" "(" CommaExpr ")" Block [ ElseIf ] { Else }
 // ElseIf ::= "else" If
 // Else   ::= "else" Block
 
-// For ::= "for" "(" Stmt Stmt Expr ")" Block
+// For ::= "for" "(" Stmt Stmt { CommaExpr } ")" Block
+
+// While ::= "while" "(" CommaExpr ")" Block
 
-// While ::= "while" "(" Expr ")" Block
+// Switch  ::= "switch" "(" CommaExpr ")" Block
+// Case    ::= "case" Terma ":" Stmt
+// Default ::= "default" ":" Stmt
+// Label   ::= ID ":" Stmt
 
 // Block ::= "{" [ Stmt ] "}" | Stmt
 
-// Stmt  ::= Expr ";" | VarDecl | Var | If | For | While | "break" ";" | "continue" ";" | "return" Expr ";" | ";"
+// Stmt  ::= CommaExpr ";" | Decl | If | For | While | Switch | Case | Default | Label | Block | "goto" ID ";" | "break" ";" | "continue" ";" | "return" { CommaExpr } ";" | ";"
+// CommaExpr ::= Expr | CommaExpr "," Expr
 // Expr  ::= Terma | Terma "=" Expr | Terma "+=" Expr | Terma "-=" Expr | Terma "*=" Expr | Terma "/=" Expr | Terma "%=" Expr
-// Terma ::= Termb | Termb "||" Terma
-// Termb ::= Termc | Termc "&&" Termb
-// Termc ::= Termd | Termd "==" Termc | Termd "!=" Termc
-// Termd ::= Terme | Terme ">" Termd | Terme "<" Termd | Terme ">=" Termd  | Terme "<=" Termd
-// Terme ::= Termf | Termf "+" Terme | Termf "-" Terme
-// Termf ::= Termg | Termg "*" Termf | Termg "/" Termf | Termg "%" Termf
-// Termg ::= "&" Termh | "*" Termh | "!" Termh | "++" Termh | "--" Termh | "+" Termh | "-" Termh | Termh
-// Termh ::= Fact "++" | Fact "--" | Fact Args | Fact "[" Expr "]" | Type "(" Expr ")"
-// Fact  ::= NUM | ID | "(" Expr ")"
-
-// Type ::= "int" | "float" | "char" | "void" | "*" Type | "&" Type | Type ID "[" Expr "]"
-
-pub fn parse(toks: &Vec<Token>) -> Node {
-    let mut root = Node::Block { v: Vec::new() };
-    let mut iter = toks.iter().peekable();
-    if let Some(parsed_root) = parse_prg(&mut root, &mut iter) { return parsed_root; }
-    panic!();
-    
-    fn next_t<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> &'a Token {
-        match toks.next() {
-            Some(t) => t,
-            _ => panic!(), 
+// Terma ::= Termb | Terma "||" Termb
+// Termb ::= Termc | Termb "&&" Termc
+// Termc ::= Termd | Termc "==" Termd | Termc "!=" Termd
+// Termd ::= Terme | Termd ">" Terme | Termd "<" Terme | Termd ">=" Terme  | Termd "<=" Terme
+// Terme ::= Termf | Terme "+" Termf | Terme "-" Termf
+// Termf ::= Termg | Termf "*" Termg | Termf "/" Termg | Termf "%" Termg
+// Termg ::= "&" Termg | "*" Termg | "!" Termg | "++" Termg | "--" Termg | "+" Termg | "-" Termg | "(" TypeName ")" Termg | Sizeof | Termh
+// Sizeof ::= "sizeof" Termg | "sizeof" "(" TypeName ")" | "sizeof" "(" TypeName ")" InitList [ Postfix ] | "_Alignof" "(" TypeName ")"
+// Termh ::= Fact [ Postfix ]
+// Postfix ::= "++" | "--" | Args | "[" CommaExpr "]" | "." ID | "->" ID
+// Fact  ::= NUM | STR [ STR ] | ID | VaBuiltin | "(" CommaExpr ")" | "(" TypeName ")" InitList
+// VaBuiltin ::= "va_start" "(" Expr "," Expr ")" | "va_arg" "(" Expr "," TypeName ")" | "va_end" "(" Expr ")" | "va_copy" "(" Expr "," Expr ")"
+
+// Specs    ::= [ Storage | Qual | "inline" ] Type [ Storage | Qual | "inline" ]
+// Storage  ::= "typedef" | "extern" | "static" | "auto" | "register" | "_Thread_local"
+// Qual     ::= "const" | "volatile" | "restrict"
+// Type     ::= [ Arith ] | Record | Enum | TypedefName
+// Arith    ::= "void" | "_Bool" | "char" | "short" | "int" | "long" | "float" | "double" | "signed" | "unsigned"
+// Record   ::= RecordKw ID | RecordKw { ID } "{" [ [ Qual ] Type [ Qual ] MemberDeclarator ] "}"
+// RecordKw ::= "struct" | "union"
+// MemberDeclarator ::= ";" | Declarator [ "," Declarator ] ";"
+// Enum       ::= "enum" ID | "enum" { ID } "{" Enumerator [ "," Enumerator ] { "," } "}"
+// Enumerator ::= ID | ID "=" Terma
+// TypeName ::= [ Qual ] Type [ Qual ] { AbstractDeclarator }
+
+// Declarator         ::= [ Pointer ] DirectDeclarator
+// Pointer            ::= "*" [ Qual ]
+// DirectDeclarator   ::= ID | "(" Declarator ")" | DirectDeclarator "[" { Expr } "]" | DirectDeclarator ArgsDecl
+// AbstractDeclarator ::= [ Pointer ] { DirectAbstract }
+// DirectAbstract     ::= "(" AbstractDeclarator ")" | { DirectAbstract } "[" { Expr } "]" | { DirectAbstract } ArgsDecl
+
+/// Syntax or constraint violation at the tokens of `span`, or at the end of the input for a missing token.
+/// Parsing stops at the first one.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct ParseError {
+    pub msg: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Ordinary identifiers the parser has to tell apart while parsing expressions
+enum Name {
+    Var,
+    EnumConst { val: i64 },
+    Typedef { t: Type },
+}
+
+struct Scope {
+    tags: HashMap<String, Type>,
+    names: HashMap<String, Name>,
+}
+
+// Case values seen so far in a switch being parsed
+struct Switch {
+    cases: Vec<i64>,
+    default: bool,
+}
+
+struct Ctx {
+    ast: Ast,
+    spans: Spans,
+    scopes: Vec<Scope>,
+    switches: Vec<Switch>,
+    // whether the function whose body is being parsed takes `...`
+    variadic: bool,
+    // identifier of the last declarator, for the declaration made of it
+    name: Option<Span>,
+}
+
+impl Ctx {
+    fn new() -> Ctx {
+        let mut cx = Ctx { 
+            ast: Ast::default(), spans: Spans::default(),
+            scopes: Vec::new(), switches: Vec::new(), variadic: false, name: None,
+        };
+        cx.push_scope();
+        cx.scopes[0].names.insert("va_list".to_string(), Name::Typedef { t: Type::VaList });
+        cx
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope { tags: HashMap::new(), names: HashMap::new() });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn find_tag(&self, tag: &str, local: bool) -> Option<Type> {
+        match local {
+            true => self.scopes.last().and_then(|scope| scope.tags.get(tag)).cloned(),
+            false => self.scopes.iter().rev().find_map(|scope| scope.tags.get(tag)).cloned(),
         }
     }
 
-    fn parse_prg<'a>(root: &mut Node, toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Option<Node> {
-        while let Some(t) = toks.peek() {
-            match **t {
-                Token::IntT | Token::FloatT | Token::CharT | Token::VoidT => {
-                    root.get_vec_mut().push(Rc::new(RefCell::new(parse_func_decl(toks))));
-                },
-                _ => panic!(),
-            }
+    fn find_name(&self, id: &str) -> Option<&Name> {
+        self.scopes.iter().rev().find_map(|scope| scope.names.get(id))
+    }
+
+    fn find_typedef(&self, id: &str) -> Option<Type> {
+        match self.find_name(id) {
+            Some(Name::Typedef { t }) => Some(t.clone()),
+            _ => None,
         }
-        Some(root.clone())
     }
 
-    fn parse_func_decl<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let func_ret_type = parse_type(next_t(toks), toks);
-        let func_id = parse_id(next_t(toks));
-        let func_args = match *next_t(toks) {
-            Token::LP => parse_args_decl(toks),
-            _ => panic!() };
-        let func_block = match *next_t(toks) {
-            Token::LC => parse_block(toks),
-            _ => panic!() };
+    // `span` is where the name is declared, for the error
+    fn declare(&mut self, id: &str, name: Name, span: Span) -> Result<(), ParseError> {
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(Name::EnumConst { .. }) = scope.names.get(id) {
+            return Err(ParseError { msg: format!("redeclaration of enumerator '{}'", id), span });
+        }
+        scope.names.insert(id.to_string(), name);
+        Ok(())
+    }
 
-        Node::FuncDecl { t: func_ret_type, id: func_id, args: Box::new(func_args), block: Box::new(func_block) }
+    fn is_complete(&self, t: &Type) -> bool {
+        match t {
+            Type::Struct { tag: _, id } | Type::Union { tag: _, id } => self.ast.records[*id].members.is_some(),
+            Type::Enum { tag: _, id } => self.ast.enums[*id].consts.is_some(),
+            _ => true,
+        }
     }
 
-    fn parse_args_decl<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let mut args_decl = Node::Block { v: Vec::new() };
-        loop {
-            match toks.peek() {
-                Some(Token::Comma) => {
-                    next_t(toks);
-                    args_decl.get_vec_mut().push(parse_arg_decl(toks));
-                }
-                Some(Token::IntT) | Some(Token::FloatT) | Some(Token::CharT) | Some(Token::VoidT) => {
-                    args_decl.get_vec_mut().push(parse_arg_decl(toks));
-                }
-                Some(Token::RP) => { next_t(toks); break; },
-                _ => panic!(),
-            }
+    fn new_record(&mut self, tag: Option<String>, union: bool) -> Type {
+        let id = self.ast.records.len();
+        self.ast.records.push(Record { union, tag: tag.clone(), members: None });
+        let t = match union {
+            true => Type::Union { tag: tag.clone(), id },
+            false => Type::Struct { tag: tag.clone(), id },
+        };
+        if let Some(tag) = tag {
+            self.scopes.last_mut().unwrap().tags.insert(tag, t.clone());
         }
+        t
+    }
 
-        args_decl
+    fn new_enum(&mut self, tag: Option<String>) -> Type {
+        let id = self.ast.enums.len();
+        self.ast.enums.push(Enum { tag: tag.clone(), consts: None });
+        self.spans.enumerators.push(Vec::new());
+        let t = Type::Enum { tag: tag.clone(), id };
+        if let Some(tag) = tag {
+            self.scopes.last_mut().unwrap().tags.insert(tag, t.clone());
+        }
+        t
     }
+}
 
-    fn parse_arg_decl<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Rc<RefCell<Node>> {
-        Rc::new(RefCell::new(
-            Node::ArgDecl { 
-                t: parse_type(next_t(toks), toks), 
-                id: parse_id(next_t(toks)) 
-            }
-        ))
+/// Why `const_eval` couldn't fold an expression
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum ConstError {
+    /// Something in it isn't constant, or it divides by zero
+    NotConstant,
+    /// A result doesn't fit in `int`
+    Overflow,
+}
+
+/// Folds an integer constant expression. Operands and results are all `int`, so any result outside
+/// its range is an overflow rather than wrapping.
+pub fn const_eval(ast: &Ast, e: ExprId) -> Result<i64, ConstError> {
+    let int = |n: Option<i64>| match n {
+        Some(n) if i32::try_from(n).is_ok() => Ok(n),
+        _ => Err(ConstError::Overflow),
+    };
+    let nonzero = |n: i64| if n == 0 { Err(ConstError::NotConstant) } else { Ok(n) };
+    Ok(match &ast[e] {
+        Expr::IntN { num } => *num as i64,
+        Expr::CharN { num } => *num as i64,
+        Expr::EnumN { s: _, num } => *num,
+        Expr::UnOp { o } => match o {
+            UnOp::UnPlus  { e } => const_eval(ast, *e)?,
+            UnOp::UnMinus { e } => int(const_eval(ast, *e)?.checked_neg())?,
+            UnOp::LogNot  { e } => (const_eval(ast, *e)? == 0) as i64,
+            UnOp::TypeCast { t, e } if t.is_integer() => const_eval(ast, *e)?,
+            _ => return Err(ConstError::NotConstant),
+        },
+        Expr::ImplicitCast { kind: CastKind::IntegralToBoolean, t: _, e } => (const_eval(ast, *e)? != 0) as i64,
+        Expr::ImplicitCast { kind: _, t, e } if t.is_integer() => const_eval(ast, *e)?,
+        Expr::BinOp { o } => match o {
+            BinOp::LogOr  { le, re } => (const_eval(ast, *le)? != 0 || const_eval(ast, *re)? != 0) as i64,
+            BinOp::LogAnd { le, re } => (const_eval(ast, *le)? != 0 && const_eval(ast, *re)? != 0) as i64,
+
+            BinOp::Equal       { le, re } => (const_eval(ast, *le)? == const_eval(ast, *re)?) as i64,
+            BinOp::NotEqual    { le, re } => (const_eval(ast, *le)? != const_eval(ast, *re)?) as i64,
+            BinOp::Bigger      { le, re } => (const_eval(ast, *le)? >  const_eval(ast, *re)?) as i64,
+            BinOp::Lesser      { le, re } => (const_eval(ast, *le)? <  const_eval(ast, *re)?) as i64,
+            BinOp::BiggerEqual { le, re } => (const_eval(ast, *le)? >= const_eval(ast, *re)?) as i64,
+            BinOp::LesserEqual { le, re } => (const_eval(ast, *le)? <= const_eval(ast, *re)?) as i64,
+
+            BinOp::Add { le, re } => int(const_eval(ast, *le)?.checked_add(const_eval(ast, *re)?))?,
+            BinOp::Sub { le, re } => int(const_eval(ast, *le)?.checked_sub(const_eval(ast, *re)?))?,
+            BinOp::Mul { le, re } => int(const_eval(ast, *le)?.checked_mul(const_eval(ast, *re)?))?,
+            BinOp::Div { le, re } => int(const_eval(ast, *le)?.checked_div(nonzero(const_eval(ast, *re)?)?))?,
+            BinOp::Mod { le, re } => int(const_eval(ast, *le)?.checked_rem(nonzero(const_eval(ast, *re)?)?))?,
+            _ => return Err(ConstError::NotConstant),
+        },
+        _ => return Err(ConstError::NotConstant),
+    })
+}
+
+// Folds the integer constant expression `e`, with `msg` the error at `lo` if it isn't one
+fn constant(e: ExprId, msg: impl Into<String>, lo: usize, toks: &mut impl TokenSource, cx: &Ctx) -> Result<i64, ParseError> {
+    match const_eval(&cx.ast, e) {
+        Ok(n) => Ok(n),
+        Err(ConstError::Overflow) => error("integer overflow in constant expression", lo, toks),
+        Err(ConstError::NotConstant) => error(msg, lo, toks),
     }
+}
 
-    fn parse_stmt<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node;
+// Whether an integer constant expression only can't be folded because of a `sizeof` or `_Alignof` in it,
+// which need the layout of types the parser doesn't compute
+fn needs_layout(ast: &Ast, e: ExprId) -> bool {
+    let constant = |e: &ExprId| const_eval(ast, *e).is_ok() || needs_layout(ast, *e);
+    match &ast[e] {
+        Expr::UnOp { o } => match o {
+            UnOp::SizeOf { .. } | UnOp::SizeOfType { .. } | UnOp::AlignOf { .. } => true,
+            UnOp::UnPlus { e } | UnOp::UnMinus { e } | UnOp::LogNot { e } => needs_layout(ast, *e),
+            UnOp::TypeCast { t, e } if t.is_integer() => needs_layout(ast, *e),
+            _ => false,
+        },
+        // never constant
+        Expr::BinOp { o: BinOp::Comma { .. } | BinOp::Assign { .. } | BinOp::AddAssign { .. } | BinOp::SubAssign { .. } |
+            BinOp::MulAssign { .. } | BinOp::DivAssign { .. } | BinOp::ModAssign { .. } } => false,
+        Expr::BinOp { o } => {
+            let (le, re) = o.operands();
+            constant(&le) && constant(&re) && (needs_layout(ast, le) || needs_layout(ast, re))
+        },
+        _ => false,
+    }
+}
 
-        match toks.peek() {
-            Some(Token::Break)    => { toks.next(); node = Node::Break; },
-            Some(Token::Continue) => { toks.next(); node = Node::Continue; },
-            Some(Token::Return)   => { toks.next(); node = Node::Return { e: Box::new(parse_expr(toks)) }; },
-            _ => node = Node::Expr { e: Box::new(parse_expr(toks)) },
-        };
+pub fn parse(toks: &[Token]) -> Result<TranslationUnit, ParseError> {
+    parse_with_spans(toks, &[])
+}
 
-        match toks.peek() {
-            Some(Token::Semicolon) | Some(Token::Comma) => { toks.next(); },
-            _ => {},
-        };
+/// Same as `parse`, with the token spans from `lex_with_spans` giving every node its source range
+pub fn parse_with_spans(toks: &[Token], spans: &[Span]) -> Result<TranslationUnit, ParseError> {
+    let f = fragment(&mut SliceTokens::new(toks, spans), "declarations", parse_prg)?;
+    Ok(TranslationUnit { ast: f.ast, items: f.node, spans: f.spans })
+}
 
-        node
+/// Same as `parse_with_spans`, taking the tokens as the parser gets to them, from the lexer or the preprocessor.
+/// If the source stops at an error the parser got that far, so that error comes first.
+pub fn parse_from(toks: &mut impl TokenSource) -> Result<TranslationUnit, Error> {
+    let f = fragment(toks, "declarations", parse_prg);
+    if let Some(e) = toks.error() {
+        return Err(Error::Lex(e.clone()));
     }
+    let f = f?;
+    Ok(TranslationUnit { ast: f.ast, items: f.node, spans: f.spans })
+}
 
-    fn parse_expr<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_terma(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Assignment => { toks.next(); return Node::BinOp { o: BinOp::Assign    { le: Box::new(node), re: Box::new(parse_expr(toks)) } } },
-                Token::AddAssign  => { toks.next(); return Node::BinOp { o: BinOp::AddAssign { le: Box::new(node), re: Box::new(parse_expr(toks)) } } },
-                Token::SubAssign  => { toks.next(); return Node::BinOp { o: BinOp::SubAssign { le: Box::new(node), re: Box::new(parse_expr(toks)) } } },
-                Token::MulAssign  => { toks.next(); return Node::BinOp { o: BinOp::MulAssign { le: Box::new(node), re: Box::new(parse_expr(toks)) } } },
-                Token::DivAssign  => { toks.next(); return Node::BinOp { o: BinOp::DivAssign { le: Box::new(node), re: Box::new(parse_expr(toks)) } } },
-                Token::ModAssign  => { toks.next(); return Node::BinOp { o: BinOp::ModAssign { le: Box::new(node), re: Box::new(parse_expr(toks)) } } },
-                _ => return node
-            }
+/// A piece of C parsed on its own, along with the arena its nodes live in.
+/// Names it uses are not looked up in any file, so a typedef name is only known if the piece declares it.
+#[derive(Debug)]
+pub struct Fragment<T> {
+    pub ast: Ast,
+    pub spans: Spans,
+    pub node: T,
+}
+
+/// An expression, commas included
+pub fn parse_expression(toks: &[Token], spans: &[Span]) -> Result<Fragment<ExprId>, ParseError> {
+    fragment(&mut SliceTokens::new(toks, spans), "expression", parse_comma_expr)
+}
+
+/// A statement; a declaration is not one, but a block can hold them
+pub fn parse_statement(toks: &[Token], spans: &[Span]) -> Result<Fragment<StmtId>, ParseError> {
+    fragment(&mut SliceTokens::new(toks, spans), "statement", parse_stmt)
+}
+
+/// A type name as in a cast, like `const char *[4]`
+pub fn parse_type_name(toks: &[Token], spans: &[Span]) -> Result<Fragment<Type>, ParseError> {
+    fragment(&mut SliceTokens::new(toks, spans), "type name", parse_cast_type)
+}
+
+/// A file-scope declaration or function definition. `int a, *b;` declares two names, so there can be several.
+pub fn parse_declaration(toks: &[Token], spans: &[Span]) -> Result<Fragment<Vec<DeclId>>, ParseError> {
+    fragment(&mut SliceTokens::new(toks, spans), "declaration", |toks, cx| parse_decl(toks, cx, true))
+}
+
+// Parses all the tokens with `f`
+fn fragment<S: TokenSource, T>(
+    toks: &mut S,
+    what: &str, 
+    f: impl FnOnce(&mut S, &mut Ctx) -> Result<T, ParseError>,
+) -> Result<Fragment<T>, ParseError> {
+    let mut cx = Ctx::new();
+    let node = f(toks, &mut cx)?;
+    if toks.peek().is_some() {
+        return error(format!("unexpected {} after the {}", found(toks), what), toks.pos(), toks);
+    }
+    // arenas grow by doubling, give the unused half back
+    cx.ast.exprs.shrink_to_fit();
+    cx.ast.stmts.shrink_to_fit();
+    cx.ast.decls.shrink_to_fit();
+    cx.spans.exprs.shrink_to_fit();
+    cx.spans.stmts.shrink_to_fit();
+    cx.spans.decls.shrink_to_fit();
+    cx.spans.names.shrink_to_fit();
+    Ok(Fragment { ast: cx.ast, spans: cx.spans, node })
+}
+
+fn next_t(toks: &mut impl TokenSource) -> Result<&Token, ParseError> {
+    let at = toks.pos();
+    if toks.peek().is_none() {
+        return Err(error_at("unexpected end of input", at, at, toks));
+    }
+    Ok(toks.next().unwrap())
+}
+
+fn expect_t(toks: &mut impl TokenSource, expected: Token) -> Result<(), ParseError> {
+    let at = toks.pos();
+    match next_t(toks)? {
+        t if *t == expected => Ok(()),
+        t => {
+            let msg = format!("expected '{}', found '{}'", expected, t);
+            Err(error_at(msg, at, at, toks))
+        },
+    }
+}
+
+// Error at the tokens from `lo` up to the next one, or at the next token if none were taken since `lo`
+fn error<T>(msg: impl Into<String>, lo: usize, toks: &mut impl TokenSource) -> Result<T, ParseError> {
+    let hi = toks.pos();
+    Err(error_at(msg, lo, hi, toks))
+}
+
+// Error at the tokens from `lo` up to `hi`, or at token `lo` if the range is empty
+fn error_at(msg: impl Into<String>, lo: usize, hi: usize, toks: &mut impl TokenSource) -> ParseError {
+    // token `lo` may not have been read yet
+    toks.peek();
+    let span = match span(toks, lo, hi.max(lo + 1)) {
+        span if span != Span::default() => span,
+        // past the last token
+        _ => toks.span(toks.pos().wrapping_sub(1)).map_or(Span::default(), |last| Span { lo: last.hi, hi: last.hi }),
+    };
+    ParseError { msg: msg.into(), span }
+}
+
+// What the next token is, for error messages
+fn found(toks: &mut impl TokenSource) -> String {
+    match toks.peek() {
+        Some(t) => format!("'{}'", t),
+        None => "end of input".to_string(),
+    }
+}
+
+// Span covering the tokens from `lo` up to `hi`
+fn span(toks: &impl TokenSource, lo: usize, hi: usize) -> Span {
+    match (toks.span(lo), toks.span(hi.wrapping_sub(1))) {
+        (Some(first), Some(last)) if lo < hi => Span { lo: first.lo, hi: last.hi },
+        _ => Span::default(),
+    }
+}
+
+// Arena helpers take the node first, so it can be built with calls borrowing `cx`.
+// The node spans the tokens from index `lo` up to the next one.
+fn expr(e: Expr, lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> ExprId {
+    let span = span(toks, lo, toks.pos());
+    cx.spans.exprs.push(span);
+    cx.ast.add_expr(e)
+}
+
+fn stmt(s: Stmt, lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> StmtId {
+    let span = span(toks, lo, toks.pos());
+    cx.spans.stmts.push(span);
+    cx.ast.add_stmt(s)
+}
+
+fn decl(d: Decl, lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> DeclId {
+    let span = span(toks, lo, toks.pos());
+    cx.spans.decls.push(span);
+    cx.spans.names.push(Span::default());
+    cx.ast.add_decl(d)
+}
+
+// Records the identifier of a declaration, which `decl` leaves empty
+fn named(id: DeclId, name: Span, cx: &mut Ctx) -> DeclId {
+    cx.spans.names[id.index()] = name;
+    id
+}
+
+// Nodes that aren't written in the source, like the size of `int a[] = {1, 2}`
+fn implicit_expr(e: Expr, cx: &mut Ctx) -> ExprId {
+    cx.spans.exprs.push(Span::default());
+    cx.ast.add_expr(e)
+}
+
+fn is_type(t: &Token, cx: &Ctx) -> bool {
+    match t {
+        Token::Struct | Token::Union | Token::Enum => true,
+        _ if is_arith(t) => true,
+        Token::Const | Token::Volatile | Token::Restrict => true,
+        Token::Id { id } => cx.find_typedef(id).is_some(),
+        _ => false,
+    }
+}
+
+fn is_arith(t: &Token) -> bool {
+    matches!(t, Token::VoidT | Token::BoolT | Token::CharT | Token::ShortT | Token::IntT | Token::LongT |
+                Token::FloatT | Token::DoubleT | Token::Signed | Token::Unsigned)
+}
+
+fn is_decl(t: &Token, cx: &Ctx) -> bool {
+    matches!(t, Token::Typedef | Token::Extern | Token::Static | Token::Auto | Token::Register | Token::Inline | Token::ThreadLocal)
+        || is_type(t, cx)
+}
+
+fn parse_prg(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Vec<DeclId>, ParseError> {
+    let mut items = Vec::new();
+    while let Some(t) = toks.peek() {
+        match *t {
+            _ if is_decl(t, cx) => items.extend(parse_decl(toks, cx, true)?),
+            _ => return error(format!("expected declaration, found {}", found(toks)), toks.pos(), toks),
         }
-        panic!();
     }
+    Ok(items)
+}
+
+fn parse_decl(toks: &mut impl TokenSource, cx: &mut Ctx, global: bool) -> Result<Vec<DeclId>, ParseError> {
+    let lo = toks.pos();
+    let (base, spec) = parse_specs(toks, cx)?;
+    let typedef = spec.storage == Some(Storage::Typedef);
+    match spec.storage {
+        Some(Storage::Auto) | Some(Storage::Register) if global => return error("illegal storage class on file-scoped declaration", lo, toks),
+        None if spec.thread_local && !global => return error("'_Thread_local' block-scope declaration must be 'static' or 'extern'", lo, toks),
+        _ => {},
+    }
+    if let Some(Token::Semicolon) = toks.peek() {
+        toks.next();
+        return Ok(vec![decl(Decl::Tag { t: base }, lo, toks, cx)]);
+    }
+
+    let mut decls = Vec::new();
+    loop {
+        let at = toks.pos();
+        let (id, derivs) = parse_declarator(toks, cx)?;
+        let name = cx.name.unwrap_or_default();
+        let id = match id {
+            Some(id) => id,
+            None => return error("declaration without a name", at, toks),
+        };
 
-    fn parse_terma<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_termb(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Or => return Node::BinOp { o: BinOp::LogOr { le: Box::new(node), re: Box::new(parse_terma(toks)) } },
-                _ => return node,
+        if typedef {
+            if spec.inline || spec.thread_local {
+                return error("'typedef' combined with a function or thread storage specifier", lo, toks);
+            }
+            let t = apply_derivs(base.clone(), derivs, &cx.ast);
+            cx.declare(&id, Name::Typedef { t: t.clone() }, name)?;
+            let d = decl(Decl::Typedef { t, id, spec: spec.clone() }, lo, toks, cx);
+            decls.push(named(d, name, cx));
+        } else if let Some(Deriv::Func { args, variadic, prototype: _ }) = derivs.last() {
+            match spec.storage {
+                _ if spec.thread_local => return error(format!("'_Thread_local' on function '{}'", id), lo, toks),
+                Some(Storage::Auto) | Some(Storage::Register) => return error(format!("illegal storage class on function '{}'", id), lo, toks),
+                Some(Storage::Static) if !global => return error(format!("function '{}' declared in block scope cannot be 'static'", id), lo, toks),
+                _ => {},
             }
+            cx.declare(&id, Name::Var, name)?;
+            let args = args.clone();
+            let variadic = *variadic;
+            let t = apply_derivs(base.clone(), derivs, &cx.ast);
+            if global && decls.is_empty() && toks.peek() == Some(&Token::LC) {
+                let body = toks.pos();
+                toks.next();
+                cx.push_scope();
+                cx.variadic = variadic;
+                declare_args(&args, cx)?;
+                let block = parse_block(body, toks, cx)?;
+                cx.variadic = false;
+                cx.pop_scope();
+                let d = decl(Decl::Func { t, id, args, block: Some(block), spec }, lo, toks, cx);
+                decls.push(named(d, name, cx));
+                return Ok(decls);
+            }
+            let d = decl(Decl::Func { t, id, args, block: None, spec: spec.clone() }, lo, toks, cx);
+            decls.push(named(d, name, cx));
+        } else {
+            if spec.inline {
+                return error(format!("'inline' on non-function '{}'", id), lo, toks);
+            }
+            cx.declare(&id, Name::Var, name)?;
+            let val = match toks.peek() {
+                Some(Token::Assignment) => { toks.next(); Some(parse_init(toks, cx)?) },
+                _ => None,
+            };
+            let t = apply_derivs(base.clone(), derivs, &cx.ast);
+            let t = match &val {
+                Some(Init::List { v }) => complete_array(t, v, lo, toks, cx)?,
+                Some(Init::Expr { e }) => complete_str_array(t, *e, cx),
+                None => t,
+            };
+            let d = decl(Decl::Var { t, id, val, spec: spec.clone() }, lo, toks, cx);
+            decls.push(named(d, name, cx));
+        }
+
+        match toks.peek() {
+            Some(Token::Comma) => { toks.next(); continue },
+            Some(Token::Semicolon) => { toks.next(); break },
+            _ => return error(format!("expected ',' or ';', found {}", found(toks)), toks.pos(), toks),
         }
-        panic!();
     }
 
-    fn parse_termb<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_termc(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::And => return Node::BinOp { o: BinOp::LogAnd { le: Box::new(node), re: Box::new(parse_termb(toks)) } },
-                _ => return node,
+    Ok(decls)
+}
+
+// The parameters, whether the function takes `...` and whether it has a prototype
+fn parse_args_decl(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<(Vec<DeclId>, bool, bool), ParseError> {
+    let lo = toks.pos();
+    let mut args = Vec::new();
+    let mut variadic = false;
+    cx.push_scope();
+    let prototype = toks.peek() != Some(&Token::RP);
+    if !prototype {
+        toks.next();
+    } else {
+        loop {
+            match toks.peek() {
+                Some(Token::Ellipsis) if args.is_empty() => return error("a named parameter is required before '...'", toks.pos(), toks),
+                Some(Token::Ellipsis) => {
+                    toks.next();
+                    expect_t(toks, Token::RP)?;
+                    variadic = true;
+                    break;
+                },
+                Some(t) if is_decl(t, cx) => args.push(parse_arg_decl(toks, cx)?),
+                _ => return error(format!("expected parameter declaration, found {}", found(toks)), toks.pos(), toks),
+            }
+            match toks.peek() {
+                Some(Token::Comma) => { toks.next(); continue },
+                Some(Token::RP) => { toks.next(); break },
+                _ => return error(format!("expected ',' or ')', found {}", found(toks)), toks.pos(), toks),
             }
         }
-        panic!();
     }
+    cx.pop_scope();
 
-    fn parse_termc<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_termd(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Equal    => { toks.next(); return Node::BinOp { o: BinOp::Equal    { le: Box::new(node), re: Box::new(parse_termc(toks)) } } },
-                Token::NotEqual => { toks.next(); return Node::BinOp { o: BinOp::NotEqual { le: Box::new(node), re: Box::new(parse_termc(toks)) } } },
-                _ => return node,
-            }
+    // `(void)` declares a function without parameters
+    let is_void = |arg: &DeclId| matches!(&cx.ast[*arg], Decl::Arg { t: Type::Void, id: None, spec: _ });
+    if args.len() == 1 && !variadic && is_void(&args[0]) {
+        args.clear();
+    } else if args.iter().any(is_void) {
+        return error("'void' must be the only parameter", lo, toks);
+    }
+
+    Ok((args, variadic, prototype))
+}
+
+fn declare_args(args: &[DeclId], cx: &mut Ctx) -> Result<(), ParseError> {
+    for arg in args {
+        if let Decl::Arg { t: _, id: Some(id), spec: _ } = &cx.ast[*arg] {
+            let id = id.to_string();
+            let span = cx.spans.decl(*arg);
+            cx.declare(&id, Name::Var, span)?;
         }
-        panic!();
     }
+    Ok(())
+}
 
-    fn parse_termd<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_terme(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Bigger => { toks.next(); return Node::BinOp { o: BinOp::Bigger { le: Box::new(node), re: Box::new(parse_termd(toks)) } } },
-                Token::Lesser => { toks.next(); return Node::BinOp { o: BinOp::Lesser { le: Box::new(node), re: Box::new(parse_termd(toks)) } } },
-                Token::LesserEqual => { toks.next(); return Node::BinOp { o: BinOp::LesserEqual { le: Box::new(node), re: Box::new(parse_termd(toks)) } } },
-                Token::BiggerEqual => { toks.next(); return Node::BinOp { o: BinOp::BiggerEqual { le: Box::new(node), re: Box::new(parse_termd(toks)) } } },
-                _ => return node,
-            }
+fn parse_arg_decl(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<DeclId, ParseError> {
+    let lo = toks.pos();
+    let (base, spec) = parse_specs(toks, cx)?;
+    if !matches!(spec.storage, None | Some(Storage::Register)) || spec.inline || spec.thread_local {
+        return error("invalid storage class for parameter", lo, toks);
+    }
+    let (id, derivs) = parse_declarator(toks, cx)?;
+    let name = cx.name.unwrap_or_default();
+    let t = apply_derivs(base, derivs, &cx.ast);
+    if let Some(id) = &id {
+        if let Type::Void = t.unqualified() {
+            return error(format!("parameter '{}' has void type", id), lo, toks);
         }
-        panic!();
+        cx.declare(id, Name::Var, name)?;
     }
+    let d = decl(Decl::Arg { t, id, spec }, lo, toks, cx);
+    Ok(named(d, name, cx))
+}
 
-    fn parse_terme<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_termf(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Plus  => { toks.next(); return Node::BinOp { o: BinOp::Add { le: Box::new(node), re: Box::new(parse_terme(toks)) } } },
-                Token::Minus => { toks.next(); return Node::BinOp { o: BinOp::Sub { le: Box::new(node), re: Box::new(parse_terme(toks)) } } },
-                _ => return node,
+fn parse_stmt(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    let lo = toks.pos();
+    // an identifier and a colon are a label, whatever else the identifier names
+    if matches!(toks.peek(), Some(Token::Id { .. })) && toks.peek_nth(1) == Some(&Token::Colon) {
+        let id = parse_id(toks)?;
+        toks.next();
+        let block = parse_stmt(toks, cx)?;
+        return Ok(stmt(Stmt::Label { id, block }, lo, toks, cx));
+    }
+    let node = match toks.peek() {
+        Some(Token::LC)        => { toks.next(); return parse_block(lo, toks, cx) },
+        Some(Token::Semicolon) => { toks.next(); return Ok(stmt(Stmt::Empty, lo, toks, cx)) },
+        Some(Token::If)        => { toks.next(); return parse_if(lo, toks, cx) },
+        Some(Token::While)     => { toks.next(); return parse_while(lo, toks, cx) },
+        Some(Token::For)       => { toks.next(); return parse_for(lo, toks, cx) },
+        Some(Token::Switch)    => { toks.next(); return parse_switch(lo, toks, cx) },
+        Some(Token::Case)      => { toks.next(); return parse_case(lo, toks, cx) },
+        Some(Token::Default)   => {
+            toks.next(); expect_t(toks, Token::Colon)?;
+            match cx.switches.last_mut() {
+                Some(switch) if !switch.default => switch.default = true,
+                Some(_) => return error("multiple default labels in one switch", lo, toks),
+                None => return error("'default' label not within a switch statement", lo, toks),
             }
-        }
-        panic!();
+            let block = parse_stmt(toks, cx)?;
+            return Ok(stmt(Stmt::Default { block }, lo, toks, cx));
+        },
+        Some(Token::Goto)     => { toks.next(); Stmt::Goto { id: parse_id(toks)? } },
+        Some(Token::Break)    => { toks.next(); Stmt::Break },
+        Some(Token::Continue) => { toks.next(); Stmt::Continue },
+        Some(Token::Return)   => {
+            toks.next();
+            match toks.peek() {
+                Some(Token::Semicolon) => Stmt::Return { e: None },
+                _ => Stmt::Return { e: Some(parse_comma_expr(toks, cx)?) },
+            }
+        },
+        _ => Stmt::Expr { e: parse_comma_expr(toks, cx)? },
+    };
+
+    expect_t(toks, Token::Semicolon)?;
+
+    Ok(stmt(node, lo, toks, cx))
+}
+
+fn parse_if(lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    expect_t(toks, Token::LP)?;
+    let cond = parse_comma_expr(toks, cx)?;
+    expect_t(toks, Token::RP)?;
+    let block = parse_stmt(toks, cx)?;
+    let next = match toks.peek() {
+        Some(Token::Else) => {
+            toks.next();
+            match toks.peek() {
+                Some(Token::If) => {
+                    let lo = toks.pos();
+                    toks.next();
+                    Some(parse_if(lo, toks, cx)?)
+                },
+                _ => Some(parse_stmt(toks, cx)?),
+            }
+        },
+        _ => None,
+    };
+
+    Ok(stmt(Stmt::If { cond, block, next }, lo, toks, cx))
+}
+
+fn parse_while(lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    expect_t(toks, Token::LP)?;
+    let cond = parse_comma_expr(toks, cx)?;
+    expect_t(toks, Token::RP)?;
+    let block = parse_stmt(toks, cx)?;
+
+    Ok(stmt(Stmt::While { cond, block }, lo, toks, cx))
+}
+
+fn parse_for(lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    expect_t(toks, Token::LP)?;
+    cx.push_scope();
+    let var = match toks.peek() {
+        Some(t) if is_decl(t, cx) => Some(ForInit::Decl { v: parse_decl(toks, cx, false)? }),
+        _ => parse_opt_expr(toks, cx, Token::Semicolon)?.map(|e| ForInit::Expr { e }),
+    };
+    let cond = parse_opt_expr(toks, cx, Token::Semicolon)?;
+    let e = parse_opt_expr(toks, cx, Token::RP)?;
+    let block = parse_stmt(toks, cx)?;
+    cx.pop_scope();
+
+    Ok(stmt(Stmt::For { var, cond, e, block }, lo, toks, cx))
+}
+
+// An optional comma expression followed by `end`
+fn parse_opt_expr(toks: &mut impl TokenSource, cx: &mut Ctx, end: Token) -> Result<Option<ExprId>, ParseError> {
+    let e = match toks.peek() {
+        Some(t) if *t == end => None,
+        _ => Some(parse_comma_expr(toks, cx)?),
+    };
+    expect_t(toks, end)?;
+    Ok(e)
+}
+
+fn parse_switch(lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    expect_t(toks, Token::LP)?;
+    let cond = parse_comma_expr(toks, cx)?;
+    expect_t(toks, Token::RP)?;
+    cx.switches.push(Switch { cases: Vec::new(), default: false });
+    let block = parse_stmt(toks, cx)?;
+    let switch = cx.switches.pop().unwrap();
+
+    Ok(stmt(Stmt::Switch { cond, block, cases: switch.cases, default: switch.default }, lo, toks, cx))
+}
+
+fn parse_case(lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    let e = parse_terma(toks, cx)?;
+    let val = constant(e, "case label does not reduce to an integer constant", lo, toks, cx)?;
+    expect_t(toks, Token::Colon)?;
+    match cx.switches.last_mut() {
+        Some(switch) if switch.cases.contains(&val) => return error(format!("duplicate case value {}", val), lo, toks),
+        Some(switch) => switch.cases.push(val),
+        None => return error("'case' label not within a switch statement", lo, toks),
+    }
+    let block = parse_stmt(toks, cx)?;
+
+    Ok(stmt(Stmt::Case { e, block }, lo, toks, cx))
+}
+
+fn parse_comma_expr(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_expr(toks, cx)?;
+    while let Some(Token::Comma) = toks.peek() {
+        toks.next();
+        node = expr(Expr::BinOp { o: BinOp::Comma { le: node, re: parse_expr(toks, cx)? } }, lo, toks, cx);
+    }
+    Ok(node)
+}
+
+fn parse_expr(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let node = parse_terma(toks, cx)?;
+    let o = match toks.peek() {
+        Some(Token::Assignment) => { toks.next(); BinOp::Assign    { le: node, re: parse_expr(toks, cx)? } },
+        Some(Token::AddAssign)  => { toks.next(); BinOp::AddAssign { le: node, re: parse_expr(toks, cx)? } },
+        Some(Token::SubAssign)  => { toks.next(); BinOp::SubAssign { le: node, re: parse_expr(toks, cx)? } },
+        Some(Token::MulAssign)  => { toks.next(); BinOp::MulAssign { le: node, re: parse_expr(toks, cx)? } },
+        Some(Token::DivAssign)  => { toks.next(); BinOp::DivAssign { le: node, re: parse_expr(toks, cx)? } },
+        Some(Token::ModAssign)  => { toks.next(); BinOp::ModAssign { le: node, re: parse_expr(toks, cx)? } },
+        _ => return Ok(node),
+    };
+    Ok(expr(Expr::BinOp { o }, lo, toks, cx))
+}
+
+fn parse_terma(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_termb(toks, cx)?;
+    loop {
+        let o = match toks.peek() {
+            Some(Token::Or) => { toks.next(); BinOp::LogOr { le: node, re: parse_termb(toks, cx)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::BinOp { o }, lo, toks, cx);
+    }
+}
+
+fn parse_termb(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_termc(toks, cx)?;
+    loop {
+        let o = match toks.peek() {
+            Some(Token::And) => { toks.next(); BinOp::LogAnd { le: node, re: parse_termc(toks, cx)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::BinOp { o }, lo, toks, cx);
+    }
+}
+
+fn parse_termc(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_termd(toks, cx)?;
+    loop {
+        let o = match toks.peek() {
+            Some(Token::Equal)    => { toks.next(); BinOp::Equal    { le: node, re: parse_termd(toks, cx)? } },
+            Some(Token::NotEqual) => { toks.next(); BinOp::NotEqual { le: node, re: parse_termd(toks, cx)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::BinOp { o }, lo, toks, cx);
+    }
+}
+
+fn parse_termd(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_terme(toks, cx)?;
+    loop {
+        let o = match toks.peek() {
+            Some(Token::Bigger)      => { toks.next(); BinOp::Bigger      { le: node, re: parse_terme(toks, cx)? } },
+            Some(Token::Lesser)      => { toks.next(); BinOp::Lesser      { le: node, re: parse_terme(toks, cx)? } },
+            Some(Token::LesserEqual) => { toks.next(); BinOp::LesserEqual { le: node, re: parse_terme(toks, cx)? } },
+            Some(Token::BiggerEqual) => { toks.next(); BinOp::BiggerEqual { le: node, re: parse_terme(toks, cx)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::BinOp { o }, lo, toks, cx);
+    }
+}
+
+fn parse_terme(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_termf(toks, cx)?;
+    loop {
+        let o = match toks.peek() {
+            Some(Token::Plus)  => { toks.next(); BinOp::Add { le: node, re: parse_termf(toks, cx)? } },
+            Some(Token::Minus) => { toks.next(); BinOp::Sub { le: node, re: parse_termf(toks, cx)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::BinOp { o }, lo, toks, cx);
+    }
+}
+
+fn parse_termf(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let mut node = parse_termg(toks, cx)?;
+    loop {
+        let o = match toks.peek() {
+            Some(Token::Star)    => { toks.next(); BinOp::Mul { le: node, re: parse_termg(toks, cx)? } },
+            Some(Token::Slash)   => { toks.next(); BinOp::Div { le: node, re: parse_termg(toks, cx)? } },
+            Some(Token::Percent) => { toks.next(); BinOp::Mod { le: node, re: parse_termg(toks, cx)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::BinOp { o }, lo, toks, cx);
     }
+}
 
-    fn parse_termf<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let node = parse_termg(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Star    => { toks.next(); return Node::BinOp { o: BinOp::Mul { le: Box::new(node), re: Box::new(parse_termf(toks)) } } },
-                Token::Slash   => { toks.next(); return Node::BinOp { o: BinOp::Div { le: Box::new(node), re: Box::new(parse_termf(toks)) } } },
-                Token::Percent => { toks.next(); return Node::BinOp { o: BinOp::Mod { le: Box::new(node), re: Box::new(parse_termf(toks)) } } },
-                _ => return node,
+fn parse_termg(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let o = match toks.peek() {
+        Some(Token::Ampersand) => { toks.next(); UnOp::AddressOf   { e: parse_termg(toks, cx)? } },
+        Some(Token::Star)      => { toks.next(); UnOp::Indirection { e: parse_termg(toks, cx)? } },
+        Some(Token::Negation)  => { toks.next(); UnOp::LogNot      { e: parse_termg(toks, cx)? } },
+        Some(Token::Inc)       => { toks.next(); UnOp::PreInc      { e: parse_termg(toks, cx)? } },
+        Some(Token::Dec)       => { toks.next(); UnOp::PreDec      { e: parse_termg(toks, cx)? } },
+        Some(Token::Plus)      => { toks.next(); UnOp::UnPlus      { e: parse_termg(toks, cx)? } },
+        Some(Token::Minus)     => { toks.next(); UnOp::UnMinus     { e: parse_termg(toks, cx)? } },
+        Some(Token::Sizeof)    => {
+            toks.next();
+            if let Some(Token::LP) = toks.peek() {
+                let paren = toks.pos();
+                toks.next();
+                if toks.peek().is_some_and(|t| is_type(t, cx)) {
+                    let t = parse_cast_type(toks, cx)?;
+                    expect_t(toks, Token::RP)?;
+                    if let Some(Token::LC) = toks.peek() {
+                        let e = parse_compound_lit(t, paren, toks, cx)?;
+                        let e = parse_postfix(e, paren, toks, cx)?;
+                        return Ok(expr(Expr::UnOp { o: UnOp::SizeOf { e } }, lo, toks, cx));
+                    }
+                    return Ok(expr(Expr::UnOp { o: UnOp::SizeOfType { t } }, lo, toks, cx));
+                }
+                let e = parse_comma_expr(toks, cx)?;
+                expect_t(toks, Token::RP)?;
+                UnOp::SizeOf { e: parse_postfix(e, paren, toks, cx)? }
+            } else {
+                UnOp::SizeOf { e: parse_termg(toks, cx)? }
             }
-        }
-        panic!();
+        },
+        Some(Token::Alignof)   => {
+            toks.next();
+            expect_t(toks, Token::LP)?;
+            let t = parse_cast_type(toks, cx)?;
+            expect_t(toks, Token::RP)?;
+            UnOp::AlignOf { t }
+        },
+        _ => return parse_termh(toks, cx),
+    };
+    Ok(expr(Expr::UnOp { o }, lo, toks, cx))
+}
+
+fn parse_termh(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let node = parse_fact(toks, cx)?;
+    parse_postfix(node, lo, toks, cx)
+}
+
+// `lo` is the first token of `node`
+fn parse_postfix(mut node: ExprId, lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    loop {
+        let o = match toks.peek() {
+            Some(Token::Inc) => { toks.next(); UnOp::PostInc { e: node } },
+            Some(Token::Dec) => { toks.next(); UnOp::PostDec { e: node } },
+            Some(Token::LP)  => { toks.next(); UnOp::FuncCall { e: node, args: parse_args(toks, cx)? } },
+            Some(Token::LB)  => {
+                toks.next();
+                let idx = parse_comma_expr(toks, cx)?;
+                expect_t(toks, Token::RB)?;
+                UnOp::Subscript { le: node, re: idx }
+            },
+            Some(Token::Dot)   => { toks.next(); UnOp::Member    { e: node, id: parse_id(toks)? } },
+            Some(Token::Arrow) => { toks.next(); UnOp::PtrMember { e: node, id: parse_id(toks)? } },
+            _ => return Ok(node),
+        };
+        node = expr(Expr::UnOp { o }, lo, toks, cx);
     }
+}
 
-    fn parse_termg<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Ampersand => { toks.next(); return Node::UnOp { o: UnOp::AddressOf   { e: Box::new(parse_termh(toks)) } } },
-                Token::Star      => { toks.next(); return Node::UnOp { o: UnOp::Indirection { e: Box::new(parse_termh(toks)) } } },
-                Token::Negation  => { toks.next(); return Node::UnOp { o: UnOp::LogNot      { e: Box::new(parse_termh(toks)) } } },
-                Token::Inc       => { toks.next(); return Node::UnOp { o: UnOp::PreInc      { e: Box::new(parse_termh(toks)) } } },
-                Token::Dec       => { toks.next(); return Node::UnOp { o: UnOp::PreDec      { e: Box::new(parse_termh(toks)) } } },
-                Token::Plus      => { toks.next(); return Node::UnOp { o: UnOp::UnPlus      { e: Box::new(parse_termh(toks)) } } },
-                Token::Minus     => { toks.next(); return Node::UnOp { o: UnOp::UnMinus     { e: Box::new(parse_termh(toks)) } } },
-                _ => return parse_termh(toks),
+fn parse_fact(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    let lo = toks.pos();
+    let node = match toks.peek() {
+        Some(&Token::Inum { n }) => { toks.next(); Expr::IntN   { num: n } },
+        Some(&Token::Fnum { n }) => { toks.next(); Expr::FloatN { num: n } },
+        Some(&Token::Cnum { n }) => { toks.next(); Expr::CharN  { num: n } },
+        Some(Token::Str  { s }) => {
+            // adjacent string literals are concatenated
+            let mut s = s.to_string();
+            toks.next();
+            while let Some(Token::Str { s: next }) = toks.peek() {
+                s.push_str(next);
+                toks.next();
+            }
+            let n = implicit_expr(Expr::IntN { num: s.chars().count() as i32 + 1 }, cx);
+            Expr::StrN { s, n }
+        },
+        Some(Token::Id   { id }) => {
+            let id = id.to_string();
+            toks.next();
+            match cx.find_name(&id) {
+                Some(Name::EnumConst { val }) => Expr::EnumN { s: id, num: *val },
+                None if matches!(id.as_str(), "va_start" | "va_arg" | "va_end" | "va_copy") => return parse_va(&id, lo, toks, cx),
+                _ => Expr::Id { s: id },
             }
+        },
+        Some(Token::LP) => {
+            toks.next();
+            if toks.peek().is_some_and(|t| is_type(t, cx)) {
+                let t = parse_cast_type(toks, cx)?;
+                expect_t(toks, Token::RP)?;
+                if let Some(Token::LC) = toks.peek() {
+                    return parse_compound_lit(t, lo, toks, cx);
+                }
+                Expr::UnOp { o: UnOp::TypeCast { t, e: parse_termg(toks, cx)? } }
+            } else {
+                let node = parse_comma_expr(toks, cx)?;
+                expect_t(toks, Token::RP)?;
+                return Ok(node);
+            }
+        },
+        _ => return error(format!("expected expression, found {}", found(toks)), lo, toks),
+    };
+    Ok(expr(node, lo, toks, cx))
+}
+
+// The `va_*` builtins, which can't be ordinary calls since `va_arg` takes a type name
+fn parse_va(id: &str, lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    if id == "va_start" && !cx.variadic {
+        return error("'va_start' used in function with fixed arguments", lo, toks);
+    }
+    expect_t(toks, Token::LP)?;
+    let e = parse_expr(toks, cx)?;
+    let o = match id {
+        "va_start" => { expect_t(toks, Token::Comma)?; UnOp::VaStart { e, last: parse_expr(toks, cx)? } },
+        "va_arg" => { expect_t(toks, Token::Comma)?; UnOp::VaArg { e, t: parse_cast_type(toks, cx)? } },
+        "va_end" => UnOp::VaEnd { e },
+        "va_copy" => { expect_t(toks, Token::Comma)?; UnOp::VaCopy { le: e, re: parse_expr(toks, cx)? } },
+        _ => unreachable!(),
+    };
+    expect_t(toks, Token::RP)?;
+
+    Ok(expr(Expr::UnOp { o }, lo, toks, cx))
+}
+
+fn parse_compound_lit(t: Type, lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<ExprId, ParseError> {
+    if let Type::Function { .. } = t {
+        return error("compound literal has function type", lo, toks);
+    }
+    let v = match parse_init(toks, cx)? {
+        Init::List { v } => v,
+        Init::Expr { .. } => unreachable!(),
+    };
+    let t = complete_array(t, &v, lo, toks, cx)?;
+    Ok(expr(Expr::CompoundLit { t, v }, lo, toks, cx))
+}
+
+fn parse_init(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Init, ParseError> {
+    if toks.peek() != Some(&Token::LC) {
+        return Ok(Init::Expr { e: parse_expr(toks, cx)? });
+    }
+    toks.next();
+    let mut list = Vec::new();
+    loop {
+        if let Some(Token::RC) = toks.peek() {
+            toks.next();
+            break;
         }
-        panic!();
-    }
-
-    fn parse_termh<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node { // TODO:
-        let node = parse_fact(toks);
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Inc => { toks.next(); return Node::UnOp { o: UnOp::PostInc { e: Box::new(node) } } },
-                Token::Dec => { toks.next(); return Node::UnOp { o: UnOp::PostDec { e: Box::new(node) } } }, 
-                Token::LP  => { toks.next(); 
-                    let node2 = match node {
-                        Node::Id { s } => {
-                            Node::UnOp { o: UnOp::FuncCall { id: s, args: Box::new(parse_args(toks)) } }
-                        },
-                        _ => panic!(),
-                    }; return node2;
+        let mut d = Vec::new();
+        loop {
+            match toks.peek() {
+                Some(Token::Dot) => { toks.next(); d.push(Designator::Member { id: parse_id(toks)? }) },
+                Some(Token::LB) => {
+                    toks.next();
+                    let lo = toks.pos();
+                    let e = parse_terma(toks, cx)?;
+                    let n = match constant(e, "array index in initializer is not an integer constant", lo, toks, cx)? {
+                        n if n >= 0 => n,
+                        n => return error(format!("array index {} in initializer is negative", n), lo, toks),
+                    };
+                    expect_t(toks, Token::RB)?;
+                    d.push(Designator::Index { e, n });
                 },
-                _ => return node,
+                _ => break,
             }
         }
-        panic!();
+        if !d.is_empty() {
+            expect_t(toks, Token::Assignment)?;
+        }
+        list.push(InitItem { d, init: parse_init(toks, cx)? });
+
+        match toks.peek() {
+            Some(Token::Comma) => { toks.next(); continue },
+            Some(Token::RC) => { toks.next(); break },
+            _ => return error(format!("expected ',' or '}}' in initializer list, found {}", found(toks)), toks.pos(), toks),
+        }
     }
 
-    fn parse_fact<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        if let Some(t) = toks.peek() {
-            match t {
-                Token::Inum { n }     => { toks.next(); return Node::IntN   { num: *n } },
-                Token::Fnum { n }     => { toks.next(); return Node::FloatN { num: *n } },
-                Token::Cnum { n }      => { toks.next(); return Node::CharN  { num: *n } },
-                Token::Id   { id } => { toks.next(); return Node::Id     { s: id.to_string() } },
-                Token::LP  => { 
-                    toks.next(); let node = parse_expr(toks); toks.next();
-                    return node;
-                },
-                _ => panic!("{:?}", t),
-            }
+    Ok(Init::List { v: list })
+}
+
+// `int a[] = {1, 2, 3}` takes its size from the initializer list. Braced items fill a whole element,
+// bare expressions fill one scalar of it, as with `int m[][2] = {1, 2, 3}`.
+fn complete_array(t: Type, v: &[InitItem], lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Type, ParseError> {
+    let t = match t {
+        Type::Array { t, n: None } => t,
+        t => return Ok(t),
+    };
+    // `char s[] = {"abc"}` is the same as without the braces
+    if let [InitItem { d, init: Init::Expr { e } }] = v {
+        if d.is_empty() && matches!(t.unqualified(), Type::Char | Type::SChar | Type::UChar) && matches!(cx.ast[*e], Expr::StrN { .. }) {
+            return Ok(complete_str_array(Type::Array { t, n: None }, *e, cx));
+        }
+    }
+    let width = scalar_count(&t, &cx.ast).max(1);
+    let (mut idx, mut filled, mut size) = (0, 0, 0);
+    for item in v {
+        if let Some(Designator::Index { e: _, n }) = item.d.first() {
+            (idx, filled) = (*n, 0);
         }
-        panic!();
+        match (item.d.len(), &item.init) {
+            (0, Init::List { .. }) | (1, _) => {
+                if filled > 0 {
+                    idx += 1;
+                }
+                (idx, filled) = (idx + 1, 0);
+            },
+            (0, _) => filled += 1,
+            // `[7].x = 1` starts inside the element, the items after it continue there
+            _ => filled = 1,
+        }
+        if filled == width {
+            (idx, filled) = (idx + 1, 0);
+        }
+        size = size.max(idx + (filled > 0) as i64);
+    }
+    if size == 0 {
+        return error("array of unknown size has an empty initializer", lo, toks);
     }
+    Ok(Type::Array { t, n: Some(implicit_expr(Expr::IntN { num: size as i32 }, cx)) })
+}
 
-    fn parse_args<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let mut block = Node::Block { v: Vec::new() };
-        loop {
-            match toks.peek() {
-                Some(Token::RP) => { toks.next(); break; },
-                Some(Token::Id  { id: _ }) | Some(Token::LP) | Some(Token::Star) | 
-                Some(Token::Inum { n: _ }) | Some(Token::Fnum { n: _ }) | Some(Token::Cnum { n: _ }) 
-                  => block.get_vec_mut().push(Rc::new(RefCell::new(parse_stmt(toks)))), // TODO:
-                _ => panic!("{:?}", toks.peek()),
-            }
+// `char s[] = "abc"` takes the size of the literal, which includes the terminating null
+fn complete_str_array(t: Type, e: ExprId, cx: &Ctx) -> Type {
+    match (t, &cx.ast[e]) {
+        (Type::Array { t, n: None }, Expr::StrN { s: _, n }) => Type::Array { t, n: Some(*n) },
+        (t, _) => t,
+    }
+}
+
+// Number of scalars an object of this type holds when its braces are elided
+fn scalar_count(t: &Type, ast: &Ast) -> i64 {
+    match t.unqualified() {
+        Type::Array { t, n } => n.and_then(|n| const_eval(ast, n).ok()).unwrap_or(0) * scalar_count(t, ast),
+        Type::Struct { tag: _, id } => match &ast.records[*id].members {
+            Some(members) => members.iter().map(|m| scalar_count(&m.t, ast)).sum(),
+            None => 0,
+        },
+        Type::Union { tag: _, id } => match &ast.records[*id].members {
+            Some(members) => members.first().map_or(0, |m| scalar_count(&m.t, ast)),
+            None => 0,
+        },
+        _ => 1,
+    }
+}
+
+fn parse_args(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Vec<ExprId>, ParseError> {
+    let mut args = Vec::new();
+    if let Some(Token::RP) = toks.peek() {
+        toks.next();
+        return Ok(args);
+    }
+    loop {
+        args.push(parse_expr(toks, cx)?);
+        match toks.peek() {
+            Some(Token::Comma) => { toks.next(); continue },
+            Some(Token::RP) => { toks.next(); break },
+            _ => return error(format!("expected ',' or ')', found {}", found(toks)), toks.pos(), toks),
+        }
+    }
+
+    Ok(args)
+}
+
+// `lo` is the opening brace, already taken
+fn parse_block(lo: usize, toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<StmtId, ParseError> {
+    let mut block = Vec::new();
+    cx.push_scope();
+    loop {
+        match toks.peek() {
+            Some(Token::RC) => { toks.next(); break; },
+            Some(t) if is_decl(t, cx) => {
+                let lo = toks.pos();
+                for d in parse_decl(toks, cx, false)? {
+                    block.push(stmt(Stmt::Decl { d }, lo, toks, cx));
+                }
+            },
+            Some(_) => block.push(parse_stmt(toks, cx)?),
+            None => return error("expected '}', found end of input", toks.pos(), toks),
+        }
+    }
+    cx.pop_scope();
+
+    Ok(stmt(Stmt::Block { v: block }, lo, toks, cx))
+}
+
+fn parse_id(toks: &mut impl TokenSource) -> Result<String, ParseError> {
+    match toks.peek() {
+        Some(Token::Id { id }) => {
+            let id = id.to_string();
+            toks.next();
+            Ok(id)
+        },
+        _ => error(format!("expected identifier, found {}", found(toks)), toks.pos(), toks),
+    }
+}
+
+// Declaration specifiers in any order: storage classes, function specifiers, qualifiers and one type
+fn parse_specs(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<(Type, DeclSpec), ParseError> {
+    let lo = toks.pos();
+    let mut base = None;
+    let mut keywords = Vec::new();
+    let mut q = Quals::default();
+    let mut spec = DeclSpec::default();
+    loop {
+        let storage = match toks.peek() {
+            Some(Token::Const)    => { toks.next(); q.is_const = true; continue },
+            Some(Token::Volatile) => { toks.next(); q.is_volatile = true; continue },
+            Some(Token::Restrict) => { toks.next(); q.is_restrict = true; continue },
+            Some(Token::Inline)   => { toks.next(); spec.inline = true; continue },
+            Some(Token::ThreadLocal) if spec.thread_local => return error("duplicate '_Thread_local'", toks.pos(), toks),
+            Some(Token::ThreadLocal) => { toks.next(); spec.thread_local = true; continue },
+            Some(Token::Typedef)  => Storage::Typedef,
+            Some(Token::Extern)   => Storage::Extern,
+            Some(Token::Static)   => Storage::Static,
+            Some(Token::Auto)     => Storage::Auto,
+            Some(Token::Register) => Storage::Register,
+            Some(t) if is_arith(t) => { keywords.push(next_t(toks)?.clone()); continue },
+            // a typedef name after other type specifiers is the declarator, as in `unsigned T;`
+            Some(t) if base.is_none() && keywords.is_empty() && is_type(t, cx) => {
+                let name = match t {
+                    Token::Id { id } => Some(id.to_string()),
+                    _ => None,
+                };
+                let t = parse_type(toks, cx)?;
+                spec.typedef = name.map(|name| (name, t.clone()));
+                base = Some(t);
+                continue
+            },
+            _ => break,
+        };
+        if spec.storage.is_some() {
+            return error("multiple storage classes in declaration specifiers", toks.pos(), toks);
         }
+        toks.next();
+        spec.storage = Some(storage);
+    }
+
+    if spec.thread_local && !matches!(spec.storage, None | Some(Storage::Static) | Some(Storage::Extern)) {
+        let storage = format!("{:?}", spec.storage.unwrap()).to_lowercase();
+        return error(format!("'_Thread_local' used with '{}'", storage), lo, toks);
+    }
+    let t = match (base, keywords.is_empty()) {
+        (Some(base), true) => base,
+        (None, false) => match arith_type(&keywords) {
+            Ok(t) => t,
+            Err(msg) => return error(msg, lo, toks),
+        },
+        (Some(_), false) => return error("two or more data types in declaration specifiers", lo, toks),
+        (None, true) => return error(format!("expected type specifier, found {}", found(toks)), toks.pos(), toks),
+    };
+    match qualify(t, q) {
+        Ok(t) => Ok((t, spec)),
+        Err(msg) => error(msg, lo, toks),
+    }
+}
+
+// Maps any legal ordering of arithmetic type keywords to its canonical type
+fn arith_type(keywords: &[Token]) -> Result<Type, String> {
+    let count = |t: Token| keywords.iter().filter(|k| **k == t).count();
+    let (signed, unsigned) = (count(Token::Signed), count(Token::Unsigned));
+    let written = || keywords.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(" ");
+    if signed + unsigned > 1 {
+        return Err(format!("duplicate or conflicting 'signed'/'unsigned' in '{}'", written()));
+    }
+    let spec = (
+        count(Token::VoidT), count(Token::BoolT), count(Token::CharT), count(Token::ShortT),
+        count(Token::IntT), count(Token::LongT), count(Token::FloatT), count(Token::DoubleT),
+    );
+    let plain = signed + unsigned == 0;
+    Ok(match spec {
+        (1, 0, 0, 0, 0, 0, 0, 0) if plain => Type::Void,
+        (0, 1, 0, 0, 0, 0, 0, 0) if plain => Type::Bool,
+        (0, 0, 1, 0, 0, 0, 0, 0) if plain => Type::Char,
+        (0, 0, 1, 0, 0, 0, 0, 0) if signed == 1 => Type::SChar,
+        (0, 0, 1, 0, 0, 0, 0, 0) => Type::UChar,
+        (0, 0, 0, 1, 0 | 1, 0, 0, 0) if unsigned == 1 => Type::UShort,
+        (0, 0, 0, 1, 0 | 1, 0, 0, 0) => Type::Short,
+        (0, 0, 0, 0, 0 | 1, 0, 0, 0) if unsigned == 1 => Type::UInt,
+        (0, 0, 0, 0, 0 | 1, 0, 0, 0) => Type::Int,
+        (0, 0, 0, 0, 0 | 1, 1, 0, 0) if unsigned == 1 => Type::ULong,
+        (0, 0, 0, 0, 0 | 1, 1, 0, 0) => Type::Long,
+        (0, 0, 0, 0, 0 | 1, 2, 0, 0) if unsigned == 1 => Type::ULongLong,
+        (0, 0, 0, 0, 0 | 1, 2, 0, 0) => Type::LongLong,
+        (0, 0, 0, 0, 0, 0, 1, 0) if plain => Type::Float,
+        (0, 0, 0, 0, 0, 0, 0, 1) if plain => Type::Double,
+        (0, 0, 0, 0, 0, 1, 0, 1) if plain => Type::LongDouble,
+        _ => return Err(format!("invalid combination of type specifiers '{}'", written())),
+    })
+}
 
-        block
+// Specifiers allowed in type names and struct members
+fn parse_spec_quals(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Type, ParseError> {
+    let lo = toks.pos();
+    match parse_specs(toks, cx)? {
+        (t, spec) if spec.storage.is_none() && !spec.thread_local && !spec.inline => Ok(t),
+        _ => error("storage class or function specifier in a type name", lo, toks),
     }
+}
 
-    fn parse_block<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Node {
-        let mut block = Node::Block { v: Vec::new() };
+fn qualify(t: Type, q: Quals) -> Result<Type, String> {
+    if q.is_restrict && !matches!(t.unqualified(), Type::Ptr { .. }) {
+        return Err("restrict requires a pointer type".to_string());
+    }
+    Ok(t.qualify(q))
+}
+
+fn parse_type(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Type, ParseError> {
+    let lo = toks.pos();
+    match next_t(toks)? {
+        Token::Struct => parse_record(toks, cx, false),
+        Token::Union => parse_record(toks, cx, true),
+        Token::Enum => parse_enum(toks, cx),
+        Token::Id { id } => match cx.find_typedef(id) {
+            Some(t) => Ok(t),
+            None => error(format!("unknown type name '{}'", id), lo, toks),
+        },
+        t => error(format!("expected type, found '{}'", t), lo, toks),
+    }
+}
+
+fn parse_tag(toks: &mut impl TokenSource, cx: &mut Ctx, new: impl Fn(&mut Ctx, Option<String>) -> Type) -> Result<Type, ParseError> {
+    let lo = toks.pos();
+    let tag = match toks.peek() {
+        Some(Token::Id { id }) => {
+            let id = id.to_string();
+            toks.next();
+            Some(id)
+        },
+        _ => None,
+    };
+
+    let t = match (toks.peek(), &tag) {
+        // `struct T { ... }` defines T in the current scope, completing a forward declaration if there is one
+        (Some(Token::LC), Some(tag)) => match cx.find_tag(tag, true) {
+            Some(t) if !cx.is_complete(&t) => t,
+            Some(_) => return error(format!("redefinition of '{}'", tag), lo, toks),
+            None => new(cx, Some(tag.to_string())),
+        },
+        (Some(Token::LC), None) => new(cx, None),
+        // `struct T;` always declares a new incomplete type in the current scope
+        (Some(Token::Semicolon), Some(tag)) => match cx.find_tag(tag, true) {
+            Some(t) => t,
+            None => new(cx, Some(tag.to_string())),
+        },
+        (_, Some(tag)) => match cx.find_tag(tag, false) {
+            Some(t) => t,
+            None => new(cx, Some(tag.to_string())),
+        },
+        (_, None) => return error(format!("expected tag or body, found {}", found(toks)), lo, toks),
+    };
+
+    Ok(t)
+}
+
+fn parse_record(toks: &mut impl TokenSource, cx: &mut Ctx, union: bool) -> Result<Type, ParseError> {
+    let lo = toks.pos();
+    let t = parse_tag(toks, cx, |cx, tag| cx.new_record(tag, union))?;
+    let id = match &t {
+        Type::Struct { tag: _, id } if !union => *id,
+        Type::Union { tag: _, id } if union => *id,
+        _ => return wrong_tag(&t, lo, toks),
+    };
+    if let Some(Token::LC) = toks.peek() {
+        toks.next();
+        cx.ast.records[id].members = Some(parse_members(toks, cx)?);
+    }
+
+    Ok(t)
+}
+
+fn wrong_tag<T>(t: &Type, lo: usize, toks: &mut impl TokenSource) -> Result<T, ParseError> {
+    let tag = match t {
+        Type::Struct { tag: Some(tag), id: _ } | Type::Union { tag: Some(tag), id: _ } | Type::Enum { tag: Some(tag), id: _ } => tag,
+        _ => "",
+    };
+    error(format!("'{}' defined as wrong kind of tag", tag), lo, toks)
+}
+
+fn parse_enum(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Type, ParseError> {
+    let lo = toks.pos();
+    let t = parse_tag(toks, cx, |cx, tag| cx.new_enum(tag))?;
+    let en = match &t {
+        Type::Enum { tag: _, id } => *id,
+        _ => return wrong_tag(&t, lo, toks),
+    };
+    if let Some(Token::LC) = toks.peek() {
+        toks.next();
+        let mut consts = Vec::new();
+        // value of an enumerator without one, None after `INT_MAX`
+        let mut next = Some(0);
         loop {
+            if toks.peek() == Some(&Token::RC) && !consts.is_empty() {
+                toks.next();
+                break;
+            }
+            let at = toks.pos();
+            let id = parse_id(toks)?;
+            let name = span(toks, at, toks.pos());
+            let mut lo = at;
+            let val = match toks.peek() {
+                Some(Token::Assignment) => {
+                    toks.next();
+                    lo = toks.pos();
+                    let e = parse_terma(toks, cx)?;
+                    // enumerators have type `int`, which is the range `const_eval` keeps to
+                    constant(e, format!("enumerator value for '{}' is not an integer constant", id), lo, toks, cx)? as i32
+                },
+                _ => match next {
+                    Some(val) => val,
+                    None => return error(format!("overflow in enumeration value for '{}'", id), lo, toks),
+                },
+            };
+            next = val.checked_add(1);
+            let val = val as i64;
+            cx.declare(&id, Name::EnumConst { val }, name)?;
+            cx.spans.enumerators[en].push(name);
+            consts.push(Enumerator { id, val });
+
             match toks.peek() {
-                Some(Token::RC) => { toks.next(); break; },
-                Some(Token::Return) | Some(Token::Break) | Some(Token::Continue) |
-                Some(Token::Id { id: _ }) | Some(Token::LP) | Some(Token::Star)  
-                => block.get_vec_mut().push(Rc::new(RefCell::new(parse_stmt(toks)))), // TODO:
-                _ => panic!("{:?}", toks.peek()),
+                Some(Token::Comma) => { toks.next(); continue },
+                Some(Token::RC) => { toks.next(); break },
+                _ => return error(format!("expected ',' or '}}', found {}", found(toks)), toks.pos(), toks),
             }
         }
-
-        block
+        cx.ast.enums[en].consts = Some(consts);
     }
 
-    fn parse_id(t: &Token) -> String {
-        match t {
-            Token::Id { id } => id.to_string(),
-            _ => panic!()
+    Ok(t)
+}
+
+fn parse_members(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Vec<Member>, ParseError> {
+    let mut members = Vec::new();
+    loop {
+        match toks.peek() {
+            Some(Token::RC) => { toks.next(); break; },
+            Some(t) if is_type(t, cx) => {
+                let lo = toks.pos();
+                let base = parse_spec_quals(toks, cx)?;
+                if let Some(Token::Semicolon) = toks.peek() {
+                    match base {
+                        Type::Struct { tag: None, .. } | Type::Union { tag: None, .. } => members.push(Member { t: base, id: None }),
+                        Type::Struct { .. } | Type::Union { .. } => {},
+                        _ => return error("declaration does not declare anything", lo, toks),
+                    }
+                    toks.next();
+                    continue;
+                }
+
+                loop {
+                    let at = toks.pos();
+                    let (id, derivs) = parse_declarator(toks, cx)?;
+                    if id.is_none() {
+                        return error("expected member name", at, toks);
+                    }
+                    members.push(Member { t: apply_derivs(base.clone(), derivs, &cx.ast), id });
+                    match toks.peek() {
+                        Some(Token::Comma) => { toks.next(); continue },
+                        Some(Token::Semicolon) => { toks.next(); break },
+                        _ => return error(format!("expected ',' or ';', found {}", found(toks)), toks.pos(), toks),
+                    }
+                }
+            },
+            _ => return error(format!("expected member declaration or '}}', found {}", found(toks)), toks.pos(), toks),
         }
     }
 
-    fn parse_type<'a>(t: &Token, toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> Type {
-        return match t {
-            // TODO: array
-            Token::IntT => create_ptr(check_ptr(toks), Type::Int),
-            Token::FloatT => create_ptr(check_ptr(toks), Type::Float),
-            Token::CharT => create_ptr(check_ptr(toks), Type::Char),
-            Token::VoidT => create_ptr(check_ptr(toks), Type::Void),
-            _ => panic!()
-        };
+    Ok(members)
+}
+
+fn parse_cast_type(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Type, ParseError> {
+    let base = parse_spec_quals(toks, cx)?;
+    let at = toks.pos();
+    match parse_declarator(toks, cx)? {
+        (None, derivs) => Ok(apply_derivs(base, derivs, &cx.ast)),
+        (Some(id), _) => error(format!("unexpected '{}' in type name", id), at, toks),
+    }
+}
+
+// Declarator is parsed into a list of derivations applied to the base type
+// from left to right, so `int *a[10]` gives [Ptr, Array] and `int (*p)[10]` gives [Array, Ptr].
+// The last derivation is the one bound to the identifier itself.
+enum Deriv {
+    Ptr   { q: Quals },
+    Array { n: Option<ExprId> },
+    Func  { args: Vec<DeclId>, variadic: bool, prototype: bool },
+}
 
-        fn check_ptr<'a>(toks: &mut std::iter::Peekable<impl Iterator<Item = &'a Token>>) -> u8 {
+fn parse_declarator(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<(Option<String>, Vec<Deriv>), ParseError> {
+    let mut ptrs = Vec::new();
+    while let Some(Token::Star) = toks.peek() {
+        toks.next();
+        let mut q = Quals::default();
+        loop {
             match toks.peek() {
-                Some(Token::Star) => {
-                    let mut ptr_count: u8 = 1;
-                    next_t(toks);
-                    loop {
-                        match toks.peek() {
-                            Some(Token::Star) => {
-                                ptr_count += 1;
-                                next_t(toks);
-                            },
-                            _ => break,
-                        };
-                    }
-                    return ptr_count;
-                },
-                _ => 0,
+                Some(Token::Const)    => q.is_const = true,
+                Some(Token::Volatile) => q.is_volatile = true,
+                Some(Token::Restrict) => q.is_restrict = true,
+                _ => break,
             }
+            toks.next();
         }
+        ptrs.push(Deriv::Ptr { q });
+    }
 
-        fn create_ptr(ptr_count: u8, end: Type) -> Type {
-            if ptr_count == 0 {
-                return end;
+    let mut id = None;
+    // declarators of parameters come in between, so the name is kept until the end
+    let mut name = None;
+    let mut inner = Vec::new();
+    let mut suffixes = Vec::new();
+    match toks.peek() {
+        Some(Token::Id { id: s }) => {
+            id = Some(s.to_string());
+            name = Some(span(toks, toks.pos(), toks.pos() + 1));
+            toks.next();
+        },
+        Some(Token::LP) => {
+            toks.next();
+            match toks.peek() {
+                Some(Token::Star) | Some(Token::LP) => {
+                    (id, inner) = parse_declarator(toks, cx)?;
+                    name = cx.name;
+                    expect_t(toks, Token::RP)?;
+                },
+                Some(Token::Id { id: s }) if cx.find_typedef(s).is_none() => {
+                    (id, inner) = parse_declarator(toks, cx)?;
+                    name = cx.name;
+                    expect_t(toks, Token::RP)?;
+                },
+                _ => suffixes.push(parse_func_deriv(toks, cx)?),
             }
-            Type::Ptr { t: Box::new(create_ptr(ptr_count - 1, end)) }
+        },
+        _ => {},
+    }
+
+    loop {
+        match toks.peek() {
+            Some(Token::LB) => {
+                toks.next();
+                let lo = toks.pos();
+                let n = match toks.peek() {
+                    Some(Token::RB) => None,
+                    _ => Some(parse_expr(toks, cx)?),
+                };
+                // variable length arrays aren't supported, so the size has to be a constant
+                match n.map(|n| (n, const_eval(&cx.ast, n))) {
+                    Some((_, Ok(n))) if n <= 0 => return error(format!("array size {} is not positive", n), lo, toks),
+                    Some((_, Err(ConstError::Overflow))) => return error("integer overflow in constant expression", lo, toks),
+                    Some((n, Err(ConstError::NotConstant))) if !needs_layout(&cx.ast, n) => return error("array size is not an integer constant", lo, toks),
+                    _ => {},
+                }
+                expect_t(toks, Token::RB)?;
+                suffixes.push(Deriv::Array { n });
+            },
+            Some(Token::LP) => { toks.next(); suffixes.push(parse_func_deriv(toks, cx)?); },
+            _ => break,
         }
     }
+
+    ptrs.extend(suffixes.into_iter().rev());
+    ptrs.extend(inner);
+    cx.name = name;
+    Ok((id, ptrs))
+}
+
+fn parse_func_deriv(toks: &mut impl TokenSource, cx: &mut Ctx) -> Result<Deriv, ParseError> {
+    let (args, variadic, prototype) = parse_args_decl(toks, cx)?;
+    Ok(Deriv::Func { args, variadic, prototype })
+}
+
+fn apply_derivs(base: Type, derivs: Vec<Deriv>, ast: &Ast) -> Type {
+    derivs.into_iter().fold(base, |t, d| match d {
+        Deriv::Ptr { q } => Type::Ptr { t: Box::new(t) }.qualify(q),
+        Deriv::Array { n } => Type::Array { t: Box::new(t), n },
+        Deriv::Func { args, variadic, prototype } => Type::Function {
+            ret: Box::new(t),
+            params: args.iter().map(|arg| match &ast[*arg] {
+                // top-level qualifiers of a parameter are not part of the function type
+                Decl::Arg { t, id: _, spec: _ } => t.unqualified().clone(),
+                _ => unreachable!(),
+            }).collect(),
+            variadic,
+            prototype,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::dump::sexpr;
+    use crate::parse::printer::type_name;
+    use crate::parse_source;
+
+    // One s-expression per record, enum and external declaration
+    fn items(src: &str) -> Vec<String> {
+        let out = sexpr(&parse_source(src).unwrap());
+        out.strip_suffix(")\n").unwrap().lines().skip(1).map(|line| line.trim().to_string()).collect()
+    }
+
+    // File-scope declarations as `name: type`
+    fn decls(src: &str) -> Vec<String> {
+        let tu = parse_source(src).unwrap();
+        tu.items.iter().map(|id| match &tu.ast[*id] {
+            Decl::Func { t, id, .. } | Decl::Var { t, id, .. } | Decl::Typedef { t, id, .. } => format!("{}: {}", id, type_name(&tu.ast, t)),
+            Decl::Arg { t, .. } | Decl::Tag { t } => type_name(&tu.ast, t),
+        }).collect()
+    }
+
+    fn error(src: &str) -> String {
+        parse_source(src).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn declarators() {
+        let src = "int *a[10]; int (*p)[10]; int (*fp)(int, char *); char **argv; int m[2][3]; int *(*g(void))[4];";
+        assert_eq!(decls(src), [
+            "a: int *[10]", "p: int (*)[10]", "fp: int (*)(int, char *)", "argv: char **", "m: int[2][3]", "g: int *(*(void))[4]",
+        ]);
+        assert_eq!(error("int a[-1];"), "array size -1 is not positive");
+        assert_eq!(error("int a[0];"), "array size 0 is not positive");
+        assert_eq!(error("int a[1/0];"), "array size is not an integer constant");
+        assert_eq!(error("int n; int a[n];"), "array size is not an integer constant");
+        assert_eq!(error("int n; int a[sizeof n + n];"), "array size is not an integer constant");
+        assert_eq!(error("int a[65536 * 32768];"), "integer overflow in constant expression");
+        // `sizeof` is constant, but the parser doesn't know the sizes of types
+        assert_eq!(decls("int n; int a[sizeof n * 2];")[1], "a: int[sizeof n * 2]");
+        assert_eq!(error("int a[] = {};"), "array of unknown size has an empty initializer");
+    }
+
+    #[test]
+    fn casts() {
+        let src = "typedef int T; int f(int *p, double d, int x) { return (T)+1 + (x) + (int)(char)d + (T)(x) + **(int (*)[2])p; }";
+        assert_eq!(items(src)[1], "(Func f \"int (int *, double, int)\" (Arg p \"int *\") (Arg d double) (Arg x int) :body (Block (Return :value \
+            (Add (Add (Add (Add (TypeCast int (UnPlus (IntN 1))) (Id x)) (TypeCast int (TypeCast char (Id d)))) (TypeCast int (Id x))) \
+            (Indirection (Indirection (TypeCast \"int (*)[2]\" (Id p))))))))");
+        assert_eq!(error("int f(int x) { return (int x)x; }"), "unexpected 'x' in type name");
+        assert_eq!(error("int f(int x) { return (int)"), "expected expression, found end of input");
+    }
+
+    #[test]
+    fn postfix_chains() {
+        let src = "int f(void) { a[i][j]; f(x)(y); tbl[i].fn(x)->next++; (*fp)(1, 2); s.a.b--; }";
+        assert_eq!(items(src)[0], "(Func f \"int (void)\" :body (Block (ExprStmt (Subscript (Subscript (Id a) (Id i)) (Id j))) (ExprStmt \
+            (FuncCall (FuncCall (Id f) (Id x)) (Id y))) (ExprStmt (PostInc (PtrMember next (FuncCall (Member fn \
+            (Subscript (Id tbl) (Id i))) (Id x))))) (ExprStmt (FuncCall (Indirection (Id fp)) (IntN 1) (IntN 2))) \
+            (ExprStmt (PostDec (Member b (Member a (Id s)))))))");
+        assert_eq!(error("int f(void) { a[1; }"), "expected ']', found ';'");
+        assert_eq!(error("int f(void) { s.1; }"), "expected identifier, found '1'");
+        assert_eq!(error("int f(void) { f(1, ); }"), "expected expression, found ')'");
+    }
+
+    #[test]
+    fn records() {
+        let src = "struct node; struct node { int v; struct node *next; }; union u { int i; struct { char c; } in; } x, *px; \
+            struct node n; int f(void) { return n.next->v + px->in.c; }";
+        assert_eq!(items(src)[..3], [
+            "(Record struct node id=0 (Member v int) (Member next \"struct node *\"))",
+            "(Record union u id=1 (Member i int) (Member in \"struct <anonymous #2>\"))",
+            "(Record struct id=2 (Member c char))",
+        ]);
+        assert_eq!(items(src)[8], "(Func f \"int (void)\" :body (Block (Return :value \
+            (Add (PtrMember v (Member next (Id n))) (Member c (PtrMember in (Id px)))))))");
+        assert_eq!(decls(src)[2..], ["x: union u", "px: union u *", "n: struct node", "f: int (void)"]);
+        assert_eq!(error("struct s { int a; }; struct s { int b; };"), "redefinition of 's'");
+        assert_eq!(error("struct s; union s *p;"), "'s' defined as wrong kind of tag");
+        assert_eq!(error("struct { int; } s;"), "declaration does not declare anything");
+        assert_eq!(error("struct s { int a }; "), "expected ',' or ';', found '}'");
+    }
+
+    #[test]
+    fn enums() {
+        let src = "enum color { RED, GREEN = 5, BLUE, LAST = BLUE * 2 + (int)GREEN - !RED, }; int a[BLUE]; \
+            enum { NEG = -2147483647 - 1, MAX = 2147483647 } e; int f(int x) { switch (x) { case GREEN: case LAST: return MAX; } }";
+        assert_eq!(items(src)[..2], [
+            "(Enum color id=0 (Enumerator RED 0) (Enumerator GREEN 5) (Enumerator BLUE 6) (Enumerator LAST 16))",
+            "(Enum id=1 (Enumerator NEG -2147483648) (Enumerator MAX 2147483647))",
+        ]);
+        assert_eq!(items(src)[3], "(Var a int[BLUE])");
+        assert_eq!(items(src)[5], "(Func f \"int (int)\" (Arg x int) :body (Block (Switch cases=\"5, 16\" default=false :cond (Id x) \
+            :body (Block (Case :value (EnumN GREEN 5) :body (Case :value (EnumN LAST 16) :body (Return :value (EnumN MAX 2147483647))))))))");
+        assert_eq!(error("enum { A = 2147483647 * 2147483647 * 2 + 2147483647 * 4 + 1, B };"), "integer overflow in constant expression");
+        assert_eq!(error("enum { A = 65536 * 65536 * 65536 * 65536 };"), "integer overflow in constant expression");
+        assert_eq!(error("enum { A = 2147483647, B };"), "overflow in enumeration value for 'B'");
+        assert_eq!(error("enum { A = 2147483647 + 1 };"), "integer overflow in constant expression");
+        assert_eq!(error("enum { A = -2147483647 - 1, B = -A, C = A / -1 };"), "integer overflow in constant expression");
+        assert_eq!(error("int x; enum { A = x };"), "enumerator value for 'A' is not an integer constant");
+        assert_eq!(error("enum { A = 1 / 0 };"), "enumerator value for 'A' is not an integer constant");
+        assert_eq!(error("enum { A, A };"), "redeclaration of enumerator 'A'");
+        assert_eq!(error("enum e {};"), "expected identifier, found '}'");
+    }
+
+    #[test]
+    fn typedef_scopes() {
+        let src = "typedef struct node Node; typedef unsigned long size_t; Node *n; \
+            int f(size_t size) { size_t x = (size_t)size; { int size_t = 1; x = (size_t) + 1; } return (size_t)x; }";
+        assert_eq!(decls(src), ["Node: struct node", "size_t: unsigned long", "n: struct node *", "f: int (unsigned long)"]);
+        assert_eq!(items(src)[4], "(Func f \"int (unsigned long)\" (Arg size \"unsigned long\") :body (Block (DeclStmt (Var x \"unsigned long\" :init \
+            (TypeCast \"unsigned long\" (Id size)))) (Block (DeclStmt (Var size_t int :init (IntN 1))) (ExprStmt (Assign \
+            (Id x) (Add (Id size_t) (IntN 1))))) (Return :value (TypeCast \"unsigned long\" (Id x)))))");
+        assert_eq!(decls("typedef int T; int g(int T) { return T; } T y;")[2], "y: int");
+        assert_eq!(error("typedef int T; int f(void) { int T; T x; }"), "expected ';', found 'x'");
+        assert_eq!(error("int f(void) { { typedef int T; } T x; }"), "expected ';', found 'x'");
+        assert_eq!(error("typedef int T = 1;"), "expected ',' or ';', found '='");
+    }
+
+    #[test]
+    fn switches_and_labels() {
+        let src = "int f(int x) { switch (x) { case 1: x++; case 2: { case 3: break; } default: switch (x) case 1: ; } \
+            goto out; out: return x; }";
+        assert_eq!(items(src)[0], "(Func f \"int (int)\" (Arg x int) :body (Block (Switch cases=\"1, 2, 3\" default=true :cond (Id x) :body \
+            (Block (Case :value (IntN 1) :body (ExprStmt (PostInc (Id x)))) (Case :value (IntN 2) :body (Block (Case :value \
+            (IntN 3) :body (Break)))) (Default :body (Switch cases=1 default=false :cond (Id x) :body (Case :value (IntN 1) :body \
+            (Empty)))))) (Goto out) (Label out :body (Return :value (Id x)))))");
+        assert_eq!(error("int f(int x) { switch (x) { case 1: case 1: ; } }"), "duplicate case value 1");
+        assert_eq!(error("int f(int x) { switch (x) { default: default: ; } }"), "multiple default labels in one switch");
+        assert_eq!(error("int f(int x) { switch (x) { case x: ; } }"), "case label does not reduce to an integer constant");
+        assert_eq!(error("int f(int x) { switch (x) { case 65536 * 65536: ; } }"), "integer overflow in constant expression");
+        assert_eq!(error("int f(int x) { case 1: ; }"), "'case' label not within a switch statement");
+        assert_eq!(error("int f(int x) { goto; }"), "expected identifier, found ';'");
+        assert_eq!(error("int f(int x) { out: }"), "expected expression, found '}'");
+    }
+
+    #[test]
+    fn sizeof_alignof() {
+        let src = "int f(int *p) { return sizeof p + sizeof(p) + sizeof *p + sizeof(int *[3]) + sizeof (int){1} + _Alignof(double) + sizeof(p)[0]; }";
+        assert_eq!(items(src)[0], "(Func f \"int (int *)\" (Arg p \"int *\") :body (Block (Return :value (Add (Add (Add (Add (Add (Add (SizeOf \
+            (Id p)) (SizeOf (Id p))) (SizeOf (Indirection (Id p)))) (SizeOfType \"int *[3]\")) (SizeOf (CompoundLit int \
+            (IntN 1)))) (AlignOf double)) (SizeOf (Subscript (Id p) (IntN 0)))))))");
+        assert_eq!(error("int f(void) { return _Alignof x; }"), "expected '(', found 'x'");
+        assert_eq!(error("int f(void) { return sizeof(int x); }"), "unexpected 'x' in type name");
+    }
+
+    #[test]
+    fn comma() {
+        let src = "int f(int i, int j) { i = 1, j = 2; for (i = 0, j = i; i < j; i++, j--) f(i, (i, j)); return i, j; }";
+        assert_eq!(items(src)[0], "(Func f \"int (int, int)\" (Arg i int) (Arg j int) :body (Block (ExprStmt (Comma (Assign (Id i) (IntN 1)) \
+            (Assign (Id j) (IntN 2)))) (For :init (Comma (Assign (Id i) (IntN 0)) (Assign (Id j) (Id i))) :cond (Lesser \
+            (Id i) (Id j)) :step (Comma (PostInc (Id i)) (PostDec (Id j))) :body (ExprStmt (FuncCall (Id f) (Id i) (Comma \
+            (Id i) (Id j))))) (Return :value (Comma (Id i) (Id j)))))");
+        assert_eq!(error("int f(int i) { f(i,); }"), "expected expression, found ')'");
+        assert_eq!(error("int f(int i) { i = 1,; }"), "expected expression, found ';'");
+    }
+
+    #[test]
+    fn qualifiers_and_storage() {
+        let src = "const char *s; char *const p; int const volatile *restrict q; static _Thread_local int t; \
+            extern int e; inline static int f(register int x) { auto int y; return x; }";
+        assert_eq!(items(src), [
+            "(Var s \"const char *\")",
+            "(Var p \"char *const\")",
+            "(Var q \"const volatile int *restrict\")",
+            "(Var t int storage=\"static _Thread_local\")",
+            "(Var e int storage=extern)",
+            "(Func f \"int (int)\" storage=\"static inline\" (Arg x int storage=register) :body (Block (DeclStmt (Var y int storage=auto)) \
+                (Return :value (Id x))))",
+        ]);
+        assert_eq!(error("static extern int x;"), "multiple storage classes in declaration specifiers");
+        assert_eq!(error("register int x;"), "illegal storage class on file-scoped declaration");
+        assert_eq!(error("int f(void) { _Thread_local int x; }"), "'_Thread_local' block-scope declaration must be 'static' or 'extern'");
+        assert_eq!(error("_Thread_local _Thread_local int x;"), "duplicate '_Thread_local'");
+        assert_eq!(error("inline int x;"), "'inline' on non-function 'x'");
+        assert_eq!(error("int f(static int x);"), "invalid storage class for parameter");
+        assert_eq!(error("int f(void) { static int g(void); }"), "function 'g' declared in block scope cannot be 'static'");
+        assert_eq!(error("restrict int x;"), "restrict requires a pointer type");
+        assert_eq!(error("struct s { static int x; };"), "expected member declaration or '}', found 'static'");
+    }
+
+    #[test]
+    fn arithmetic_types() {
+        let src = "unsigned long int a; long unsigned b; signed c; signed char d; char e; unsigned short f; long long g; \
+            unsigned long long int h; long double i; _Bool j; int long signed k; unsigned l; double m; float n; short o;";
+        assert_eq!(decls(src), [
+            "a: unsigned long", "b: unsigned long", "c: int", "d: signed char", "e: char", "f: unsigned short", "g: long long",
+            "h: unsigned long long", "i: long double", "j: _Bool", "k: long", "l: unsigned int", "m: double", "n: float", "o: short",
+        ]);
+        assert_eq!(error("short double x;"), "invalid combination of type specifiers 'short double'");
+        assert_eq!(error("long long long x;"), "invalid combination of type specifiers 'long long long'");
+        assert_eq!(error("signed unsigned x;"), "duplicate or conflicting 'signed'/'unsigned' in 'signed unsigned'");
+        assert_eq!(error("unsigned float x;"), "invalid combination of type specifiers 'unsigned float'");
+        assert_eq!(error("int char x;"), "invalid combination of type specifiers 'int char'");
+        assert_eq!(error("x;"), "expected declaration, found 'x'");
+    }
+
+    #[test]
+    fn initializers() {
+        let src = "struct point { int x, y; }; int a[] = {1, 2, 3,}; int b[] = { [4] = 1, 2 }; int m[][2] = {1, 2, 3}; \
+            struct point ps[] = {1, 2, 3, 4, {5, 6}, [7].x = 1, 2}; char s[] = \"abc\"; char t[] = {\"abc\"}; char *u[] = {\"abc\"}; \
+            struct point p = { .y = 2, .x = 1 }; int *z = (int[]){1, 2};";
+        assert_eq!(decls(src)[1..9], [
+            "a: int[3]", "b: int[6]", "m: int[2][2]", "ps: struct point[8]", "s: char[4]", "t: char[4]", "u: char *[1]", "p: struct point",
+        ]);
+        assert_eq!(items(src)[9], "(Var p \"struct point\" :init (InitList (Designated designators=.y (IntN 2)) (Designated designators=.x (IntN 1))))");
+        assert_eq!(items(src)[10], "(Var z \"int *\" :init (CompoundLit int[2] (IntN 1) (IntN 2)))");
+        assert_eq!(error("int a[2] = { [-1] = 1 };"), "array index -1 in initializer is negative");
+        assert_eq!(error("int x; int a[2] = { [x] = 1 };"), "array index in initializer is not an integer constant");
+        assert_eq!(error("int a[2] = { [0] 1 };"), "expected '=', found '1'");
+        assert_eq!(error("int f(void) { return (int (void)){1}; }"), "compound literal has function type");
+    }
+
+    #[test]
+    fn function_prototypes() {
+        let src = "int printf(const char *fmt, ...); int none(void); int old(); int (*fp)(); int main() { return 0; } \
+            int sum(int n, ...) { va_list ap, aq; va_start(ap, n); va_copy(aq, ap); int x = va_arg(ap, int); va_end(ap); return x; }";
+        assert_eq!(decls(src), [
+            "printf: int (const char *, ...)", "none: int (void)", "old: int ()", "fp: int (*)()", "main: int ()", "sum: int (int, ...)",
+        ]);
+        let tu = parse_source(src).unwrap();
+        let prototypes: Vec<_> = tu.items.iter().map(|id| match &tu.ast[*id] {
+            Decl::Func { t: Type::Function { prototype, .. }, .. } => *prototype,
+            Decl::Var { t: Type::Ptr { t }, .. } => matches!(**t, Type::Function { prototype: true, .. }),
+            d => panic!("{:?}", d),
+        }).collect();
+        assert_eq!(prototypes, [true, true, false, false, false, true]);
+        assert_eq!(items(src)[5], "(Func sum \"int (int, ...)\" (Arg n int) :body (Block (DeclStmt (Var ap va_list)) (DeclStmt (Var aq va_list)) \
+            (ExprStmt (VaStart (Id ap) (Id n))) (ExprStmt (VaCopy (Id aq) (Id ap))) (DeclStmt (Var x int :init (VaArg int \
+            (Id ap)))) (ExprStmt (VaEnd (Id ap))) (Return :value (Id x))))");
+        assert_eq!(error("int f(...);"), "a named parameter is required before '...'");
+        assert_eq!(error("int f(int, void);"), "'void' must be the only parameter");
+        assert_eq!(error("int f(void x);"), "parameter 'x' has void type");
+        assert_eq!(error("int f(int x, ...) { return 0; } int g(int x) { va_list ap; va_start(ap, x); }"), "'va_start' used in function with fixed arguments");
+        assert_eq!(error("int f(int x, ..., int y);"), "expected ')', found ','");
+    }
+
+    #[test]
+    fn arenas() {
+        let src = "int a[] = {1, 2}; int f(int x) { return x + 1; }";
+        let tu = parse_source(src).unwrap();
+        // the implicit size of `a` comes after the items of its initializer
+        let exprs: Vec<_> = tu.ast.exprs.iter().map(|e| format!("{:?}", e)).collect();
+        assert_eq!(exprs, [
+            "IntN { num: 1 }", "IntN { num: 2 }", "IntN { num: 2 }", "Id { s: \"x\" }", "IntN { num: 1 }",
+            "BinOp { o: Add { le: ExprId(3), re: ExprId(4) } }",
+        ]);
+        assert_eq!(tu.ast.stmts.len(), 2);
+        assert_eq!(tu.ast.decls.len(), 3);
+        // spans are a side table of the same length, the implicit size has none
+        assert_eq!((tu.spans.exprs.len(), tu.spans.stmts.len(), tu.spans.decls.len(), tu.spans.names.len()), (6, 2, 3, 3));
+        assert_eq!(tu.spans.expr(ExprId(2)), Span::default());
+        assert_eq!(&src[tu.spans.name(tu.items[1]).lo..tu.spans.name(tu.items[1]).hi], "f");
+        // ids past the arena have an empty span
+        assert_eq!(tu.spans.stmt(StmtId(7)), Span::default());
+    }
+
+    #[test]
+    fn statements_and_declarations() {
+        let src = "int f(int n) { int a = 1, b; if (n) a = 2; else if (b) return a; else {} \
+            for (int i = 0; i < n; i++) continue; for (;;) break; while (n) n--; ; return b; }";
+        let tu = parse_source(src).unwrap();
+        let Decl::Func { args, block: Some(body), .. } = &tu.ast[tu.items[0]] else { panic!() };
+        assert!(matches!(&tu.ast[args[0]], Decl::Arg { t: Type::Int, id: Some(id), .. } if id == "n"));
+        let Stmt::Block { v } = &tu.ast[*body] else { panic!() };
+        // one declaration statement per declarator
+        assert!(matches!(&tu.ast[v[0]], Stmt::Decl { d } if matches!(&tu.ast[*d], Decl::Var { val: Some(Init::Expr { .. }), .. })));
+        assert!(matches!(&tu.ast[v[1]], Stmt::Decl { d } if matches!(&tu.ast[*d], Decl::Var { val: None, .. })));
+        let Stmt::If { next: Some(next), .. } = &tu.ast[v[2]] else { panic!() };
+        assert!(matches!(&tu.ast[*next], Stmt::If { next: Some(_), .. }));
+        assert!(matches!(&tu.ast[v[3]], Stmt::For { var: Some(ForInit::Decl { v }), cond: Some(_), e: Some(_), .. } if v.len() == 1));
+        assert!(matches!(&tu.ast[v[4]], Stmt::For { var: None, cond: None, e: None, block } if matches!(tu.ast[*block], Stmt::Break)));
+        assert!(matches!(&tu.ast[v[5]], Stmt::While { .. }));
+        assert!(matches!(&tu.ast[v[6]], Stmt::Empty));
+        assert!(matches!(&tu.ast[v[7]], Stmt::Return { e: Some(_) }));
+        assert_eq!(v.len(), 8);
+
+        assert_eq!(error("int f(void) { if (return 1) ; }"), "expected expression, found 'return'");
+        assert_eq!(error("int x = 1; x = 2;"), "expected declaration, found 'x'");
+        assert_eq!(error("int f(void) { else ; }"), "expected expression, found 'else'");
+    }
 }
@@ -0,0 +1,912 @@
+//! Turns a translation unit back into C source.
+//!
+//! Expressions only get the parentheses their precedence requires. Declarations keep the typedef
+//! name their type was written with; other types, like those of casts, are printed expanded, except
+//! for anonymous records and enums which couldn't be mentioned again otherwise. A record or enum body
+//! is printed at the first mention of the type, so a forward declaration picks up the body of the
+//! definition that follows it.
+
+use std::collections::HashMap;
+
+use crate::parse::parser::*;
+
+/// Turns a translation unit back into C source.
+pub fn print(tu: &TranslationUnit) -> String {
+    let ast = &tu.ast;
+    let mut p = Printer::new(ast);
+
+    // function definitions are set apart by blank lines
+    let mut items = &tu.items[..];
+    let mut blank = false;
+    while !items.is_empty() {
+        let n = group_len(ast, items);
+        let func = matches!(&ast[items[0]], Decl::Func { block: Some(_), .. });
+        if (func || blank) && !p.out.is_empty() {
+            p.out.push('\n');
+        }
+        p.decls(&items[..n]);
+        blank = func;
+        items = &items[n..];
+    }
+
+    p.out
+}
+
+/// A type as written in a cast, like `int (*)[3]`. Records and enums are referred to by tag
+/// and never printed with their bodies, anonymous ones are told apart by their index.
+pub fn type_name(ast: &Ast, t: &Type) -> String {
+    let mut p = Printer::new(ast);
+    p.records.fill(true);
+    p.enums.fill(true);
+    for (id, r) in ast.records.iter().enumerate().filter(|(_, r)| r.tag.is_none()) {
+        let kw = if r.union { "union" } else { "struct" };
+        p.record_names.insert(id, format!("{} <anonymous #{}>", kw, id));
+    }
+    for (id, _) in ast.enums.iter().enumerate().filter(|(_, e)| e.tag.is_none()) {
+        p.enum_names.insert(id, format!("enum <anonymous #{}>", id));
+    }
+    p.type_name(t)
+}
+
+// Precedence levels, loosest first
+const COMMA: u8 = 1;
+const ASSIGN: u8 = 2;
+const LOG_OR: u8 = 3;
+const LOG_AND: u8 = 4;
+const EQUALITY: u8 = 5;
+const RELATIONAL: u8 = 6;
+const ADDITIVE: u8 = 7;
+const MULTIPLICATIVE: u8 = 8;
+const UNARY: u8 = 9;
+const POSTFIX: u8 = 10;
+const PRIMARY: u8 = 11;
+
+fn prec(e: &Expr) -> u8 {
+    match e {
+        Expr::BinOp { o } => match o {
+            BinOp::Comma { .. } => COMMA,
+            BinOp::Assign { .. } | BinOp::AddAssign { .. } | BinOp::SubAssign { .. } |
+            BinOp::MulAssign { .. } | BinOp::DivAssign { .. } | BinOp::ModAssign { .. } => ASSIGN,
+            BinOp::LogOr { .. } => LOG_OR,
+            BinOp::LogAnd { .. } => LOG_AND,
+            BinOp::Equal { .. } | BinOp::NotEqual { .. } => EQUALITY,
+            BinOp::Bigger { .. } | BinOp::Lesser { .. } | BinOp::BiggerEqual { .. } | BinOp::LesserEqual { .. } => RELATIONAL,
+            BinOp::Add { .. } | BinOp::Sub { .. } => ADDITIVE,
+            BinOp::Mul { .. } | BinOp::Div { .. } | BinOp::Mod { .. } => MULTIPLICATIVE,
+        },
+        Expr::UnOp { o } => match o {
+            UnOp::PostInc { .. } | UnOp::PostDec { .. } | UnOp::Subscript { .. } | UnOp::FuncCall { .. } |
+            UnOp::Member { .. } | UnOp::PtrMember { .. } |
+            UnOp::VaStart { .. } | UnOp::VaArg { .. } | UnOp::VaEnd { .. } | UnOp::VaCopy { .. } => POSTFIX,
+            _ => UNARY,
+        },
+        Expr::CompoundLit { .. } => POSTFIX,
+        _ => PRIMARY,
+    }
+}
+
+fn symbol(o: &BinOp) -> &'static str {
+    match o {
+        BinOp::Comma { .. } => ",",
+        BinOp::Assign { .. } => "=",
+        BinOp::AddAssign { .. } => "+=",
+        BinOp::SubAssign { .. } => "-=",
+        BinOp::MulAssign { .. } => "*=",
+        BinOp::DivAssign { .. } => "/=",
+        BinOp::ModAssign { .. } => "%=",
+        BinOp::LogOr { .. } => "||",
+        BinOp::LogAnd { .. } => "&&",
+        BinOp::Equal { .. } => "==",
+        BinOp::NotEqual { .. } => "!=",
+        BinOp::Bigger { .. } => ">",
+        BinOp::Lesser { .. } => "<",
+        BinOp::BiggerEqual { .. } => ">=",
+        BinOp::LesserEqual { .. } => "<=",
+        BinOp::Add { .. } => "+",
+        BinOp::Sub { .. } => "-",
+        BinOp::Mul { .. } => "*",
+        BinOp::Div { .. } => "/",
+        BinOp::Mod { .. } => "%",
+    }
+}
+
+// A character as written inside a literal delimited by `quote`
+fn escape(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c == quote => format!("\\{}", c),
+        c => c.to_string(),
+    }
+}
+
+fn type_quals(t: &Type) -> Quals {
+    match t {
+        Type::Qualified { t: _, q } => q.clone(),
+        _ => Quals::default(),
+    }
+}
+
+fn quals(q: &Quals) -> String {
+    let mut v = Vec::new();
+    if q.is_const {
+        v.push("const");
+    }
+    if q.is_volatile {
+        v.push("volatile");
+    }
+    if q.is_restrict {
+        v.push("restrict");
+    }
+    v.join(" ")
+}
+
+pub fn storage(spec: &DeclSpec) -> String {
+    let mut s = match spec.storage {
+        Some(Storage::Typedef) => "typedef ",
+        Some(Storage::Extern) => "extern ",
+        Some(Storage::Static) => "static ",
+        Some(Storage::Auto) => "auto ",
+        Some(Storage::Register) => "register ",
+        None => "",
+    }.to_string();
+    if spec.thread_local {
+        s.push_str("_Thread_local ");
+    }
+    if spec.inline {
+        s.push_str("inline ");
+    }
+    s
+}
+
+// The type left once every pointer, array and function derivation is taken off,
+// which is what goes into the declaration specifiers
+fn base(t: &Type) -> Type {
+    match t {
+        Type::Ptr { t } | Type::Array { t, n: _ } | Type::Function { ret: t, .. } => base(t),
+        Type::Qualified { t: inner, q } => match &**inner {
+            Type::Ptr { t } | Type::Function { ret: t, .. } => base(t),
+            // qualifiers of an array type belong to its elements
+            Type::Array { t, n: _ } => base(&(**t).clone().qualify(q.clone())),
+            _ => t.clone(),
+        },
+        t => t.clone(),
+    }
+}
+
+// The type left once the derivations of a declarator are taken off up to `td`, the type of the typedef name
+// the declaration was written with. None if they don't lead to it, as after a `Fold` changed the type.
+fn typedef_base(t: &Type, td: &Type) -> Option<Type> {
+    // qualifiers written before the name add to the ones of its type
+    if t.unqualified() == td.unqualified() {
+        return Some(t.clone());
+    }
+    match t {
+        Type::Ptr { t } | Type::Array { t, n: _ } | Type::Function { ret: t, .. } => typedef_base(t, td),
+        Type::Qualified { t: inner, q } => match &**inner {
+            Type::Ptr { t } => typedef_base(t, td),
+            Type::Array { t, n: _ } => typedef_base(&(**t).clone().qualify(q.clone()), td),
+            t @ Type::Function { .. } => typedef_base(t, td),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn anonymous(t: &Type) -> bool {
+    matches!(t.unqualified(), Type::Struct { tag: None, .. } | Type::Union { tag: None, .. } | Type::Enum { tag: None, .. })
+}
+
+// Number of declarations from the start of `ids` to print as one. Declarators sharing an anonymous
+// record or enum can't be split, there would be no way to name the type in the second declaration.
+fn group_len(ast: &Ast, ids: &[DeclId]) -> usize {
+    let key = |id: &DeclId| match &ast[*id] {
+        Decl::Var { t, id: _, val: _, spec } | Decl::Func { t, id: _, args: _, block: None, spec } |
+        Decl::Typedef { t, id: _, spec } => Some((base(t), spec.clone())),
+        _ => None,
+    };
+    match key(&ids[0]) {
+        Some(k) if anonymous(&k.0) => 1 + ids[1..].iter().take_while(|id| key(id).as_ref() == Some(&k)).count(),
+        _ => 1,
+    }
+}
+
+// Same for struct members
+fn member_group_len(members: &[Member]) -> usize {
+    match &members[0] {
+        Member { t, id: Some(_) } if anonymous(&base(t)) => {
+            let k = base(t);
+            1 + members[1..].iter().take_while(|m| m.id.is_some() && base(&m.t) == k).count()
+        },
+        _ => 1,
+    }
+}
+
+// Whether a statement ends in an `if` without an else, which would take an else following it
+fn dangles(ast: &Ast, id: StmtId) -> bool {
+    match &ast[id] {
+        Stmt::If { cond: _, block: _, next: None } => true,
+        Stmt::If { cond: _, block: _, next: Some(s) } |
+        Stmt::For { var: _, cond: _, e: _, block: s } |
+        Stmt::While { cond: _, block: s } |
+        Stmt::Switch { cond: _, block: s, cases: _, default: _ } |
+        Stmt::Case { e: _, block: s } |
+        Stmt::Default { block: s } |
+        Stmt::Label { id: _, block: s } => dangles(ast, *s),
+        _ => false,
+    }
+}
+
+struct Printer<'a> {
+    ast: &'a Ast,
+    out: String,
+    indent: usize,
+    // whether the body of each record and enum has been printed, indexed like `Ast::records` and `Ast::enums`
+    records: Vec<bool>,
+    enums: Vec<bool>,
+    // typedef names of anonymous records and enums
+    record_names: HashMap<usize, String>,
+    enum_names: HashMap<usize, String>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(ast: &'a Ast) -> Printer<'a> {
+        Printer {
+            ast,
+            out: String::new(),
+            indent: 0,
+            records: vec![false; ast.records.len()],
+            enums: vec![false; ast.enums.len()],
+            record_names: HashMap::new(),
+            enum_names: HashMap::new(),
+        }
+    }
+
+    // Starts a line at the current indentation
+    fn open(&mut self, s: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(s);
+    }
+
+    fn line(&mut self, s: &str) {
+        self.open(s);
+        self.out.push('\n');
+    }
+
+    // Case and goto labels stand one level left of the statements they label
+    fn label(&mut self, s: &str) {
+        let indent = self.indent;
+        self.indent = indent.saturating_sub(1);
+        self.line(s);
+        self.indent = indent;
+    }
+
+    fn decls(&mut self, ids: &[DeclId]) {
+        let ast = self.ast;
+        let head = self.decl_group(ids);
+        match &ast[ids[0]] {
+            Decl::Func { block: Some(block), .. } => {
+                self.open(&head);
+                let braced = self.body(*block, false);
+                self.close(braced);
+            },
+            _ => self.line(&format!("{};", head)),
+        }
+    }
+
+    // One declaration without the semicolon: the specifiers of the first of `ids` followed by all their declarators
+    fn decl_group(&mut self, ids: &[DeclId]) -> String {
+        let ast = self.ast;
+        let (t, spec) = match &ast[ids[0]] {
+            Decl::Func { t, id: _, args: _, block: _, spec } |
+            Decl::Var { t, id: _, val: _, spec } |
+            Decl::Arg { t, id: _, spec } |
+            Decl::Typedef { t, id: _, spec } => (t, spec),
+            Decl::Tag { t } => return self.specs(t),
+        };
+        let mut s = storage(spec);
+        // the typedef name the declaration was written with, if the declarators still lead to its type
+        let typedef = spec.typedef.as_ref().and_then(|(name, td)| Some((name, typedef_base(t, td)?, td)));
+        match &typedef {
+            Some((name, b, td)) => {
+                let (have, written) = (type_quals(b), type_quals(td));
+                let extra = Quals {
+                    is_const: have.is_const && !written.is_const,
+                    is_volatile: have.is_volatile && !written.is_volatile,
+                    is_restrict: have.is_restrict && !written.is_restrict,
+                };
+                match quals(&extra) {
+                    q if q.is_empty() => s.push_str(name),
+                    q => s.push_str(&format!("{} {}", q, name)),
+                }
+            },
+            None => s.push_str(&self.specs(&base(t))),
+        }
+        let td = typedef.map(|(_, _, td)| td);
+
+        for (i, id) in ids.iter().enumerate() {
+            let d = match &ast[*id] {
+                Decl::Func { t, id, args, block: _, spec: _ } => self.declarator(t, id, Some(args), td),
+                Decl::Var { t, id, val, spec: _ } => {
+                    let mut d = self.declarator(t, id, None, td);
+                    if let Some(val) = val {
+                        d.push_str(" = ");
+                        d.push_str(&self.init(val));
+                    }
+                    d
+                },
+                Decl::Arg { t, id, spec: _ } => self.declarator(t, id.as_deref().unwrap_or(""), None, td),
+                Decl::Typedef { t, id, spec: _ } => {
+                    match t {
+                        Type::Struct { tag: None, id: r } | Type::Union { tag: None, id: r } => { self.record_names.entry(*r).or_insert(id.to_string()); },
+                        Type::Enum { tag: None, id: e } => { self.enum_names.entry(*e).or_insert(id.to_string()); },
+                        _ => {},
+                    }
+                    self.declarator(t, id, None, td)
+                },
+                Decl::Tag { .. } => unreachable!(),
+            };
+            match i {
+                0 if d.is_empty() => {},
+                0 => s.push(' '),
+                _ => s.push_str(", "),
+            }
+            s.push_str(&d);
+        }
+        s
+    }
+
+    // A type with an optional name, as in members, parameters of function types and type names
+    fn declaration(&mut self, t: &Type, name: &str) -> String {
+        let specs = self.specs(&base(t));
+        match self.declarator(t, name, None, None) {
+            d if d.is_empty() || d.starts_with('[') => specs + &d,
+            d => format!("{} {}", specs, d),
+        }
+    }
+
+    fn type_name(&mut self, t: &Type) -> String {
+        self.declaration(t, "")
+    }
+
+    // Specifiers for a base type, with the body of a record or enum at its first mention
+    fn specs(&mut self, t: &Type) -> String {
+        match t {
+            Type::Qualified { t, q } => {
+                let q = quals(q);
+                format!("{} {}", q, self.specs(t))
+            },
+            Type::Struct { tag: _, id } | Type::Union { tag: _, id } => self.record(*id),
+            Type::Enum { tag: _, id } => self.enumeration(*id),
+            Type::Void => "void".to_string(),
+            Type::Bool => "_Bool".to_string(),
+            Type::Char => "char".to_string(),
+            Type::SChar => "signed char".to_string(),
+            Type::UChar => "unsigned char".to_string(),
+            Type::Short => "short".to_string(),
+            Type::UShort => "unsigned short".to_string(),
+            Type::Int => "int".to_string(),
+            Type::UInt => "unsigned int".to_string(),
+            Type::Long => "long".to_string(),
+            Type::ULong => "unsigned long".to_string(),
+            Type::LongLong => "long long".to_string(),
+            Type::ULongLong => "unsigned long long".to_string(),
+            Type::Float => "float".to_string(),
+            Type::Double => "double".to_string(),
+            Type::LongDouble => "long double".to_string(),
+            Type::VaList => "va_list".to_string(),
+            Type::Ptr { .. } | Type::Array { .. } | Type::Function { .. } => unreachable!("derived type {:?} in specifiers", t),
+        }
+    }
+
+    fn record(&mut self, id: usize) -> String {
+        let ast = self.ast;
+        let r = &ast.records[id];
+        let kw = if r.union { "union" } else { "struct" };
+        let members = match (&r.tag, &r.members) {
+            (Some(tag), None) => return format!("{} {}", kw, tag),
+            (Some(tag), Some(_)) if self.records[id] => return format!("{} {}", kw, tag),
+            (None, _) if self.records[id] && self.record_names.contains_key(&id) => return self.record_names[&id].clone(),
+            (_, members) => members.as_deref().unwrap_or_default(),
+        };
+        self.records[id] = true;
+
+        let mut s = match &r.tag {
+            Some(tag) => format!("{} {} {{\n", kw, tag),
+            None => format!("{} {{\n", kw),
+        };
+        self.indent += 1;
+        let mut members = members;
+        while !members.is_empty() {
+            let n = member_group_len(members);
+            let m = match &members[0].id {
+                None => self.specs(&members[0].t),
+                Some(_) => {
+                    let mut m = self.specs(&base(&members[0].t));
+                    for (i, member) in members[..n].iter().enumerate() {
+                        m.push_str(if i == 0 { " " } else { ", " });
+                        m.push_str(&self.declarator(&member.t, member.id.as_deref().unwrap_or(""), None, None));
+                    }
+                    m
+                },
+            };
+            s.push_str(&"    ".repeat(self.indent));
+            s.push_str(&m);
+            s.push_str(";\n");
+            members = &members[n..];
+        }
+        self.indent -= 1;
+        s.push_str(&"    ".repeat(self.indent));
+        s.push('}');
+        s
+    }
+
+    // Enumerators are printed with their values, which are only written out where they don't follow from the previous one
+    fn enumeration(&mut self, id: usize) -> String {
+        let ast = self.ast;
+        let e = &ast.enums[id];
+        let consts = match (&e.tag, &e.consts) {
+            (Some(tag), None) => return format!("enum {}", tag),
+            (Some(tag), Some(_)) if self.enums[id] => return format!("enum {}", tag),
+            (None, _) if self.enums[id] && self.enum_names.contains_key(&id) => return self.enum_names[&id].clone(),
+            (_, consts) => consts.as_deref().unwrap_or_default(),
+        };
+        self.enums[id] = true;
+
+        let mut next = 0;
+        let consts: Vec<String> = consts.iter().map(|c| {
+            let s = match c.val == next {
+                true => c.id.to_string(),
+                false => format!("{} = {}", c.id, c.val),
+            };
+            next = c.val + 1;
+            s
+        }).collect();
+        match &e.tag {
+            Some(tag) => format!("enum {} {{ {} }}", tag, consts.join(", ")),
+            None => format!("enum {{ {} }}", consts.join(", ")),
+        }
+    }
+
+    // The declarator of `t` around `name`, which is empty in a type name. Derivations are taken off
+    // from the one bound to the name outwards, which is also the order they appear in the source.
+    // `args` are the named parameters of a function declaration, and the declarator stops at `typedef`,
+    // the type of the typedef name in the specifiers.
+    fn declarator(&mut self, t: &Type, name: &str, mut args: Option<&[DeclId]>, typedef: Option<&Type>) -> String {
+        // a pointer to an array or function needs parentheses, `*p[3]` would be an array of pointers
+        fn ptr(d: String, pointee: &Type) -> String {
+            match pointee.unqualified() {
+                Type::Array { .. } | Type::Function { .. } => format!("({})", d),
+                _ => d,
+            }
+        }
+
+        let mut d = name.to_string();
+        let mut t = t.clone();
+        loop {
+            if typedef.is_some_and(|td| t.unqualified() == td.unqualified()) {
+                return d;
+            }
+            t = match t {
+                Type::Ptr { t } => {
+                    d = ptr(format!("*{}", d), &t);
+                    *t
+                },
+                Type::Qualified { t: inner, q } => match *inner {
+                    Type::Ptr { t } => {
+                        d = match d.is_empty() {
+                            true => format!("*{}", quals(&q)),
+                            false => format!("*{} {}", quals(&q), d),
+                        };
+                        d = ptr(d, &t);
+                        *t
+                    },
+                    Type::Array { t, n } => Type::Array { t: Box::new(t.qualify(q)), n },
+                    t @ Type::Function { .. } => t,
+                    _ => return d,
+                },
+                Type::Array { t, n } => {
+                    let n = n.map_or(String::new(), |n| self.expr(n, ASSIGN));
+                    d = format!("{}[{}]", d, n);
+                    *t
+                },
+                Type::Function { ret, params, variadic, prototype } => {
+                    let mut v: Vec<String> = match args.take() {
+                        Some(args) => args.iter().map(|arg| self.decl_group(&[*arg])).collect(),
+                        None => params.iter().map(|t| self.type_name(t)).collect(),
+                    };
+                    match variadic {
+                        true => v.push("...".to_string()),
+                        false if v.is_empty() && prototype => v.push("void".to_string()),
+                        false => {},
+                    }
+                    d = format!("{}({})", d, v.join(", "));
+                    *ret
+                },
+                _ => return d,
+            };
+        }
+    }
+
+    fn init(&mut self, init: &Init) -> String {
+        match init {
+            Init::Expr { e } => self.expr(*e, ASSIGN),
+            Init::List { v } => self.init_list(v),
+        }
+    }
+
+    fn init_list(&mut self, v: &[InitItem]) -> String {
+        let mut items = Vec::new();
+        for item in v {
+            let mut s = String::new();
+            for d in &item.d {
+                match d {
+                    Designator::Member { id } => { s.push('.'); s.push_str(id); },
+                    Designator::Index { e, n: _ } => { let e = self.expr(*e, LOG_OR); s.push_str(&format!("[{}]", e)); },
+                }
+            }
+            if !item.d.is_empty() {
+                s.push_str(" = ");
+            }
+            s.push_str(&self.init(&item.init));
+            items.push(s);
+        }
+        format!("{{{}}}", items.join(", "))
+    }
+
+    // An expression in a context that accepts precedence `min` or tighter without parentheses
+    fn expr(&mut self, id: ExprId, min: u8) -> String {
+        let ast = self.ast;
+        // implicit conversions aren't written in C
+        if let Expr::ImplicitCast { kind: _, t: _, e } = &ast[id] {
+            return self.expr(*e, min);
+        }
+        let p = prec(&ast[id]);
+        let s = match &ast[id] {
+            Expr::Id { s } | Expr::EnumN { s, num: _ } => s.to_string(),
+            Expr::IntN { num } => num.to_string(),
+            Expr::FloatN { num } => match num.to_string() {
+                s if s.contains('.') => s,
+                s => s + ".0",
+            },
+            Expr::CharN { num } => format!("'{}'", escape(*num as char, '\'')),
+            Expr::StrN { s, n: _ } => format!("\"{}\"", s.chars().map(|c| escape(c, '"')).collect::<String>()),
+            Expr::CompoundLit { t, v } => {
+                let t = self.type_name(t);
+                format!("({}){}", t, self.init_list(v))
+            },
+            Expr::BinOp { o } => {
+                let (le, re) = o.operands();
+                // assignments group to the right, everything else to the left
+                let (lp, rp) = match p {
+                    COMMA => (COMMA, ASSIGN),
+                    ASSIGN => (LOG_OR, ASSIGN),
+                    p => (p, p + 1),
+                };
+                let le = self.expr(le, lp);
+                let re = self.expr(re, rp);
+                match o {
+                    BinOp::Comma { .. } => format!("{}, {}", le, re),
+                    o => format!("{} {} {}", le, symbol(o), re),
+                }
+            },
+            Expr::UnOp { o } => self.unop(o),
+            Expr::ImplicitCast { .. } => unreachable!(),
+        };
+
+        match p < min {
+            true => format!("({})", s),
+            false => s,
+        }
+    }
+
+    fn unop(&mut self, o: &UnOp) -> String {
+        let ast = self.ast;
+        match o {
+            UnOp::UnPlus { e } => self.prefix("+", *e),
+            UnOp::UnMinus { e } => self.prefix("-", *e),
+            UnOp::PreInc { e } => self.prefix("++", *e),
+            UnOp::PreDec { e } => self.prefix("--", *e),
+            UnOp::LogNot { e } => self.prefix("!", *e),
+            UnOp::Indirection { e } => self.prefix("*", *e),
+            UnOp::AddressOf { e } => self.prefix("&", *e),
+
+            UnOp::PostInc { e } => format!("{}++", self.expr(*e, POSTFIX)),
+            UnOp::PostDec { e } => format!("{}--", self.expr(*e, POSTFIX)),
+            UnOp::Subscript { le, re } => {
+                let le = self.expr(*le, POSTFIX);
+                format!("{}[{}]", le, self.expr(*re, COMMA))
+            },
+            UnOp::FuncCall { e, args } => {
+                let e = self.expr(*e, POSTFIX);
+                let args: Vec<String> = args.iter().map(|arg| self.expr(*arg, ASSIGN)).collect();
+                format!("{}({})", e, args.join(", "))
+            },
+            UnOp::Member { e, id } => format!("{}.{}", self.expr(*e, POSTFIX), id),
+            UnOp::PtrMember { e, id } => format!("{}->{}", self.expr(*e, POSTFIX), id),
+
+            UnOp::TypeCast { t, e } => {
+                let t = self.type_name(t);
+                format!("({}){}", t, self.expr(*e, UNARY))
+            },
+            // `sizeof (int)x` would read as the size of a type name followed by `x`
+            UnOp::SizeOf { e } if matches!(&ast[*e], Expr::UnOp { o: UnOp::TypeCast { .. } }) => format!("sizeof ({})", self.expr(*e, COMMA)),
+            UnOp::SizeOf { e } => format!("sizeof {}", self.expr(*e, UNARY)),
+            UnOp::SizeOfType { t } => format!("sizeof({})", self.type_name(t)),
+            UnOp::AlignOf { t } => format!("_Alignof({})", self.type_name(t)),
+
+            UnOp::VaStart { e, last } => {
+                let e = self.expr(*e, ASSIGN);
+                format!("va_start({}, {})", e, self.expr(*last, ASSIGN))
+            },
+            UnOp::VaArg { e, t } => {
+                let e = self.expr(*e, ASSIGN);
+                format!("va_arg({}, {})", e, self.type_name(t))
+            },
+            UnOp::VaEnd { e } => format!("va_end({})", self.expr(*e, ASSIGN)),
+            UnOp::VaCopy { le, re } => {
+                let le = self.expr(*le, ASSIGN);
+                format!("va_copy({}, {})", le, self.expr(*re, ASSIGN))
+            },
+        }
+    }
+
+    // `- -x` and `& &x` keep the space, `--x` and `&&x` would lex as other operators
+    fn prefix(&mut self, op: &str, e: ExprId) -> String {
+        let e = self.expr(e, UNARY);
+        let last = op.chars().last().unwrap();
+        match "+-&".contains(last) && e.starts_with(last) {
+            true => format!("{} {}", op, e),
+            false => format!("{}{}", op, e),
+        }
+    }
+
+    fn stmt(&mut self, id: StmtId) {
+        let ast = self.ast;
+        match &ast[id] {
+            Stmt::Block { v } => {
+                self.line("{");
+                self.indent += 1;
+                self.items(v);
+                self.indent -= 1;
+                self.line("}");
+            },
+            Stmt::Decl { d } => self.decls(&[*d]),
+            Stmt::Expr { e } => {
+                let e = self.expr(*e, COMMA);
+                self.line(&format!("{};", e));
+            },
+
+            Stmt::If { cond, block, next } => {
+                let cond = self.expr(*cond, COMMA);
+                self.open(&format!("if ({})", cond));
+                let mut braced = self.body(*block, next.is_some());
+                let mut next = *next;
+                while let Some(n) = next.take() {
+                    match braced {
+                        true => self.out.push_str(" else"),
+                        false => self.open("else"),
+                    }
+                    braced = match &ast[n] {
+                        Stmt::If { cond, block, next: after } => {
+                            let cond = self.expr(*cond, COMMA);
+                            self.out.push_str(&format!(" if ({})", cond));
+                            next = *after;
+                            self.body(*block, after.is_some())
+                        },
+                        _ => self.body(n, false),
+                    };
+                }
+                self.close(braced);
+            },
+            Stmt::For { var, cond, e, block } => {
+                let var = match var {
+                    Some(ForInit::Decl { v }) => self.decl_group(v),
+                    Some(ForInit::Expr { e }) => self.expr(*e, COMMA),
+                    None => String::new(),
+                };
+                let cond = cond.map_or(String::new(), |e| format!(" {}", self.expr(e, COMMA)));
+                let e = e.map_or(String::new(), |e| format!(" {}", self.expr(e, COMMA)));
+                self.open(&format!("for ({};{};{})", var, cond, e));
+                let braced = self.body(*block, false);
+                self.close(braced);
+            },
+            Stmt::While { cond, block } => {
+                let cond = self.expr(*cond, COMMA);
+                self.open(&format!("while ({})", cond));
+                let braced = self.body(*block, false);
+                self.close(braced);
+            },
+
+            Stmt::Switch { cond, block, cases: _, default: _ } => {
+                let cond = self.expr(*cond, COMMA);
+                self.open(&format!("switch ({})", cond));
+                let braced = self.body(*block, false);
+                self.close(braced);
+            },
+            Stmt::Case { e, block } => {
+                let e = self.expr(*e, LOG_OR);
+                self.label(&format!("case {}:", e));
+                self.stmt(*block);
+            },
+            Stmt::Default { block } => {
+                self.label("default:");
+                self.stmt(*block);
+            },
+
+            Stmt::Label { id, block } => {
+                self.label(&format!("{}:", id));
+                self.stmt(*block);
+            },
+            Stmt::Goto { id } => self.line(&format!("goto {};", id)),
+
+            Stmt::Break => self.line("break;"),
+            Stmt::Continue => self.line("continue;"),
+            Stmt::Return { e: None } => self.line("return;"),
+            Stmt::Return { e: Some(e) } => {
+                let e = self.expr(*e, COMMA);
+                self.line(&format!("return {};", e));
+            },
+
+            Stmt::Empty => self.line(";"),
+        }
+    }
+
+    // Statements of a block, with runs of declarations grouped as `group_len` requires
+    fn items(&mut self, v: &[StmtId]) {
+        let ast = self.ast;
+        let mut i = 0;
+        while i < v.len() {
+            let run: Vec<DeclId> = v[i..].iter().map_while(|s| match &ast[*s] {
+                Stmt::Decl { d } => Some(*d),
+                _ => None,
+            }).collect();
+            if run.is_empty() {
+                self.stmt(v[i]);
+                i += 1;
+                continue;
+            }
+            let mut decls = &run[..];
+            while !decls.is_empty() {
+                let n = group_len(ast, decls);
+                self.decls(&decls[..n]);
+                decls = &decls[n..];
+            }
+            i += run.len();
+        }
+    }
+
+    // The statement under an `if`, `for`, `while` or `switch` whose head is already on the current line.
+    // A block opens on the same line and its closing brace is left unterminated, so `else` can follow it.
+    // Returns whether the body ended in such a brace.
+    fn body(&mut self, id: StmtId, before_else: bool) -> bool {
+        let ast = self.ast;
+        match &ast[id] {
+            Stmt::Block { v } => {
+                self.out.push_str(" {\n");
+                self.indent += 1;
+                self.items(v);
+                self.indent -= 1;
+            },
+            // only an AST built by hand can get here, the parser gives the else to the inner if
+            _ if before_else && dangles(ast, id) => {
+                self.out.push_str(" {\n");
+                self.indent += 1;
+                self.stmt(id);
+                self.indent -= 1;
+            },
+            _ => {
+                self.out.push('\n');
+                self.indent += 1;
+                self.stmt(id);
+                self.indent -= 1;
+                return false;
+            },
+        }
+        self.open("}");
+        true
+    }
+
+    fn close(&mut self, braced: bool) {
+        if braced {
+            self.out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::print;
+    use crate::lex::lexer::lex;
+    use crate::parse::parser::*;
+
+    // Debug output with every id replaced by the node it refers to,
+    // so trees compare equal no matter in which order their nodes were allocated
+    fn expand(ast: &Ast, s: &str) -> String {
+        let mut out = String::new();
+        let mut rest = s;
+        while let Some((i, kind)) = ["ExprId(", "StmtId(", "DeclId("].iter().filter_map(|k| rest.find(k).map(|i| (i, *k))).min() {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + kind.len()..];
+            let end = rest.find(')').unwrap();
+            let n: usize = rest[..end].parse().unwrap();
+            rest = &rest[end + 1..];
+            let node = match kind {
+                "ExprId(" => format!("{:?}", ast.exprs[n]),
+                "StmtId(" => format!("{:?}", ast.stmts[n]),
+                _ => format!("{:?}", ast.decls[n]),
+            };
+            out.push_str(&expand(ast, &node));
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn tree(tu: &TranslationUnit) -> String {
+        let ast = &tu.ast;
+        format!("{}\n{}\n{:?}", expand(ast, &format!("{:?}", tu.items)), expand(ast, &format!("{:?}", ast.records)), ast.enums)
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+        let mut paths: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            let src = fs::read_to_string(&path).unwrap();
+            let tu = parse(&lex(&src).unwrap()).unwrap();
+            let printed = print(&tu);
+            let again = parse(&lex(&printed).unwrap()).unwrap();
+            assert_eq!(tree(&tu), tree(&again), "{} printed as\n{}", path.display(), printed);
+            assert_eq!(print(&again), printed, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        let src = "int f() { x = (a + b) * c + (d * e) - (f - g); y = a = (b = c); (a = b) = c; z = -(-x) + - --x + (int)(y); w = sizeof((int)x) + sizeof(x) + (*p)[0]; }";
+        let expected = "\
+int f() {
+    x = (a + b) * c + d * e - (f - g);
+    y = a = b = c;
+    (a = b) = c;
+    z = - -x + - --x + (int)y;
+    w = sizeof ((int)x) + sizeof x + (*p)[0];
+}
+";
+        assert_eq!(print(&parse(&lex(src).unwrap()).unwrap()), expected);
+    }
+
+    #[test]
+    fn typedef_names() {
+        let src = "typedef struct S T; T x; typedef int *P; const P cp, *pp[2]; typedef const int CI; volatile CI v; \
+            T *f(T a, P); typedef T U; typedef int A[3]; A arr[2]; int g(void) { U u; return sizeof(T); }";
+        let expected = "\
+typedef struct S T;
+T x;
+typedef int *P;
+const P cp;
+const P *pp[2];
+typedef const int CI;
+volatile CI v;
+T *f(T a, P);
+typedef T U;
+typedef int A[3];
+A arr[2];
+
+int g(void) {
+    U u;
+    return sizeof(struct S);
+}
+";
+        assert_eq!(print(&parse(&lex(src).unwrap()).unwrap()), expected);
+    }
+}
@@ -0,0 +1,447 @@
+//! Traits for walking a tree and for rewriting it node by node.
+
+use crate::parse::parser::*;
+
+/// Read-only traversal. Every method walks into the children by default, so an analysis
+/// only overrides the node kinds it cares about and calls the matching `walk_*` to keep descending.
+///
+/// Types are values, so an array size behind a typedef is reached from every declaration using it.
+/// Struct and union members live in `Ast::records` and aren't visited through the types referring to them.
+pub trait Visitor {
+    fn visit_translation_unit(&mut self, tu: &TranslationUnit) {
+        walk_translation_unit(self, tu)
+    }
+
+    fn visit_decl(&mut self, ast: &Ast, id: DeclId) {
+        walk_decl(self, ast, id)
+    }
+
+    fn visit_stmt(&mut self, ast: &Ast, id: StmtId) {
+        walk_stmt(self, ast, id)
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        walk_expr(self, ast, id)
+    }
+
+    fn visit_binop(&mut self, ast: &Ast, o: &BinOp) {
+        walk_binop(self, ast, o)
+    }
+
+    fn visit_unop(&mut self, ast: &Ast, o: &UnOp) {
+        walk_unop(self, ast, o)
+    }
+
+    fn visit_init(&mut self, ast: &Ast, init: &Init) {
+        walk_init(self, ast, init)
+    }
+
+    fn visit_type(&mut self, ast: &Ast, t: &Type) {
+        walk_type(self, ast, t)
+    }
+}
+
+pub fn walk_translation_unit<V: Visitor + ?Sized>(v: &mut V, tu: &TranslationUnit) {
+    for id in &tu.items {
+        v.visit_decl(&tu.ast, *id);
+    }
+}
+
+pub fn walk_decl<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, id: DeclId) {
+    match &ast[id] {
+        Decl::Func { t, id: _, args, block, spec: _ } => {
+            // parameter types are visited through `args`
+            if let Type::Function { ret, .. } = t {
+                v.visit_type(ast, ret);
+            }
+            for arg in args {
+                v.visit_decl(ast, *arg);
+            }
+            if let Some(block) = block {
+                v.visit_stmt(ast, *block);
+            }
+        },
+        Decl::Arg { t, id: _, spec: _ } => v.visit_type(ast, t),
+        Decl::Var { t, id: _, val, spec: _ } => {
+            v.visit_type(ast, t);
+            if let Some(val) = val {
+                v.visit_init(ast, val);
+            }
+        },
+        Decl::Tag { t } | Decl::Typedef { t, .. } => v.visit_type(ast, t),
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, id: StmtId) {
+    match &ast[id] {
+        Stmt::Block { v: block } => {
+            for s in block {
+                v.visit_stmt(ast, *s);
+            }
+        },
+        Stmt::Decl { d } => v.visit_decl(ast, *d),
+        Stmt::Expr { e } => v.visit_expr(ast, *e),
+        Stmt::If { cond, block, next } => {
+            v.visit_expr(ast, *cond);
+            v.visit_stmt(ast, *block);
+            if let Some(next) = next {
+                v.visit_stmt(ast, *next);
+            }
+        },
+        Stmt::For { var, cond, e, block } => {
+            match var {
+                Some(ForInit::Decl { v: decls }) => {
+                    for d in decls {
+                        v.visit_decl(ast, *d);
+                    }
+                },
+                Some(ForInit::Expr { e }) => v.visit_expr(ast, *e),
+                None => {},
+            }
+            if let Some(cond) = cond {
+                v.visit_expr(ast, *cond);
+            }
+            if let Some(e) = e {
+                v.visit_expr(ast, *e);
+            }
+            v.visit_stmt(ast, *block);
+        },
+        Stmt::While { cond, block } | Stmt::Switch { cond, block, cases: _, default: _ } | Stmt::Case { e: cond, block } => {
+            v.visit_expr(ast, *cond);
+            v.visit_stmt(ast, *block);
+        },
+        Stmt::Default { block } | Stmt::Label { id: _, block } => v.visit_stmt(ast, *block),
+        Stmt::Return { e: Some(e) } => v.visit_expr(ast, *e),
+        Stmt::Return { e: None } | Stmt::Goto { .. } | Stmt::Break | Stmt::Continue | Stmt::Empty => {},
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, id: ExprId) {
+    match &ast[id] {
+        Expr::CompoundLit { t, v: items } => {
+            v.visit_type(ast, t);
+            walk_items(v, ast, items);
+        },
+        Expr::BinOp { o } => v.visit_binop(ast, o),
+        Expr::UnOp { o } => v.visit_unop(ast, o),
+        // the type is the operand's converted, nothing in it is new
+        Expr::ImplicitCast { kind: _, t: _, e } => v.visit_expr(ast, *e),
+        // the size of a string literal is a node of its own, like an array size in a type
+        Expr::StrN { s: _, n } => v.visit_expr(ast, *n),
+        Expr::Id { .. } | Expr::IntN { .. } | Expr::FloatN { .. } | Expr::CharN { .. } | Expr::EnumN { .. } => {},
+    }
+}
+
+pub fn walk_binop<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, o: &BinOp) {
+    let (le, re) = o.operands();
+    v.visit_expr(ast, le);
+    v.visit_expr(ast, re);
+}
+
+pub fn walk_unop<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, o: &UnOp) {
+    match o {
+        UnOp::UnPlus { e } | UnOp::UnMinus { e } | UnOp::LogNot { e } |
+        UnOp::PreInc { e } | UnOp::PostInc { e } | UnOp::PreDec { e } | UnOp::PostDec { e } |
+        UnOp::Indirection { e } | UnOp::AddressOf { e } |
+        UnOp::Member { e, id: _ } | UnOp::PtrMember { e, id: _ } |
+        UnOp::SizeOf { e } | UnOp::VaEnd { e } => v.visit_expr(ast, *e),
+        UnOp::Subscript { le, re } | UnOp::VaCopy { le, re } | UnOp::VaStart { e: le, last: re } => {
+            v.visit_expr(ast, *le);
+            v.visit_expr(ast, *re);
+        },
+        UnOp::FuncCall { e, args } => {
+            v.visit_expr(ast, *e);
+            for arg in args {
+                v.visit_expr(ast, *arg);
+            }
+        },
+        UnOp::TypeCast { t, e } | UnOp::VaArg { e, t } => {
+            v.visit_type(ast, t);
+            v.visit_expr(ast, *e);
+        },
+        UnOp::SizeOfType { t } | UnOp::AlignOf { t } => v.visit_type(ast, t),
+    }
+}
+
+pub fn walk_init<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, init: &Init) {
+    match init {
+        Init::Expr { e } => v.visit_expr(ast, *e),
+        Init::List { v: items } => walk_items(v, ast, items),
+    }
+}
+
+fn walk_items<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, items: &[InitItem]) {
+    for item in items {
+        for d in &item.d {
+            if let Designator::Index { e, n: _ } = d {
+                v.visit_expr(ast, *e);
+            }
+        }
+        v.visit_init(ast, &item.init);
+    }
+}
+
+pub fn walk_type<V: Visitor + ?Sized>(v: &mut V, ast: &Ast, t: &Type) {
+    match t {
+        Type::Ptr { t } | Type::Qualified { t, q: _ } => v.visit_type(ast, t),
+        Type::Array { t, n } => {
+            v.visit_type(ast, t);
+            if let Some(n) = n {
+                v.visit_expr(ast, *n);
+            }
+        },
+        Type::Function { ret, params, variadic: _, prototype: _ } => {
+            v.visit_type(ast, ret);
+            for param in params {
+                v.visit_type(ast, param);
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Rewriting traversal. Each method returns the id the parent should refer to from now on:
+/// the default walks fold the children and write the node back under its own id,
+/// an override can instead allocate a replacement and return its id.
+///
+/// While its children are folded a node is taken out of the arena and a placeholder stands in its slot.
+pub trait Fold {
+    fn fold_translation_unit(&mut self, tu: &mut TranslationUnit) {
+        fold_walk_translation_unit(self, tu)
+    }
+
+    fn fold_decl(&mut self, ast: &mut Ast, id: DeclId) -> DeclId {
+        fold_walk_decl(self, ast, id)
+    }
+
+    fn fold_stmt(&mut self, ast: &mut Ast, id: StmtId) -> StmtId {
+        fold_walk_stmt(self, ast, id)
+    }
+
+    fn fold_expr(&mut self, ast: &mut Ast, id: ExprId) -> ExprId {
+        fold_walk_expr(self, ast, id)
+    }
+
+    fn fold_binop(&mut self, ast: &mut Ast, o: BinOp) -> BinOp {
+        fold_walk_binop(self, ast, o)
+    }
+
+    fn fold_unop(&mut self, ast: &mut Ast, o: UnOp) -> UnOp {
+        fold_walk_unop(self, ast, o)
+    }
+
+    fn fold_init(&mut self, ast: &mut Ast, init: Init) -> Init {
+        fold_walk_init(self, ast, init)
+    }
+
+    fn fold_type(&mut self, ast: &mut Ast, t: Type) -> Type {
+        fold_walk_type(self, ast, t)
+    }
+}
+
+pub fn fold_walk_translation_unit<F: Fold + ?Sized>(f: &mut F, tu: &mut TranslationUnit) {
+    for i in 0..tu.items.len() {
+        tu.items[i] = f.fold_decl(&mut tu.ast, tu.items[i]);
+    }
+}
+
+pub fn fold_walk_decl<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, id: DeclId) -> DeclId {
+    let mut d = std::mem::replace(&mut ast.decls[id.index()], Decl::Tag { t: Type::Void });
+    match &mut d {
+        Decl::Func { t, id: _, args, block, spec: _ } => {
+            for arg in args.iter_mut() {
+                *arg = f.fold_decl(ast, *arg);
+            }
+            *block = block.map(|block| f.fold_stmt(ast, block));
+            // the parameter types are rebuilt from the folded parameters, as the parser builds them
+            if let Type::Function { ret, params, variadic: _, prototype: _ } = t {
+                **ret = f.fold_type(ast, std::mem::replace(&mut **ret, Type::Void));
+                *params = args.iter().map(|arg| match &ast[*arg] {
+                    Decl::Arg { t, id: _, spec: _ } => t.unqualified().clone(),
+                    _ => unreachable!(),
+                }).collect();
+            }
+        },
+        Decl::Var { t, id: _, val, spec: _ } => {
+            *t = f.fold_type(ast, std::mem::replace(t, Type::Void));
+            *val = val.take().map(|val| f.fold_init(ast, val));
+        },
+        Decl::Arg { t, id: _, spec: _ } | Decl::Tag { t } | Decl::Typedef { t, id: _, spec: _ } => {
+            *t = f.fold_type(ast, std::mem::replace(t, Type::Void));
+        },
+    }
+    ast.decls[id.index()] = d;
+    id
+}
+
+pub fn fold_walk_stmt<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, id: StmtId) -> StmtId {
+    let mut s = std::mem::replace(&mut ast.stmts[id.index()], Stmt::Empty);
+    match &mut s {
+        Stmt::Block { v } => {
+            for s in v.iter_mut() {
+                *s = f.fold_stmt(ast, *s);
+            }
+        },
+        Stmt::Decl { d } => *d = f.fold_decl(ast, *d),
+        Stmt::Expr { e } => *e = f.fold_expr(ast, *e),
+        Stmt::If { cond, block, next } => {
+            *cond = f.fold_expr(ast, *cond);
+            *block = f.fold_stmt(ast, *block);
+            *next = next.map(|next| f.fold_stmt(ast, next));
+        },
+        Stmt::For { var, cond, e, block } => {
+            match var {
+                Some(ForInit::Decl { v }) => {
+                    for d in v.iter_mut() {
+                        *d = f.fold_decl(ast, *d);
+                    }
+                },
+                Some(ForInit::Expr { e }) => *e = f.fold_expr(ast, *e),
+                None => {},
+            }
+            *cond = cond.map(|cond| f.fold_expr(ast, cond));
+            *e = e.map(|e| f.fold_expr(ast, e));
+            *block = f.fold_stmt(ast, *block);
+        },
+        Stmt::While { cond, block } | Stmt::Switch { cond, block, cases: _, default: _ } | Stmt::Case { e: cond, block } => {
+            *cond = f.fold_expr(ast, *cond);
+            *block = f.fold_stmt(ast, *block);
+        },
+        Stmt::Default { block } | Stmt::Label { id: _, block } => *block = f.fold_stmt(ast, *block),
+        Stmt::Return { e } => *e = e.map(|e| f.fold_expr(ast, e)),
+        Stmt::Goto { .. } | Stmt::Break | Stmt::Continue | Stmt::Empty => {},
+    }
+    ast.stmts[id.index()] = s;
+    id
+}
+
+pub fn fold_walk_expr<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, id: ExprId) -> ExprId {
+    let e = std::mem::replace(&mut ast.exprs[id.index()], Expr::IntN { num: 0 });
+    let e = match e {
+        Expr::CompoundLit { t, v } => Expr::CompoundLit { t: f.fold_type(ast, t), v: fold_walk_items(f, ast, v) },
+        Expr::BinOp { o } => Expr::BinOp { o: f.fold_binop(ast, o) },
+        Expr::UnOp { o } => Expr::UnOp { o: f.fold_unop(ast, o) },
+        Expr::ImplicitCast { kind, t, e } => Expr::ImplicitCast { kind, t, e: f.fold_expr(ast, e) },
+        Expr::StrN { s, n } => Expr::StrN { s, n: f.fold_expr(ast, n) },
+        e => e,
+    };
+    ast.exprs[id.index()] = e;
+    id
+}
+
+pub fn fold_walk_binop<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, mut o: BinOp) -> BinOp {
+    let (le, re) = o.operands_mut();
+    *le = f.fold_expr(ast, *le);
+    *re = f.fold_expr(ast, *re);
+    o
+}
+
+pub fn fold_walk_unop<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, mut o: UnOp) -> UnOp {
+    match &mut o {
+        UnOp::UnPlus { e } | UnOp::UnMinus { e } | UnOp::LogNot { e } |
+        UnOp::PreInc { e } | UnOp::PostInc { e } | UnOp::PreDec { e } | UnOp::PostDec { e } |
+        UnOp::Indirection { e } | UnOp::AddressOf { e } |
+        UnOp::Member { e, id: _ } | UnOp::PtrMember { e, id: _ } |
+        UnOp::SizeOf { e } | UnOp::VaEnd { e } => *e = f.fold_expr(ast, *e),
+        UnOp::Subscript { le, re } | UnOp::VaCopy { le, re } | UnOp::VaStart { e: le, last: re } => {
+            *le = f.fold_expr(ast, *le);
+            *re = f.fold_expr(ast, *re);
+        },
+        UnOp::FuncCall { e, args } => {
+            *e = f.fold_expr(ast, *e);
+            for arg in args.iter_mut() {
+                *arg = f.fold_expr(ast, *arg);
+            }
+        },
+        UnOp::TypeCast { t, e } | UnOp::VaArg { e, t } => {
+            *t = f.fold_type(ast, std::mem::replace(t, Type::Void));
+            *e = f.fold_expr(ast, *e);
+        },
+        UnOp::SizeOfType { t } | UnOp::AlignOf { t } => *t = f.fold_type(ast, std::mem::replace(t, Type::Void)),
+    }
+    o
+}
+
+pub fn fold_walk_init<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, init: Init) -> Init {
+    match init {
+        Init::Expr { e } => Init::Expr { e: f.fold_expr(ast, e) },
+        Init::List { v } => Init::List { v: fold_walk_items(f, ast, v) },
+    }
+}
+
+fn fold_walk_items<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, mut items: Vec<InitItem>) -> Vec<InitItem> {
+    for item in items.iter_mut() {
+        for d in item.d.iter_mut() {
+            if let Designator::Index { e, n: _ } = d {
+                *e = f.fold_expr(ast, *e);
+            }
+        }
+        item.init = f.fold_init(ast, std::mem::replace(&mut item.init, Init::List { v: Vec::new() }));
+    }
+    items
+}
+
+pub fn fold_walk_type<F: Fold + ?Sized>(f: &mut F, ast: &mut Ast, t: Type) -> Type {
+    match t {
+        Type::Ptr { t } => Type::Ptr { t: Box::new(f.fold_type(ast, *t)) },
+        Type::Qualified { t, q } => Type::Qualified { t: Box::new(f.fold_type(ast, *t)), q },
+        Type::Array { t, n } => Type::Array { t: Box::new(f.fold_type(ast, *t)), n: n.map(|n| f.fold_expr(ast, n)) },
+        Type::Function { ret, params, variadic, prototype } => Type::Function {
+            ret: Box::new(f.fold_type(ast, *ret)),
+            params: params.into_iter().map(|param| f.fold_type(ast, param)).collect(),
+            variadic,
+            prototype,
+        },
+        t => t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::printer::print;
+    use crate::parse_source;
+
+    // Identifiers in the order they are visited
+    struct Ids(Vec<String>);
+
+    impl Visitor for Ids {
+        fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+            if let Expr::Id { s } = &ast[id] {
+                self.0.push(s.to_string());
+            }
+            walk_expr(self, ast, id)
+        }
+    }
+
+    // Folds `a + b` of two constants and swaps the operands of every other `+`
+    struct Adds;
+
+    impl Fold for Adds {
+        fn fold_expr(&mut self, ast: &mut Ast, id: ExprId) -> ExprId {
+            let id = fold_walk_expr(self, ast, id);
+            match &ast[id] {
+                Expr::BinOp { o: BinOp::Add { le, re } } => match (const_eval(ast, *le), const_eval(ast, *re)) {
+                    (Ok(a), Ok(b)) => ast.add_expr(Expr::IntN { num: (a + b) as i32 }),
+                    _ => ast.add_expr(Expr::BinOp { o: BinOp::Add { le: *re, re: *le } }),
+                },
+                _ => id,
+            }
+        }
+    }
+
+    #[test]
+    fn visitor() {
+        let tu = parse_source("int n; int a[sizeof n]; int f(int x) { int b[2] = { x, [1] = a[0] }; if (x) return f(n + x); return (int){ b[1] }; }").unwrap();
+        let mut ids = Ids(Vec::new());
+        ids.visit_translation_unit(&tu);
+        assert_eq!(ids.0, ["n", "x", "a", "x", "f", "n", "x", "b"]);
+    }
+
+    #[test]
+    fn fold() {
+        let mut tu = parse_source("int a[1 + 2]; int f(int x) { return (1 + 2) * f(x + 1) + x; }").unwrap();
+        Adds.fold_translation_unit(&mut tu);
+        assert_eq!(print(&tu), "int a[3];\n\nint f(int x) {\n    return x + 3 * f(1 + x);\n}\n");
+    }
+}
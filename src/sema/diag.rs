@@ -0,0 +1,58 @@
+//! Errors and warnings of the semantic passes.
+
+use std::fmt;
+
+use crate::lex::lexer::Span;
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem a semantic pass found in a tree that parsed. Unlike the lexer and the parser they go on
+/// after one, so there can be many. `note` points at another place that explains it, like the earlier declaration.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub msg: String,
+    pub span: Span,
+    pub note: Option<(String, Span)>,
+}
+
+impl Diagnostic {
+    pub fn error(msg: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic { level: Level::Error, msg: msg.into(), span, note: None }
+    }
+
+    pub fn warning(msg: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic { level: Level::Warning, msg: msg.into(), span, note: None }
+    }
+
+    pub fn with_note(self, msg: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic { note: Some((msg.into(), span)), ..self }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+pub fn has_errors(diags: &[Diagnostic]) -> bool {
+    diags.iter().any(|d| d.level == Level::Error)
+}
@@ -0,0 +1,417 @@
+//! Name resolution: links every identifier in an expression to the declaration it refers to, with C's scopes.
+//! The file scope holds the external declarations, a function's parameters share a scope with the outermost
+//! block of its body, and every other block and `for` statement opens one of its own. Parameters of a function
+//! declaration without a body only live until the end of it. Labels are per function and can be used before
+//! they are defined.
+//!
+//! Errors are undeclared identifiers and labels, and declarations that clash with an earlier one in the
+//! same scope; a redeclaration that C allows, like a prototype before the definition, is fine as long as the
+//! types are compatible, and later uses refer to the newer declaration. Declarations hiding one from an enclosing scope get a warning.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::lex::lexer::Span;
+use crate::parse::parser::*;
+use crate::parse::printer::type_name;
+use crate::parse::visit::{self, Visitor};
+use crate::sema::diag::Diagnostic;
+use crate::sema::types::compatible;
+
+/// What an identifier refers to
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Binding {
+    Decl(DeclId),
+    /// constant `index` of `Ast::enums[id]`, which has no declaration of its own
+    Enumerator { id: usize, index: usize },
+}
+
+#[derive(Debug)]
+pub struct Resolution {
+    /// binding of every `Expr::Id` and `Expr::EnumN`, a side table indexed like `Ast::exprs`
+    pub uses: Vec<Option<Binding>>,
+    pub diags: Vec<Diagnostic>,
+}
+
+impl Resolution {
+    pub fn binding(&self, id: ExprId) -> Option<Binding> {
+        self.uses.get(id.index()).copied().flatten()
+    }
+}
+
+pub fn resolve(tu: &TranslationUnit) -> Resolution {
+    let mut r = Resolver {
+        spans: &tu.spans, scopes: Vec::new(), uses: vec![None; tu.ast.exprs.len()], diags: Vec::new(),
+        enums: HashSet::new(), records: HashSet::new(),
+        labels: HashMap::new(), gotos: Vec::new(),
+    };
+    r.push(ScopeKind::File);
+    r.visit_translation_unit(tu);
+    Resolution { uses: r.uses, diags: r.diags }
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+enum ScopeKind {
+    File,
+    // parameters and body of a function definition
+    Params,
+    // parameters of a function declaration
+    Prototype,
+    Block,
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+enum Kind {
+    // `defined` is having an initializer
+    Var { defined: bool, external: bool },
+    Func { defined: bool },
+    Param,
+    Typedef,
+    Enumerator,
+}
+
+struct Entry {
+    binding: Binding,
+    kind: Kind,
+    span: Span,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    names: HashMap<String, Entry>,
+}
+
+struct Resolver<'a> {
+    spans: &'a Spans,
+    scopes: Vec<Scope>,
+    uses: Vec<Option<Binding>>,
+    diags: Vec<Diagnostic>,
+    // enums and records whose definitions were seen, since types are visited wherever they're used
+    enums: HashSet<usize>,
+    records: HashSet<usize>,
+    // labels of the function being visited, and its gotos
+    labels: HashMap<String, Span>,
+    gotos: Vec<(String, Span)>,
+}
+
+impl Resolver<'_> {
+    fn push(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope { kind, names: HashMap::new() });
+    }
+
+    fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Entry> {
+        self.scopes.iter().rev().find_map(|scope| scope.names.get(name))
+    }
+
+    fn declare(&mut self, ast: &Ast, name: &str, binding: Binding, kind: Kind, span: Span) {
+        let scope = self.scopes.last().unwrap();
+        if let Some(old) = scope.names.get(name) {
+            if let Some(msg) = conflict(ast, name, old, binding, kind, scope.kind == ScopeKind::File) {
+                let note = match old.kind {
+                    Kind::Var { defined: false, .. } | Kind::Func { defined: false } => "previous declaration is here",
+                    _ => "previous definition is here",
+                };
+                self.diags.push(Diagnostic::error(msg, span).with_note(note, old.span));
+                return;
+            }
+        } else if !matches!(scope.kind, ScopeKind::File | ScopeKind::Prototype) {
+            let outer = self.scopes.iter().rev().skip(1).find_map(|scope| Some((scope.kind, scope.names.get(name)?)));
+            let what = match outer {
+                Some((_, Entry { kind: Kind::Func { .. }, .. })) | None => None,
+                Some((ScopeKind::File, _)) => Some("a global declaration"),
+                Some((ScopeKind::Params, Entry { kind: Kind::Param, .. })) => Some("a parameter"),
+                Some(_) => Some("a previous local"),
+            };
+            if let (Some(what), Some((_, old))) = (what, outer) {
+                let d = Diagnostic::warning(format!("declaration of '{}' shadows {}", name, what), span);
+                self.diags.push(d.with_note("shadowed declaration is here", old.span));
+            }
+        }
+
+        // a definition after declarations is still one
+        let kind = match (self.scopes.last().unwrap().names.get(name).map(|old| old.kind), kind) {
+            (Some(Kind::Func { defined: true }), Kind::Func { .. }) => Kind::Func { defined: true },
+            (Some(Kind::Var { defined: true, .. }), Kind::Var { external, .. }) => Kind::Var { defined: true, external },
+            (_, kind) => kind,
+        };
+        self.scopes.last_mut().unwrap().names.insert(name.to_string(), Entry { binding, kind, span });
+    }
+}
+
+// Why a declaration can't follow another of the same name in the same scope, if it can't
+fn conflict(ast: &Ast, name: &str, old: &Entry, binding: Binding, new: Kind, file: bool) -> Option<String> {
+    let redefinition = Some(format!("redefinition of '{}'", name));
+    let t = |b: Binding| match b {
+        Binding::Decl(d) => match &ast[d] {
+            Decl::Typedef { t, .. } | Decl::Var { t, .. } | Decl::Func { t, .. } => Some(t),
+            _ => None,
+        },
+        Binding::Enumerator { .. } => None,
+    };
+    // declarations C allows to repeat still have to agree on the type
+    let conflicting = || match (t(old.binding), t(binding)) {
+        (Some(a), Some(b)) if !compatible(a, b) => {
+            Some(format!("conflicting types for '{}' ('{}' vs '{}')", name, type_name(ast, b), type_name(ast, a)))
+        },
+        _ => None,
+    };
+    match (old.kind, new) {
+        (Kind::Func { defined: a }, Kind::Func { defined: b }) => if a && b { redefinition } else { conflicting() },
+        (Kind::Var { defined: a, .. }, Kind::Var { defined: b, .. }) if file => if a && b { redefinition } else { conflicting() },
+        (Kind::Var { external: true, .. }, Kind::Var { external: true, .. }) => conflicting(),
+        (Kind::Var { .. } | Kind::Param, Kind::Var { .. }) => redefinition,
+        (Kind::Param, Kind::Param) => Some(format!("redefinition of parameter '{}'", name)),
+        (Kind::Typedef, Kind::Typedef) => match (t(old.binding), t(binding)) {
+            (Some(a), Some(b)) if a != b => {
+                Some(format!("typedef redefinition with different types ('{}' vs '{}')", type_name(ast, b), type_name(ast, a)))
+            },
+            _ => None,
+        },
+        _ => Some(format!("redefinition of '{}' as a different kind of symbol", name)),
+    }
+}
+
+impl Visitor for Resolver<'_> {
+    fn visit_decl(&mut self, ast: &Ast, id: DeclId) {
+        let name = self.spans.name(id);
+        match &ast[id] {
+            Decl::Func { t, id: f, args, block, spec: _ } => {
+                if let Type::Function { ret, .. } = t {
+                    self.visit_type(ast, ret);
+                }
+                // declared before the body, which can call it
+                self.declare(ast, f, Binding::Decl(id), Kind::Func { defined: block.is_some() }, name);
+                let Some(block) = block else {
+                    self.push(ScopeKind::Prototype);
+                    args.iter().for_each(|arg| self.visit_decl(ast, *arg));
+                    self.pop();
+                    return;
+                };
+                self.push(ScopeKind::Params);
+                args.iter().for_each(|arg| self.visit_decl(ast, *arg));
+                // the outermost block is the parameters' scope
+                match &ast[*block] {
+                    Stmt::Block { v } => v.iter().for_each(|s| self.visit_stmt(ast, *s)),
+                    _ => self.visit_stmt(ast, *block),
+                }
+                self.pop();
+                for (label, span) in std::mem::take(&mut self.gotos) {
+                    if !self.labels.contains_key(&label) {
+                        self.diags.push(Diagnostic::error(format!("use of undeclared label '{}'", label), span));
+                    }
+                }
+                self.labels.clear();
+            },
+            Decl::Arg { t, id: arg, spec: _ } => {
+                self.visit_type(ast, t);
+                if let Some(arg) = arg {
+                    self.declare(ast, arg, Binding::Decl(id), Kind::Param, name);
+                }
+            },
+            Decl::Var { t, id: var, val, spec } => {
+                self.visit_type(ast, t);
+                let kind = Kind::Var { defined: val.is_some(), external: spec.storage == Some(Storage::Extern) };
+                // in scope from its declarator on, so `int x = x;` is itself
+                self.declare(ast, var, Binding::Decl(id), kind, name);
+                if let Some(val) = val {
+                    self.visit_init(ast, val);
+                }
+            },
+            Decl::Typedef { t, id: typedef, spec: _ } => {
+                self.visit_type(ast, t);
+                self.declare(ast, typedef, Binding::Decl(id), Kind::Typedef, name);
+            },
+            Decl::Tag { t } => self.visit_type(ast, t),
+        }
+    }
+
+    fn visit_stmt(&mut self, ast: &Ast, id: StmtId) {
+        match &ast[id] {
+            Stmt::Block { .. } | Stmt::For { .. } => {
+                self.push(ScopeKind::Block);
+                visit::walk_stmt(self, ast, id);
+                self.pop();
+                return;
+            },
+            Stmt::Label { id: label, block: _ } => {
+                let span = self.spans.stmt(id);
+                if let Some(old) = self.labels.get(label) {
+                    let d = Diagnostic::error(format!("redefinition of label '{}'", label), span);
+                    self.diags.push(d.with_note("previous definition is here", *old));
+                } else {
+                    self.labels.insert(label.to_string(), span);
+                }
+            },
+            Stmt::Goto { id: label } => self.gotos.push((label.to_string(), self.spans.stmt(id))),
+            _ => {},
+        }
+        visit::walk_stmt(self, ast, id)
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        match &ast[id] {
+            Expr::Id { s } => match self.lookup(s) {
+                Some(entry) => self.uses[id.index()] = Some(entry.binding),
+                None => self.diags.push(Diagnostic::error(format!("use of undeclared identifier '{}'", s), self.spans.expr(id))),
+            },
+            // the parser found the constant already, but maybe in an enum inside a struct, which isn't in a scope here
+            Expr::EnumN { s, num } => {
+                self.uses[id.index()] = match self.lookup(s) {
+                    Some(Entry { binding: b @ Binding::Enumerator { .. }, .. }) => Some(*b),
+                    _ => ast.enums.iter().enumerate().rev().find_map(|(e, en)| {
+                        let index = en.consts.as_ref()?.iter().position(|c| c.id == *s && c.val == *num)?;
+                        Some(Binding::Enumerator { id: e, index })
+                    }),
+                };
+            },
+            _ => {},
+        }
+        visit::walk_expr(self, ast, id)
+    }
+
+    fn visit_type(&mut self, ast: &Ast, t: &Type) {
+        match t {
+            Type::Enum { tag: _, id } if self.enums.insert(*id) => {
+                for (index, c) in ast.enums[*id].consts.iter().flatten().enumerate() {
+                    self.declare(ast, &c.id, Binding::Enumerator { id: *id, index }, Kind::Enumerator, self.spans.enumerator(*id, index));
+                }
+            },
+            Type::Struct { tag: _, id } | Type::Union { tag: _, id } if self.records.insert(*id) => {
+                for m in ast.records[*id].members.iter().flatten() {
+                    self.visit_type(ast, &m.t);
+                }
+            },
+            _ => {},
+        }
+        visit::walk_type(self, ast, t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lexer::line_col;
+    use crate::parse_source;
+    use crate::sema::diag::Level;
+
+    // Diagnostics as `line:col: level: message`
+    fn check(src: &str) -> Vec<String> {
+        let tu = parse_source(src).unwrap();
+        resolve(&tu).diags.iter()
+            .map(|d| {
+                let (line, col) = line_col(src, d.span.lo);
+                format!("{}:{}: {}: {}", line, col, d.level, d.msg)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bindings() {
+        let src = "int x;\nint f(int x) { { int x = x; } return x; }\nint g(void) { return f(x); }";
+        let tu = parse_source(src).unwrap();
+        let r = resolve(&tu);
+        // every identifier names a declaration of the same name, which for the `x`s is the closest one
+        let uses: Vec<_> = r.uses.iter().enumerate()
+            .filter_map(|(e, b)| match b {
+                Some(Binding::Decl(d)) => Some((line_col(src, tu.spans.exprs[e].lo), line_col(src, tu.spans.name(*d).lo))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(uses, vec![
+            ((2, 26), (2, 22)),
+            ((2, 38), (2, 11)),
+            ((3, 22), (2, 5)),
+            ((3, 24), (1, 5)),
+        ]);
+        assert!(r.diags.iter().all(|d| d.level == Level::Warning));
+    }
+
+    #[test]
+    fn errors() {
+        let src = "\
+int main() {
+    int x = 2;
+    x += test(a) + 2;
+    goto out;
+    return 0;
+}
+int main() { return 0; }
+int f(int a, int a);
+typedef int T;
+typedef long T;
+enum { A, T };
+void g(int p) {
+    int p;
+    extern int e;
+    extern int e;
+    int q;
+    int q;
+l:
+l:
+    ;
+}
+";
+        assert_eq!(check(src), vec![
+            "3:10: error: use of undeclared identifier 'test'",
+            "3:15: error: use of undeclared identifier 'a'",
+            "4:5: error: use of undeclared label 'out'",
+            "7:5: error: redefinition of 'main'",
+            "8:18: error: redefinition of parameter 'a'",
+            "10:14: error: typedef redefinition with different types ('long' vs 'int')",
+            "11:11: error: redefinition of 'T' as a different kind of symbol",
+            "13:9: error: redefinition of 'p'",
+            "17:9: error: redefinition of 'q'",
+            "19:1: error: redefinition of label 'l'",
+        ]);
+    }
+
+    #[test]
+    fn shadowing() {
+        let src = "int g;\nenum { E };\nvoid f(int a) {\n    int g;\n    { int a; int E; }\n    for (int i = 0; ; ) { int i; }\n}\nint h(int g);";
+        assert_eq!(check(src), vec![
+            "4:9: warning: declaration of 'g' shadows a global declaration",
+            "5:11: warning: declaration of 'a' shadows a parameter",
+            "5:18: warning: declaration of 'E' shadows a global declaration",
+            "6:31: warning: declaration of 'i' shadows a previous local",
+        ]);
+        let tu = parse_source("int x;\nint x;\nextern int x;\nint x = 1;\nint f();\nint f() { return 0; }").unwrap();
+        assert!(resolve(&tu).diags.is_empty());
+    }
+
+    #[test]
+    fn conflicting_types() {
+        let src = "\
+int x;
+char *x;
+int f(int);
+double f(char *);
+int g(void);
+int g(int n) { return n; }
+void h() {
+    extern long e;
+    extern int e;
+}
+";
+        assert_eq!(check(src), vec![
+            "2:7: error: conflicting types for 'x' ('char *' vs 'int')",
+            "4:8: error: conflicting types for 'f' ('double (char *)' vs 'int (int)')",
+            "6:5: error: conflicting types for 'g' ('int (int)' vs 'int (void)')",
+            "9:16: error: conflicting types for 'e' ('int' vs 'long')",
+        ]);
+        let d = &resolve(&parse_source(src).unwrap()).diags[0];
+        assert_eq!(d.note.as_ref().map(|(msg, span)| (msg.as_str(), line_col(src, span.lo))), Some(("previous declaration is here", (1, 5))));
+
+        // redeclarations that agree: old-style and prototyped, array sizes, adjusted and qualified parameters
+        let src = "int a[];\nint a[3];\nint f();\nint f(int x, char *s) { return x; }\nvoid g(const int n, int v[]);\nvoid g(int n, int *v);";
+        assert!(resolve(&parse_source(src).unwrap()).diags.is_empty());
+    }
+}
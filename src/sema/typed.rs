@@ -0,0 +1,385 @@
+//! The typed tree: the tree `check` accepted, with every conversion C does implicitly made an `Expr::ImplicitCast`
+//! around the expression converted, so a code generator can take every value as having exactly the type it needs.
+//!
+//! An operand used for its value is read from its object (`LValueToRValue`), or decays to a pointer if it is an
+//! array or a function. Then it converts to the type its operator computes in: both operands of arithmetic and
+//! comparisons to their common type, the operand of unary `+` and `-` and a `switch` condition to its promoted
+//! type, conditions and the operands of `&&`, `||` and `!` to `_Bool`, and assigned, initializing, passed and
+//! returned values to the type of what they end up in. Arguments with no parameter for them get the default
+//! argument promotions. Operands of `&`, `sizeof`, `++` and `--`, the left side of assignments and of `.`, and
+//! expressions whose value is thrown away stay as they are.
+//!
+//! New nodes are added at the end of the arena, so the ids of the parsed ones don't change and a `Resolution`
+//! of the tree before still applies.
+
+use crate::lex::lexer::Span;
+use crate::parse::parser::*;
+use crate::parse::printer::type_name;
+use crate::parse::visit::{self, Fold};
+use crate::sema::diag::Level;
+use crate::sema::resolve::Resolution;
+use crate::sema::types::{adjust_param, arith_conv, decay, initialized, is_arithmetic, is_lvalue, is_null_pointer, is_string_init, promote, Typing};
+
+#[derive(Debug)]
+pub struct TypedUnit {
+    pub tu: TranslationUnit,
+    /// type of every expression, the casts included, a side table indexed like `Ast::exprs`.
+    /// None only where `check` found an error.
+    pub types: Vec<Option<Type>>,
+}
+
+impl TypedUnit {
+    pub fn type_of(&self, id: ExprId) -> Option<&Type> {
+        self.types.get(id.index())?.as_ref()
+    }
+}
+
+/// Makes the typed tree from a translation unit and what `resolve` and `check` found in it. Meant for a tree without
+/// errors; where there are some, the expressions without a type and the conversions `check` rejected are left without casts.
+pub fn annotate(mut tu: TranslationUnit, res: &Resolution, typing: Typing) -> TypedUnit {
+    let spans = std::mem::take(&mut tu.spans.exprs);
+    let errors = res.diags.iter().chain(&typing.diags).any(|d| d.level == Level::Error);
+    let mut c = Caster { res, types: typing.types, spans, ret: None, errors };
+    visit::fold_walk_translation_unit(&mut c, &mut tu);
+    tu.spans.exprs = c.spans;
+    TypedUnit { tu, types: c.types }
+}
+
+struct Caster<'a> {
+    res: &'a Resolution,
+    types: Vec<Option<Type>>,
+    // `Spans::exprs` of the tree, which the casts get the spans of their operands in
+    spans: Vec<Span>,
+    // return type of the function being folded
+    ret: Option<Type>,
+    // whether `resolve` or `check` found errors, so conversions they should have rejected can be there
+    errors: bool,
+}
+
+impl Caster<'_> {
+    fn type_of(&self, e: ExprId) -> Option<Type> {
+        self.types.get(e.index()).cloned().flatten()
+    }
+
+    fn cast(&mut self, ast: &mut Ast, kind: CastKind, t: Type, e: ExprId) -> ExprId {
+        let id = ast.add_expr(Expr::ImplicitCast { kind, t: t.clone(), e });
+        self.types.push(Some(t));
+        let span = self.spans.get(e.index()).copied().unwrap_or_default();
+        self.spans.resize(id.index(), Span::default());
+        self.spans.push(span);
+        id
+    }
+
+    // The value of an operand: an object is read, and arrays and functions become pointers
+    fn value(&mut self, ast: &mut Ast, e: ExprId) -> ExprId {
+        let Some(t) = self.type_of(e) else {
+            return e;
+        };
+        match t.unqualified() {
+            Type::Array { .. } => self.cast(ast, CastKind::ArrayToPointerDecay, decay(&t), e),
+            Type::Function { .. } => self.cast(ast, CastKind::FunctionToPointerDecay, decay(&t), e),
+            _ if is_lvalue(ast, self.res, e) => self.cast(ast, CastKind::LValueToRValue, t.unqualified().clone(), e),
+            _ => e,
+        }
+    }
+
+    // The value of an operand converted to `to`, which `check` made sure it can be
+    fn convert(&mut self, ast: &mut Ast, e: ExprId, to: &Type) -> ExprId {
+        let e = self.value(ast, e);
+        let Some(from) = self.type_of(e) else {
+            return e;
+        };
+        let (from, to) = (from.unqualified(), to.unqualified());
+        let kind = match (from, to) {
+            _ if from == to => return e,
+            (Type::Ptr { .. }, Type::Bool) => CastKind::PointerToBoolean,
+            (_, Type::Bool) if from.is_floating() => CastKind::FloatingToBoolean,
+            (_, Type::Bool) => CastKind::IntegralToBoolean,
+            (Type::Ptr { t: a }, Type::Ptr { t: b }) if a.unqualified() == b.unqualified() => CastKind::NoOp,
+            (Type::Ptr { .. }, Type::Ptr { .. }) => CastKind::BitCast,
+            (_, Type::Ptr { .. }) if is_null_pointer(ast, e, from) => CastKind::NullToPointer,
+            // `check` lets nothing else become a pointer, so it reported this one
+            (_, Type::Ptr { .. }) => {
+                debug_assert!(self.errors, "'{}' converted to '{}' without a cast", type_name(ast, from), type_name(ast, to));
+                return e;
+            },
+            _ if from.is_integer() && to.is_integer() => CastKind::IntegralCast,
+            _ if from.is_integer() && to.is_floating() => CastKind::IntegralToFloating,
+            _ if from.is_floating() && to.is_integer() => CastKind::FloatingToIntegral,
+            _ if from.is_floating() && to.is_floating() => CastKind::FloatingCast,
+            // records and `va_list` only go to their own type
+            _ => return e,
+        };
+        self.cast(ast, kind, to.clone(), e)
+    }
+
+    // An argument without a parameter saying its type gets the default argument promotions
+    fn promote_arg(&mut self, ast: &mut Ast, e: ExprId) -> ExprId {
+        let e = self.value(ast, e);
+        match self.type_of(e) {
+            Some(Type::Float) => self.convert(ast, e, &Type::Double),
+            Some(t) if t.is_integer() => self.convert(ast, e, &promote(&t)),
+            _ => e,
+        }
+    }
+
+    fn binop(&mut self, ast: &mut Ast, o: &mut BinOp) {
+        let (le, re) = o.operands();
+        let (Some(lt), Some(rt)) = (self.type_of(le), self.type_of(re)) else {
+            return;
+        };
+        let (l, r) = (decay(&lt), decay(&rt));
+        let ptr = |t: &Type| matches!(t, Type::Ptr { .. });
+        let void_ptr = |t: &Type| matches!(t, Type::Ptr { t } if *t.unqualified() == Type::Void);
+        let equality = matches!(o, BinOp::Equal { .. } | BinOp::NotEqual { .. });
+        match o {
+            // the left operand is only evaluated
+            BinOp::Comma { le: _, re } => *re = self.value(ast, *re),
+            BinOp::Assign { le: _, re } => *re = self.convert(ast, *re, &lt),
+            BinOp::AddAssign { le: _, re } | BinOp::SubAssign { le: _, re } if ptr(&l) => *re = self.value(ast, *re),
+            // computed in the common type of both sides, and converted back to the left one when stored
+            BinOp::AddAssign { le: _, re } | BinOp::SubAssign { le: _, re } | BinOp::MulAssign { le: _, re } |
+            BinOp::DivAssign { le: _, re } | BinOp::ModAssign { le: _, re } => *re = self.convert(ast, *re, &arith_conv(&l, &r)),
+            BinOp::LogOr { le, re } | BinOp::LogAnd { le, re } => {
+                *le = self.convert(ast, *le, &Type::Bool);
+                *re = self.convert(ast, *re, &Type::Bool);
+            },
+            BinOp::Equal { le, re } | BinOp::NotEqual { le, re } |
+            BinOp::Bigger { le, re } | BinOp::Lesser { le, re } | BinOp::BiggerEqual { le, re } | BinOp::LesserEqual { le, re } |
+            BinOp::Add { le, re } | BinOp::Sub { le, re } | BinOp::Mul { le, re } | BinOp::Div { le, re } | BinOp::Mod { le, re } => {
+                // `==` and `!=` compare a pointer with `void *` or a null pointer constant as that type
+                let to = match () {
+                    _ if is_arithmetic(&l) && is_arithmetic(&r) => Some(arith_conv(&l, &r)),
+                    _ if equality && ptr(&l) && ptr(&r) && void_ptr(&l) != void_ptr(&r) => Some(if void_ptr(&l) { l } else { r }),
+                    _ if equality && ptr(&l) && is_null_pointer(ast, *re, &r) => Some(l),
+                    _ if equality && ptr(&r) && is_null_pointer(ast, *le, &l) => Some(r),
+                    // pointer arithmetic and relational comparisons keep both sides as they are, an integer offset too
+                    _ => None,
+                };
+                match to {
+                    Some(to) => {
+                        *le = self.convert(ast, *le, &to);
+                        *re = self.convert(ast, *re, &to);
+                    },
+                    None => {
+                        *le = self.value(ast, *le);
+                        *re = self.value(ast, *re);
+                    },
+                }
+            },
+        }
+    }
+
+    fn unop(&mut self, ast: &mut Ast, o: &mut UnOp) {
+        match o {
+            UnOp::UnPlus { e } | UnOp::UnMinus { e } => {
+                if let Some(t) = self.type_of(*e) {
+                    *e = self.convert(ast, *e, &promote(&t));
+                }
+            },
+            UnOp::LogNot { e } => *e = self.convert(ast, *e, &Type::Bool),
+            UnOp::Indirection { e } | UnOp::PtrMember { e, id: _ } => *e = self.value(ast, *e),
+            UnOp::TypeCast { t, e } if *t.unqualified() != Type::Void => *e = self.value(ast, *e),
+            UnOp::Subscript { le, re } => {
+                *le = self.value(ast, *le);
+                *re = self.value(ast, *re);
+            },
+            UnOp::FuncCall { e, args } => {
+                let params = match self.type_of(*e).map(|t| decay(&t)) {
+                    Some(Type::Ptr { t }) => match *t {
+                        Type::Function { ret: _, params, .. } => params,
+                        _ => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                };
+                *e = self.value(ast, *e);
+                for (i, arg) in args.iter_mut().enumerate() {
+                    *arg = match params.get(i) {
+                        Some(param) => self.convert(ast, *arg, &adjust_param(param)),
+                        None => self.promote_arg(ast, *arg),
+                    };
+                }
+            },
+            // objects rather than values, or not evaluated
+            UnOp::PreInc { .. } | UnOp::PostInc { .. } | UnOp::PreDec { .. } | UnOp::PostDec { .. } |
+            UnOp::AddressOf { .. } | UnOp::Member { .. } | UnOp::TypeCast { .. } |
+            UnOp::SizeOf { .. } | UnOp::SizeOfType { .. } | UnOp::AlignOf { .. } |
+            UnOp::VaStart { .. } | UnOp::VaArg { .. } | UnOp::VaEnd { .. } | UnOp::VaCopy { .. } => {},
+        }
+    }
+
+    fn init(&mut self, ast: &mut Ast, t: &Type, init: &mut Init) {
+        match init {
+            // a string literal fills a char array, with or without braces
+            _ if is_string_init(ast, t, init) => {},
+            Init::List { v } => self.items(ast, t, v),
+            // `initialized` took the value to the scalar it is for, with braces left out
+            Init::Expr { e } => {
+                *e = match t.unqualified() {
+                    Type::Array { .. } | Type::Void => self.value(ast, *e),
+                    _ => self.convert(ast, *e, t),
+                };
+            },
+        }
+    }
+
+    // The items of an initializer list for an object of type `t`
+    fn items(&mut self, ast: &mut Ast, t: &Type, v: &mut [InitItem]) {
+        let targets = initialized(ast, t, v, &self.types);
+        for (item, target) in v.iter_mut().zip(targets) {
+            self.init(ast, &target.ok().flatten().unwrap_or(Type::Void), &mut item.init);
+        }
+    }
+}
+
+impl Fold for Caster<'_> {
+    fn fold_decl(&mut self, ast: &mut Ast, id: DeclId) -> DeclId {
+        if let Decl::Func { t: Type::Function { ret, .. }, block: Some(_), .. } = &ast[id] {
+            self.ret = Some((**ret).clone());
+        }
+        let id = visit::fold_walk_decl(self, ast, id);
+        let mut d = std::mem::replace(&mut ast.decls[id.index()], Decl::Tag { t: Type::Void });
+        if let Decl::Var { t, id: _, val: Some(val), spec: _ } = &mut d {
+            self.init(ast, t, val);
+        }
+        ast.decls[id.index()] = d;
+        id
+    }
+
+    fn fold_stmt(&mut self, ast: &mut Ast, id: StmtId) -> StmtId {
+        let id = visit::fold_walk_stmt(self, ast, id);
+        let mut s = std::mem::replace(&mut ast.stmts[id.index()], Stmt::Empty);
+        match &mut s {
+            Stmt::If { cond, .. } | Stmt::While { cond, .. } | Stmt::For { cond: Some(cond), .. } => {
+                *cond = self.convert(ast, *cond, &Type::Bool);
+            },
+            Stmt::Switch { cond, .. } => {
+                if let Some(t) = self.type_of(*cond) {
+                    *cond = self.convert(ast, *cond, &promote(&t));
+                }
+            },
+            Stmt::Return { e: Some(e) } => match self.ret.clone() {
+                Some(Type::Void) | None => {},
+                Some(ret) => *e = self.convert(ast, *e, &ret),
+            },
+            _ => {},
+        }
+        ast.stmts[id.index()] = s;
+        id
+    }
+
+    fn fold_expr(&mut self, ast: &mut Ast, id: ExprId) -> ExprId {
+        let id = visit::fold_walk_expr(self, ast, id);
+        let mut e = std::mem::replace(&mut ast.exprs[id.index()], Expr::IntN { num: 0 });
+        match &mut e {
+            Expr::BinOp { o } => self.binop(ast, o),
+            Expr::UnOp { o } => self.unop(ast, o),
+            Expr::CompoundLit { t, v } => {
+                let t = t.clone();
+                self.items(ast, &t, v);
+            },
+            _ => {},
+        }
+        ast.exprs[id.index()] = e;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{dump, printer};
+    use crate::parse_source;
+    use crate::sema::resolve::resolve;
+    use crate::sema::types::check;
+
+    fn typed(src: &str) -> TypedUnit {
+        let tu = parse_source(src).unwrap();
+        let res = resolve(&tu);
+        let typing = check(&tu, &res);
+        assert!(typing.diags.is_empty(), "{:?}", typing.diags);
+        annotate(tu, &res, typing)
+    }
+
+    #[test]
+    fn casts() {
+        let src = "\
+struct P { double x; int *p; };
+int g(int n, ...);
+int f(char c, float x, int a[2], struct P s) {
+    double d = c + 1;
+    long l[] = { c, [1] = x };
+    char str[] = { \"ab\" }, *strs[] = { \"ab\" };
+    struct P q = { 1, 0 }, ps[] = { 1, 0, 2 };
+    _Bool b = s.p;
+    d += x;
+    switch (c) { default: break; }
+    if (a && !s.p) return g(c, x, a, f);
+    return -c == \"ab\"[0];
+}";
+        let t = typed(src);
+        let s = dump::sexpr(&t.tu);
+        for expected in [
+            "(Var d double :init (ImplicitCast double IntegralToFloating (Add (ImplicitCast int IntegralCast (ImplicitCast char LValueToRValue (Id c))) (IntN 1))))",
+            "(InitList (ImplicitCast long IntegralCast (ImplicitCast char LValueToRValue (Id c))) (Designated designators=[1] (ImplicitCast long FloatingToIntegral",
+            // a string in braces still fills a char array, but decays for a pointer
+            "(Var str char[3] :init (InitList (StrN ab)))",
+            "(Var strs \"char *[1]\" :init (InitList (ImplicitCast \"char *\" ArrayToPointerDecay (StrN ab))))",
+            "(InitList (ImplicitCast double IntegralToFloating (IntN 1)) (ImplicitCast \"int *\" NullToPointer (IntN 0)))",
+            // with braces left out the items go on to the next element
+            "(InitList (ImplicitCast double IntegralToFloating (IntN 1)) (ImplicitCast \"int *\" NullToPointer (IntN 0)) (ImplicitCast double IntegralToFloating (IntN 2)))",
+            "(Var b _Bool :init (ImplicitCast _Bool PointerToBoolean (ImplicitCast \"int *\" LValueToRValue (Member p (Id s)))))",
+            "(AddAssign (Id d) (ImplicitCast double FloatingCast (ImplicitCast float LValueToRValue (Id x))))",
+            "(Switch cases=\"\" default=true :cond (ImplicitCast int IntegralCast (ImplicitCast char LValueToRValue (Id c)))",
+            "(LogAnd (ImplicitCast _Bool PointerToBoolean (ImplicitCast \"int *\" LValueToRValue (Id a)))",
+            // the arguments to `...` are promoted, functions decay
+            "(FuncCall (ImplicitCast \"int (*)(int, ...)\" FunctionToPointerDecay (Id g)) (ImplicitCast int IntegralCast",
+            "(ImplicitCast double FloatingCast (ImplicitCast float LValueToRValue (Id x)))",
+            "(ImplicitCast \"int (*)(char, float, int[2], struct P)\" FunctionToPointerDecay (Id f))",
+            "(Equal (UnMinus (ImplicitCast int IntegralCast (ImplicitCast char LValueToRValue (Id c)))) \
+             (ImplicitCast int IntegralCast (ImplicitCast char LValueToRValue (Subscript (ImplicitCast \"char *\" ArrayToPointerDecay (StrN ab)) (IntN 0)))))",
+        ] {
+            assert!(s.contains(expected), "{} not in\n{}", expected, s);
+        }
+
+        // every expression has a type, and a cast the span of its operand
+        assert_eq!(t.types.len(), t.tu.ast.exprs.len());
+        assert!(t.types.iter().all(Option::is_some));
+        for (i, e) in t.tu.ast.exprs.iter().enumerate() {
+            if let Expr::ImplicitCast { kind: _, t: ty, e } = e {
+                assert_eq!(t.types[i].as_ref(), Some(ty));
+                assert_eq!(t.tu.spans.exprs[i], t.tu.spans.expr(*e));
+            }
+        }
+        // the casts aren't written in C
+        assert_eq!(printer::print(&t.tu), printer::print(&parse_source(src).unwrap()));
+    }
+
+    #[test]
+    fn null_pointers() {
+        let t = typed("enum { Z };\nint *a = 1 - 1, *b = Z, *c = (void *)0;");
+        let s = dump::sexpr(&t.tu);
+        for expected in [
+            "(Var a \"int *\" :init (ImplicitCast \"int *\" NullToPointer (Sub (IntN 1) (IntN 1))))",
+            "(Var b \"int *\" :init (ImplicitCast \"int *\" NullToPointer (EnumN Z 0)))",
+            "(Var c \"int *\" :init (ImplicitCast \"int *\" BitCast (TypeCast \"void *\" (IntN 0))))",
+        ] {
+            assert!(s.contains(expected), "{} not in\n{}", expected, s);
+        }
+
+        // a 0 added to a pointer is an offset, only compared with one it is a null pointer
+        let t = typed("int *p;\nint f(void) { p + 0; 0 + p; p - 0; return p == 0; }");
+        let s = dump::sexpr(&t.tu);
+        assert!(s.contains("(Add (ImplicitCast \"int *\" LValueToRValue (Id p)) (IntN 0))"), "{}", s);
+        assert!(s.contains("(Add (IntN 0) (ImplicitCast \"int *\" LValueToRValue (Id p)))"), "{}", s);
+        assert_eq!(s.matches("NullToPointer").count(), 1, "{}", s);
+
+        // an integer that isn't a null pointer constant only gets to a pointer in a tree with errors, and stays as it is
+        let tu = parse_source("int *p = 5;").unwrap();
+        let res = resolve(&tu);
+        let typing = check(&tu, &res);
+        assert_eq!(typing.diags.len(), 1);
+        let t = annotate(tu, &res, typing);
+        assert_eq!(dump::sexpr(&t.tu), "(TranslationUnit\n  (Var p \"int *\" :init (IntN 5)))\n");
+    }
+}
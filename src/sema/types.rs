@@ -0,0 +1,1073 @@
+//! Type checking: gives every expression its type and checks the operands of every operator, the conditions of
+//! statements, initializers, arguments and returned values against C's rules, for a target where int is 32 bits
+//! and long and pointers are 64. Arithmetic operands go through the integer promotions and the usual arithmetic
+//! conversions, arrays and functions used as values decay to pointers, and pointers only take part in the
+//! arithmetic and comparisons C allows.
+//!
+//! An expression with an error in it gets no type, and neither does anything around it, so one mistake is
+//! reported once. Identifiers the resolver couldn't bind have no type either, as the resolver reported them.
+
+use std::collections::HashSet;
+
+use crate::lex::lexer::Span;
+use crate::parse::parser::*;
+use crate::parse::printer::type_name;
+use crate::parse::visit::{self, Visitor};
+use crate::sema::diag::Diagnostic;
+use crate::sema::resolve::{Binding, Resolution};
+
+#[derive(Debug)]
+pub struct Typing {
+    /// type of every expression as written, before any conversion its parent applies to it, a side table indexed
+    /// like `Ast::exprs`: an array is still an array. None where the expression or something in it is in error.
+    pub types: Vec<Option<Type>>,
+    pub diags: Vec<Diagnostic>,
+}
+
+impl Typing {
+    pub fn type_of(&self, id: ExprId) -> Option<&Type> {
+        self.types.get(id.index())?.as_ref()
+    }
+}
+
+pub fn check(tu: &TranslationUnit, res: &Resolution) -> Typing {
+    let n = tu.ast.exprs.len();
+    let mut c = Checker {
+        spans: &tu.spans, res, types: vec![None; n], seen: vec![false; n], diags: Vec::new(),
+        records: HashSet::new(), func: None,
+    };
+    c.visit_translation_unit(tu);
+    Typing { types: c.types, diags: c.diags }
+}
+
+pub fn is_arithmetic(t: &Type) -> bool {
+    t.is_integer() || t.is_floating()
+}
+
+pub fn is_scalar(t: &Type) -> bool {
+    is_arithmetic(t) || matches!(t.unqualified(), Type::Ptr { .. })
+}
+
+// Integer conversion rank, which doesn't depend on signedness
+fn rank(t: &Type) -> u8 {
+    match t.unqualified() {
+        Type::Char | Type::SChar | Type::UChar => 1,
+        Type::Short | Type::UShort => 2,
+        Type::Int | Type::UInt | Type::Enum { .. } => 3,
+        Type::Long | Type::ULong => 4,
+        Type::LongLong | Type::ULongLong => 5,
+        _ => 0,
+    }
+}
+
+fn is_unsigned(t: &Type) -> bool {
+    matches!(t.unqualified(), Type::Bool | Type::UChar | Type::UShort | Type::UInt | Type::ULong | Type::ULongLong)
+}
+
+// Size in bytes of an integer type
+fn width(t: &Type) -> u8 {
+    match rank(t) {
+        0 | 1 => 1,
+        2 => 2,
+        3 => 4,
+        _ => 8,
+    }
+}
+
+/// Integer promotions: everything narrower than int becomes int, which holds all of their values.
+/// Other types stay as they are, without their qualifiers.
+pub fn promote(t: &Type) -> Type {
+    match t.unqualified() {
+        Type::Bool | Type::Char | Type::SChar | Type::UChar | Type::Short | Type::UShort | Type::Enum { .. } => Type::Int,
+        t => t.clone(),
+    }
+}
+
+/// The usual arithmetic conversions: the common type two arithmetic operands are converted to
+pub fn arith_conv(a: &Type, b: &Type) -> Type {
+    for t in [Type::LongDouble, Type::Double, Type::Float] {
+        if *a.unqualified() == t || *b.unqualified() == t {
+            return t;
+        }
+    }
+    let (a, b) = (promote(a), promote(b));
+    if a == b {
+        return a;
+    }
+    let (hi, lo) = if rank(&a) >= rank(&b) { (a, b) } else { (b, a) };
+    if is_unsigned(&hi) || !is_unsigned(&lo) {
+        return hi;
+    }
+    // a signed type and an unsigned one of lower or equal rank: the signed type if it holds all values of the other
+    if width(&hi) > width(&lo) {
+        return hi;
+    }
+    match hi {
+        Type::Int => Type::UInt,
+        Type::Long => Type::ULong,
+        _ => Type::ULongLong,
+    }
+}
+
+/// The type of an operand's value: arrays become pointers to their first element and functions pointers to
+/// themselves, and other types lose their qualifiers, since a value isn't an object
+pub fn decay(t: &Type) -> Type {
+    match t {
+        Type::Array { t, n: _ } => Type::Ptr { t: t.clone() },
+        Type::Function { .. } => Type::Ptr { t: Box::new(t.clone()) },
+        Type::Qualified { t: inner, q } => match &**inner {
+            // qualifiers of an array are its elements'
+            Type::Array { t, n: _ } => Type::Ptr { t: Box::new((**t).clone().qualify(q.clone())) },
+            _ => decay(inner),
+        },
+        t => t.clone(),
+    }
+}
+
+/// Parameters declared as arrays or functions are pointers
+pub fn adjust_param(t: &Type) -> Type {
+    match t.unqualified() {
+        Type::Array { .. } | Type::Function { .. } => decay(t),
+        _ => t.clone(),
+    }
+}
+
+fn quals(t: &Type) -> Quals {
+    match t {
+        Type::Qualified { t: _, q } => q.clone(),
+        _ => Quals::default(),
+    }
+}
+
+/// Whether two types are compatible, so that pointers to them can be mixed. Array sizes aren't compared, and
+/// a function type without a prototype is compatible with any other returning the same type that doesn't take `...`.
+pub fn compatible(a: &Type, b: &Type) -> bool {
+    if quals(a) != quals(b) {
+        return false;
+    }
+    match (a.unqualified(), b.unqualified()) {
+        (Type::Ptr { t: a }, Type::Ptr { t: b }) => compatible(a, b),
+        (Type::Array { t: a, n: _ }, Type::Array { t: b, n: _ }) => compatible(a, b),
+        (
+            Type::Function { ret: ra, params: pa, variadic: va, prototype: proto_a },
+            Type::Function { ret: rb, params: pb, variadic: vb, prototype: proto_b },
+        ) => {
+            // parameters are compared as the function sees them, so `int a[]` is `int *a` and `const int` is `int`
+            let param = |t: &Type| adjust_param(t).unqualified().clone();
+            compatible(ra, rb) && (!proto_a && !vb || !proto_b && !va ||
+                va == vb && pa.len() == pb.len() && pa.iter().zip(pb).all(|(a, b)| compatible(&param(a), &param(b))))
+        },
+        (a, b) => a == b,
+    }
+}
+
+/// Whether objects of a type have a known size
+pub fn is_complete(ast: &Ast, t: &Type) -> bool {
+    match t.unqualified() {
+        Type::Struct { tag: _, id } | Type::Union { tag: _, id } => ast.records[*id].members.is_some(),
+        Type::Enum { tag: _, id } => ast.enums[*id].consts.is_some(),
+        Type::Array { t, n } => n.is_some() && is_complete(ast, t),
+        Type::Void | Type::Function { .. } => false,
+        _ => true,
+    }
+}
+
+/// Whether an expression designates an object, which can be assigned to or have its address taken
+pub fn is_lvalue(ast: &Ast, res: &Resolution, id: ExprId) -> bool {
+    match &ast[id] {
+        Expr::Id { .. } => match res.binding(id) {
+            Some(Binding::Decl(d)) => matches!(ast[d], Decl::Var { .. } | Decl::Arg { .. }),
+            _ => false,
+        },
+        Expr::StrN { .. } | Expr::CompoundLit { .. } => true,
+        Expr::UnOp { o } => match o {
+            UnOp::Indirection { .. } | UnOp::Subscript { .. } | UnOp::PtrMember { .. } => true,
+            UnOp::Member { e, id: _ } => is_lvalue(ast, res, *e),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// An integer constant expression equal to 0, or one cast to `void *`
+pub fn is_null_pointer(ast: &Ast, id: ExprId, t: &Type) -> bool {
+    match &ast[id] {
+        Expr::UnOp { o: UnOp::TypeCast { t: Type::Ptr { t: to }, e } } if **to == Type::Void => const_eval(ast, *e) == Ok(0),
+        _ => t.is_integer() && const_eval(ast, id) == Ok(0),
+    }
+}
+
+/// Whether a value of type `from`, already decayed, can be stored in an object of type `to` as by assignment;
+/// `null` is whether it is a null pointer constant. Qualifiers a pointer drops from what it points to don't count.
+pub fn assignable(to: &Type, from: &Type, null: bool) -> bool {
+    match (to.unqualified(), from.unqualified()) {
+        (to, from) if is_arithmetic(to) && is_arithmetic(from) => true,
+        (Type::Bool, Type::Ptr { .. }) => true,
+        (Type::Ptr { t: a }, Type::Ptr { t: b }) => {
+            let (a, b) = (a.unqualified(), b.unqualified());
+            *a == Type::Void || *b == Type::Void || compatible(a, b)
+        },
+        (Type::Ptr { .. }, _) => null,
+        (to @ (Type::Struct { .. } | Type::Union { .. } | Type::VaList), from) => to == from,
+        _ => false,
+    }
+}
+
+/// Whether `init` is a string literal filling a char array, as in `char s[] = "abc"` or `char s[] = {"abc"}`
+pub fn is_string_init(ast: &Ast, t: &Type, init: &Init) -> bool {
+    let e = match init {
+        Init::Expr { e } => *e,
+        Init::List { v } => match &v[..] {
+            [InitItem { d, init: Init::Expr { e } }] if d.is_empty() => *e,
+            _ => return false,
+        },
+    };
+    matches!(t.unqualified(), Type::Array { t, n: _ } if rank(t) == 1) && matches!(ast[e], Expr::StrN { .. })
+}
+
+/// Type of member `name` of a record, looking into the anonymous structs and unions in it too
+pub fn find_member(ast: &Ast, id: usize, name: &str) -> Option<Type> {
+    for m in ast.records[id].members.iter().flatten() {
+        match (&m.id, m.t.unqualified()) {
+            (Some(id), _) if id == name => return Some(m.t.clone()),
+            (None, Type::Struct { tag: _, id } | Type::Union { tag: _, id }) => {
+                if let Some(t) = find_member(ast, *id, name) {
+                    return Some(t.qualify(quals(&m.t)));
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// What each item of an initializer list for an object of type `t` initializes, in the order of the items. With braces
+/// left out, as in `int m[2][2] = {1, 2, 3, 4}`, a value goes to the first scalar of the aggregate it starts unless it
+/// is one of the same type, and the items after it fill the rest of that aggregate. `types` are the expression types
+/// `check` found. None where nothing is known about the target, like a member of an incomplete struct, and a message
+/// for an item that has no place in the object.
+pub fn initialized(ast: &Ast, t: &Type, v: &[InitItem], types: &[Option<Type>]) -> Vec<Result<Option<Type>, String>> {
+    let mut items = Items { ast, v, types, i: 0, out: Vec::with_capacity(v.len()) };
+    items.fill(t, 0, true);
+    items.out
+}
+
+// Number of elements or members an initializer list can fill, None for an array of unknown size and an incomplete struct.
+// A scalar in braces is its own only element.
+fn length(ast: &Ast, t: &Type) -> Option<usize> {
+    match t.unqualified() {
+        Type::Array { t: _, n } => usize::try_from(const_eval(ast, (*n)?).ok()?).ok(),
+        Type::Struct { tag: _, id } => Some(ast.records[*id].members.as_ref()?.len()),
+        _ => Some(1),
+    }
+}
+
+// Type of the element or member number `i` of an aggregate, in the order an initializer list goes through them
+fn element(ast: &Ast, t: &Type, i: usize) -> Option<Type> {
+    match t.unqualified() {
+        Type::Array { t, n: _ } => Some((**t).clone()),
+        Type::Struct { tag: _, id } => Some(ast.records[*id].members.as_ref()?.get(i)?.t.clone()),
+        Type::Union { tag: _, id } if i == 0 => Some(ast.records[*id].members.as_ref()?.first()?.t.clone()),
+        t if i == 0 => Some(t.clone()),
+        _ => None,
+    }
+}
+
+// Where a designator points in an object of type `t`: the element or member number and its type,
+// and whether it is an anonymous struct or union that the designator is to be looked up in again
+fn locate(ast: &Ast, t: &Type, d: &Designator) -> Result<(usize, Option<Type>, bool), String> {
+    match (d, t.unqualified()) {
+        (Designator::Index { e: _, n }, Type::Array { t: elem, n: _ }) => {
+            // the parser rejected negative indices
+            let n = *n as usize;
+            match length(ast, t) {
+                Some(len) if n >= len => Err(format!("array designator index ({}) exceeds array bounds ({})", n, len)),
+                _ => Ok((n, Some((**elem).clone()), false)),
+            }
+        },
+        (Designator::Index { .. }, _) => Err(format!("array designator cannot initialize non-array type '{}'", type_name(ast, t))),
+        (Designator::Member { id }, Type::Struct { tag: _, id: r } | Type::Union { tag: _, id: r }) => {
+            let Some(members) = &ast.records[*r].members else {
+                return Ok((0, None, false));
+            };
+            for (i, m) in members.iter().enumerate() {
+                match (&m.id, m.t.unqualified()) {
+                    (Some(name), _) if name == id => return Ok((i, Some(m.t.clone()), false)),
+                    (None, Type::Struct { tag: _, id: a } | Type::Union { tag: _, id: a }) if find_member(ast, *a, id).is_some() => {
+                        return Ok((i, Some(m.t.clone()), true));
+                    },
+                    _ => {},
+                }
+            }
+            Err(format!("field designator '{}' does not refer to any field in type '{}'", id, type_name(ast, t)))
+        },
+        (Designator::Member { .. }, _) => {
+            Err(format!("field designator cannot initialize a non-struct, non-union type '{}'", type_name(ast, t)))
+        },
+    }
+}
+
+// The walk of `initialized` through the items of one list
+struct Items<'a> {
+    ast: &'a Ast,
+    v: &'a [InitItem],
+    types: &'a [Option<Type>],
+    // the next item, and what the ones before it initialize
+    i: usize,
+    out: Vec<Result<Option<Type>, String>>,
+}
+
+impl Items<'_> {
+    // Places items in an object of type `t`: all that are left if it has braces of its own, otherwise until it is full
+    // or a designator starts over from the enclosing braces. The first `skip` designators of the first item are used up.
+    fn fill(&mut self, t: &Type, mut skip: usize, braced: bool) {
+        let (ast, v) = (self.ast, self.v);
+        let len = length(ast, t);
+        let (start, mut next, mut excess) = (self.i, 0, false);
+        while let Some(item) = v.get(self.i) {
+            let d = &item.d[skip..];
+            if !braced && self.i > start && !d.is_empty() {
+                return;
+            }
+            let target = match d.first() {
+                Some(first) => match locate(ast, t, first) {
+                    Ok((pos, target, anonymous)) => {
+                        next = pos;
+                        // `.a.x = 1` and `.x = 1` for a member of an anonymous struct go on inside the member
+                        if d.len() > 1 || anonymous {
+                            self.nested(target, skip + !anonymous as usize);
+                            (next, skip) = (next + 1, 0);
+                            continue;
+                        }
+                        target
+                    },
+                    Err(msg) => {
+                        self.out.push(Err(msg));
+                        (self.i, skip) = (self.i + 1, 0);
+                        continue;
+                    },
+                },
+                None if len.is_some_and(|len| next >= len) => {
+                    if !braced {
+                        return;
+                    }
+                    let what = match t.unqualified() {
+                        Type::Array { .. } => "array",
+                        Type::Struct { .. } => "struct",
+                        Type::Union { .. } => "union",
+                        _ => "scalar",
+                    };
+                    // reported once for a list
+                    self.out.push(if excess { Ok(None) } else { Err(format!("excess elements in {} initializer", what)) });
+                    (self.i, excess) = (self.i + 1, true);
+                    continue;
+                },
+                None => element(ast, t, next),
+            };
+            match (&item.init, target) {
+                (Init::Expr { e }, Some(target)) if self.elided(&target, &item.init, *e) => self.nested(Some(target), skip + d.len()),
+                (_, target) => {
+                    self.out.push(Ok(target));
+                    self.i += 1;
+                },
+            }
+            (next, skip) = (next + 1, 0);
+        }
+    }
+
+    // Fills a subobject from the items from here on, which have no braces of their own
+    fn nested(&mut self, t: Option<Type>, skip: usize) {
+        let start = self.i;
+        if let Some(t) = t {
+            self.fill(&t, skip, false);
+        }
+        if self.i == start {
+            self.out.push(Ok(None));
+            self.i += 1;
+        }
+    }
+
+    // Whether a value for an object of type `t` starts filling it instead of initializing it whole
+    fn elided(&self, t: &Type, init: &Init, e: ExprId) -> bool {
+        matches!(t.unqualified(), Type::Array { .. } | Type::Struct { .. } | Type::Union { .. })
+            && !is_string_init(self.ast, t, init)
+            && self.types.get(e.index()).cloned().flatten().is_some_and(|from| from.unqualified() != t.unqualified())
+    }
+}
+
+// Where a value is converted to a type as if by assignment, for the message when it can't be
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+enum Conversion {
+    Assign,
+    Init,
+    Pass,
+    Return,
+}
+
+// The expression an error about an initializer item points to: its first index or else its first value
+fn item_expr(item: &InitItem) -> Option<ExprId> {
+    let index = item.d.iter().find_map(|d| match d {
+        Designator::Index { e, n: _ } => Some(*e),
+        Designator::Member { .. } => None,
+    });
+    index.or_else(|| match &item.init {
+        Init::Expr { e } => Some(*e),
+        Init::List { v } => v.iter().find_map(item_expr),
+    })
+}
+
+struct Checker<'a> {
+    spans: &'a Spans,
+    res: &'a Resolution,
+    types: Vec<Option<Type>>,
+    // expressions typed already, which the visitor reaches again through their parents
+    seen: Vec<bool>,
+    diags: Vec<Diagnostic>,
+    // records whose member types were visited
+    records: HashSet<usize>,
+    // name and return type of the function whose body is being checked
+    func: Option<(String, Type)>,
+}
+
+impl Checker<'_> {
+    fn error<T>(&mut self, msg: impl Into<String>, id: ExprId) -> Option<T> {
+        self.diags.push(Diagnostic::error(msg, self.spans.expr(id)));
+        None
+    }
+
+    // Types an expression and everything in it, the first time it's reached
+    fn expr(&mut self, ast: &Ast, id: ExprId) -> Option<Type> {
+        if !self.seen[id.index()] {
+            self.seen[id.index()] = true;
+            self.types[id.index()] = self.compute(ast, id);
+        }
+        self.types[id.index()].clone()
+    }
+
+    fn compute(&mut self, ast: &Ast, id: ExprId) -> Option<Type> {
+        match &ast[id] {
+            // character constants are ints in C
+            Expr::IntN { .. } | Expr::CharN { .. } | Expr::EnumN { .. } => Some(Type::Int),
+            Expr::FloatN { .. } => Some(Type::Double),
+            Expr::StrN { s: _, n } => {
+                visit::walk_expr(self, ast, id);
+                Some(Type::Array { t: Box::new(Type::Char), n: Some(*n) })
+            },
+            Expr::Id { s } => match self.res.binding(id)? {
+                Binding::Enumerator { .. } => Some(Type::Int),
+                Binding::Decl(d) => match &ast[d] {
+                    Decl::Var { t, .. } | Decl::Func { t, .. } => Some(t.clone()),
+                    Decl::Arg { t, .. } => Some(adjust_param(t)),
+                    Decl::Typedef { .. } | Decl::Tag { .. } => self.error(format!("unexpected type name '{}': expected expression", s), id),
+                },
+            },
+            Expr::CompoundLit { t, v } => {
+                visit::walk_expr(self, ast, id);
+                self.items(ast, t, v, self.spans.expr(id));
+                Some(t.clone())
+            },
+            Expr::BinOp { o } => self.binop(ast, id, o),
+            Expr::UnOp { o } => self.unop(ast, id, o),
+            // a tree with the conversions in it already
+            Expr::ImplicitCast { kind: _, t, e } => self.expr(ast, *e).map(|_| t.clone()),
+        }
+    }
+
+    fn binop(&mut self, ast: &Ast, id: ExprId, o: &BinOp) -> Option<Type> {
+        let (le, re) = o.operands();
+        let (lt, rt) = (self.expr(ast, le), self.expr(ast, re));
+        let (lt, rt) = (lt?, rt?);
+        let (l, r) = (decay(&lt), decay(&rt));
+        let ptr = |t: &Type| matches!(t, Type::Ptr { .. });
+        let arith = is_arithmetic(&l) && is_arithmetic(&r);
+        let ints = l.is_integer() && r.is_integer();
+        let t = match o {
+            BinOp::Comma { .. } => Some(r),
+            BinOp::Assign { .. } => {
+                self.modifiable(ast, le, &lt)?;
+                self.convert(ast, re, &rt, &lt, Conversion::Assign)?;
+                Some(lt.unqualified().clone())
+            },
+            BinOp::AddAssign { .. } | BinOp::SubAssign { .. } if ptr(&l) && r.is_integer() => {
+                self.modifiable(ast, le, &lt)?;
+                self.pointer_arith(ast, &l, id)?;
+                Some(lt.unqualified().clone())
+            },
+            BinOp::AddAssign { .. } | BinOp::SubAssign { .. } | BinOp::MulAssign { .. } | BinOp::DivAssign { .. } if arith => {
+                self.modifiable(ast, le, &lt)?;
+                Some(lt.unqualified().clone())
+            },
+            BinOp::ModAssign { .. } if ints => {
+                self.modifiable(ast, le, &lt)?;
+                Some(lt.unqualified().clone())
+            },
+            BinOp::LogOr { .. } | BinOp::LogAnd { .. } if is_scalar(&l) && is_scalar(&r) => Some(Type::Int),
+            BinOp::Equal { .. } | BinOp::NotEqual { .. } |
+            BinOp::Bigger { .. } | BinOp::Lesser { .. } | BinOp::BiggerEqual { .. } | BinOp::LesserEqual { .. } => {
+                let equality = matches!(o, BinOp::Equal { .. } | BinOp::NotEqual { .. });
+                match (&l, &r) {
+                    _ if arith => Some(Type::Int),
+                    (Type::Ptr { t: a }, Type::Ptr { t: b }) => {
+                        let (a, b) = (a.unqualified(), b.unqualified());
+                        if !compatible(a, b) && !(equality && (*a == Type::Void || *b == Type::Void)) {
+                            return self.error(format!("comparison of distinct pointer types ('{}' and '{}')", type_name(ast, &lt), type_name(ast, &rt)), id);
+                        }
+                        Some(Type::Int)
+                    },
+                    (Type::Ptr { .. }, _) if equality && is_null_pointer(ast, re, &r) => Some(Type::Int),
+                    (_, Type::Ptr { .. }) if equality && is_null_pointer(ast, le, &l) => Some(Type::Int),
+                    _ => None,
+                }
+            },
+            BinOp::Add { .. } | BinOp::Sub { .. } | BinOp::Mul { .. } | BinOp::Div { .. } if arith => Some(arith_conv(&l, &r)),
+            BinOp::Mod { .. } if ints => Some(arith_conv(&l, &r)),
+            BinOp::Add { .. } | BinOp::Sub { .. } if ptr(&l) && r.is_integer() => {
+                self.pointer_arith(ast, &l, id)?;
+                Some(l)
+            },
+            BinOp::Add { .. } if l.is_integer() && ptr(&r) => {
+                self.pointer_arith(ast, &r, id)?;
+                Some(r)
+            },
+            BinOp::Sub { .. } if ptr(&l) && ptr(&r) => {
+                let (Type::Ptr { t: a }, Type::Ptr { t: b }) = (&l, &r) else { unreachable!() };
+                if !compatible(a.unqualified(), b.unqualified()) {
+                    return self.error(format!("'{}' and '{}' are not pointers to compatible types", type_name(ast, &lt), type_name(ast, &rt)), id);
+                }
+                self.pointer_arith(ast, &l, id)?;
+                // ptrdiff_t
+                Some(Type::Long)
+            },
+            _ => None,
+        };
+        match t {
+            Some(t) => Some(t),
+            None => self.error(format!("invalid operands to binary expression ('{}' and '{}')", type_name(ast, &lt), type_name(ast, &rt)), id),
+        }
+    }
+
+    fn unop(&mut self, ast: &Ast, id: ExprId, o: &UnOp) -> Option<Type> {
+        match o {
+            UnOp::UnPlus { e } | UnOp::UnMinus { e } | UnOp::LogNot { e } => {
+                let t = self.expr(ast, *e)?;
+                let v = decay(&t);
+                match o {
+                    UnOp::LogNot { .. } if is_scalar(&v) => Some(Type::Int),
+                    UnOp::UnPlus { .. } | UnOp::UnMinus { .. } if is_arithmetic(&v) => Some(promote(&v)),
+                    _ => self.error(format!("invalid argument type '{}' to unary expression", type_name(ast, &t)), id),
+                }
+            },
+            UnOp::PreInc { e } | UnOp::PostInc { e } | UnOp::PreDec { e } | UnOp::PostDec { e } => {
+                let t = self.expr(ast, *e)?;
+                let v = decay(&t);
+                if !is_scalar(&v) {
+                    let what = if matches!(o, UnOp::PreInc { .. } | UnOp::PostInc { .. }) { "increment" } else { "decrement" };
+                    return self.error(format!("cannot {} value of type '{}'", what, type_name(ast, &t)), id);
+                }
+                self.modifiable(ast, *e, &t)?;
+                self.pointer_arith(ast, &v, id)?;
+                Some(v)
+            },
+            UnOp::Indirection { e } => {
+                let t = self.expr(ast, *e)?;
+                match decay(&t) {
+                    Type::Ptr { t } => Some(*t),
+                    _ => self.error(format!("indirection requires pointer operand ('{}' invalid)", type_name(ast, &t)), id),
+                }
+            },
+            UnOp::AddressOf { e } => {
+                let t = self.expr(ast, *e)?;
+                if !matches!(t, Type::Function { .. }) && !is_lvalue(ast, self.res, *e) {
+                    return self.error(format!("cannot take the address of an rvalue of type '{}'", type_name(ast, &t)), id);
+                }
+                Some(Type::Ptr { t: Box::new(t) })
+            },
+            UnOp::Subscript { le, re } => {
+                let (lt, rt) = (self.expr(ast, *le), self.expr(ast, *re));
+                let (l, r) = (decay(&lt?), decay(&rt?));
+                // `i[a]` is `a[i]` too
+                let (p, i) = match (&l, &r) {
+                    (Type::Ptr { .. }, _) => (l, r),
+                    (_, Type::Ptr { .. }) => (r, l),
+                    _ => return self.error("subscripted value is not an array or pointer", id),
+                };
+                if !i.is_integer() {
+                    return self.error("array subscript is not an integer", id);
+                }
+                self.pointer_arith(ast, &p, id)?;
+                let Type::Ptr { t } = p else { unreachable!() };
+                Some(*t)
+            },
+            UnOp::FuncCall { e, args } => {
+                let f = self.expr(ast, *e);
+                let types: Vec<_> = args.iter().map(|arg| self.expr(ast, *arg)).collect();
+                let f = f?;
+                let (ret, params, variadic, prototype) = match decay(&f) {
+                    Type::Ptr { t } => match *t {
+                        Type::Function { ret, params, variadic, prototype } => (ret, params, variadic, prototype),
+                        _ => return self.error(format!("called object type '{}' is not a function or function pointer", type_name(ast, &f)), id),
+                    },
+                    _ => return self.error(format!("called object type '{}' is not a function or function pointer", type_name(ast, &f)), id),
+                };
+                // a function declared without a prototype doesn't say what it takes
+                if prototype {
+                    if args.len() < params.len() {
+                        return self.error(format!("too few arguments to function call, expected {}, have {}", params.len(), args.len()), id);
+                    }
+                    if args.len() > params.len() && !variadic {
+                        return self.error(format!("too many arguments to function call, expected {}, have {}", params.len(), args.len()), id);
+                    }
+                }
+                let mut ok = true;
+                for ((arg, t), param) in args.iter().zip(types).zip(&params) {
+                    ok &= t.is_some_and(|t| self.convert(ast, *arg, &t, &adjust_param(param), Conversion::Pass).is_some());
+                }
+                match ok {
+                    true => Some(ret.unqualified().clone()),
+                    false => None,
+                }
+            },
+            UnOp::Member { e, id: name } | UnOp::PtrMember { e, id: name } => {
+                let t = self.expr(ast, *e)?;
+                let base = match (o, decay(&t)) {
+                    (UnOp::Member { .. }, _) => t.clone(),
+                    (_, Type::Ptr { t }) => *t,
+                    _ => return self.error(format!("member reference type '{}' is not a pointer", type_name(ast, &t)), id),
+                };
+                let record = match base.unqualified() {
+                    Type::Struct { tag: _, id } | Type::Union { tag: _, id } => *id,
+                    _ => return self.error(format!("member reference base type '{}' is not a structure or union", type_name(ast, &base)), id),
+                };
+                if ast.records[record].members.is_none() {
+                    return self.error(format!("incomplete definition of type '{}'", type_name(ast, base.unqualified())), id);
+                }
+                match find_member(ast, record, name) {
+                    Some(m) => Some(m.qualify(quals(&base))),
+                    None => self.error(format!("no member named '{}' in '{}'", name, type_name(ast, base.unqualified())), id),
+                }
+            },
+            UnOp::TypeCast { t, e } => {
+                self.visit_type(ast, t);
+                let from = self.expr(ast, *e)?;
+                let v = decay(&from);
+                let to = t.unqualified();
+                let msg = match to {
+                    Type::Void => None,
+                    _ if !is_scalar(to) => Some(format!("used type '{}' where arithmetic or pointer type is required", type_name(ast, t))),
+                    _ if !is_scalar(&v) => Some(format!("operand of type '{}' where arithmetic or pointer type is required", type_name(ast, &from))),
+                    Type::Ptr { .. } if v.is_floating() => Some(format!("operand of type '{}' cannot be cast to a pointer type", type_name(ast, &from))),
+                    _ if to.is_floating() && matches!(v, Type::Ptr { .. }) => Some(format!("pointer cannot be cast to type '{}'", type_name(ast, t))),
+                    _ => None,
+                };
+                match msg {
+                    Some(msg) => self.error(msg, id),
+                    None => Some(to.clone()),
+                }
+            },
+            UnOp::SizeOf { e } => {
+                let t = self.expr(ast, *e)?;
+                self.sizeof(ast, &t, id)
+            },
+            UnOp::SizeOfType { t } | UnOp::AlignOf { t } => {
+                self.visit_type(ast, t);
+                self.sizeof(ast, t, id)
+            },
+            UnOp::VaStart { e: le, last: re } | UnOp::VaCopy { le, re } => {
+                let (lt, rt) = (self.expr(ast, *le), self.expr(ast, *re));
+                lt.and(rt).map(|_| Type::Void)
+            },
+            UnOp::VaEnd { e } => self.expr(ast, *e).map(|_| Type::Void),
+            UnOp::VaArg { e, t } => {
+                self.visit_type(ast, t);
+                self.expr(ast, *e).map(|_| t.clone())
+            },
+        }
+    }
+
+    // `sizeof` and `_Alignof` give a size_t
+    fn sizeof(&mut self, ast: &Ast, t: &Type, id: ExprId) -> Option<Type> {
+        match t.unqualified() {
+            Type::Function { .. } => self.error("invalid application of 'sizeof' to a function type", id),
+            _ if !is_complete(ast, t) => {
+                self.error(format!("invalid application of 'sizeof' to an incomplete type '{}'", type_name(ast, t)), id)
+            },
+            _ => Some(Type::ULong),
+        }
+    }
+
+    // Arithmetic on a pointer needs the size of what it points to
+    fn pointer_arith(&mut self, ast: &Ast, p: &Type, id: ExprId) -> Option<()> {
+        let Type::Ptr { t } = p else {
+            return Some(());
+        };
+        let msg = match t.unqualified() {
+            Type::Void => "arithmetic on a pointer to void".to_string(),
+            Type::Function { .. } => format!("arithmetic on a pointer to the function type '{}'", type_name(ast, t)),
+            _ if !is_complete(ast, t) => format!("arithmetic on a pointer to an incomplete type '{}'", type_name(ast, t)),
+            _ => return Some(()),
+        };
+        self.error(msg, id)
+    }
+
+    // Checks that the operand of an assignment, `++` or `--` is an object that can change
+    fn modifiable(&mut self, ast: &Ast, e: ExprId, t: &Type) -> Option<()> {
+        let msg = match t.unqualified() {
+            Type::Function { .. } => "expression is not assignable".to_string(),
+            _ if !is_lvalue(ast, self.res, e) => "expression is not assignable".to_string(),
+            Type::Array { .. } => format!("array type '{}' is not assignable", type_name(ast, t)),
+            _ if quals(t).is_const => format!("cannot assign to an object of const-qualified type '{}'", type_name(ast, t)),
+            _ => return Some(()),
+        };
+        self.error(msg, e)
+    }
+
+    // Checks that the value of `e`, of type `from`, can be converted to `to` as if by assignment
+    fn convert(&mut self, ast: &Ast, e: ExprId, from: &Type, to: &Type, how: Conversion) -> Option<()> {
+        let v = decay(from);
+        if assignable(to, &v, is_null_pointer(ast, e, &v)) {
+            return Some(());
+        }
+        let (to, from) = (type_name(ast, to), type_name(ast, from));
+        let msg = match how {
+            Conversion::Assign => format!("assigning to '{}' from incompatible type '{}'", to, from),
+            Conversion::Init => format!("initializing '{}' with an expression of incompatible type '{}'", to, from),
+            Conversion::Pass => format!("passing '{}' to parameter of incompatible type '{}'", from, to),
+            Conversion::Return => format!("returning '{}' from a function with incompatible result type '{}'", from, to),
+        };
+        self.error(msg, e)
+    }
+
+    // The controlling expression of `if`, a loop or `switch`
+    fn condition(&mut self, ast: &Ast, e: ExprId, switch: bool) {
+        let Some(t) = self.expr(ast, e) else {
+            return;
+        };
+        let v = decay(&t);
+        if switch && !v.is_integer() {
+            self.error::<()>(format!("statement requires expression of integer type ('{}' invalid)", type_name(ast, &t)), e);
+        } else if !is_scalar(&v) {
+            self.error::<()>(format!("statement requires expression of scalar type ('{}' invalid)", type_name(ast, &t)), e);
+        }
+    }
+
+    fn ret(&mut self, ast: &Ast, id: StmtId, e: Option<ExprId>) {
+        let Some((name, ret)) = self.func.clone() else {
+            return;
+        };
+        let void = *ret.unqualified() == Type::Void;
+        let msg = match e.map(|e| (e, self.expr(ast, e))) {
+            Some((_, None)) => None,
+            // a void function can return the result of another
+            Some((_, Some(t))) if void && *t.unqualified() == Type::Void => None,
+            Some(_) if void => Some(format!("void function '{}' should not return a value", name)),
+            Some((e, Some(t))) => {
+                self.convert(ast, e, &t, &ret, Conversion::Return);
+                None
+            },
+            None if !void => Some(format!("non-void function '{}' should return a value", name)),
+            None => None,
+        };
+        if let Some(msg) = msg {
+            self.diags.push(Diagnostic::error(msg, self.spans.stmt(id)));
+        }
+    }
+
+    // An initializer for an object of type `t`; `at` is where an error goes that no expression of it can point to
+    fn initializer(&mut self, ast: &Ast, t: &Type, init: &Init, at: Span) {
+        match init {
+            Init::Expr { e } => self.init(ast, t, *e),
+            Init::List { v } => self.items(ast, t, v, at),
+        }
+    }
+
+    // The items of an initializer list or a compound literal
+    fn items(&mut self, ast: &Ast, t: &Type, v: &[InitItem], at: Span) {
+        if let [InitItem { d, init }] = v {
+            if d.is_empty() && is_string_init(ast, t, init) {
+                return;
+            }
+        }
+        for (item, target) in v.iter().zip(initialized(ast, t, v, &self.types)) {
+            match target {
+                Ok(Some(t)) => self.initializer(ast, &t, &item.init, at),
+                Ok(None) => {},
+                Err(msg) => {
+                    let span = item_expr(item).map_or(at, |e| self.spans.expr(e));
+                    self.diags.push(Diagnostic::error(msg, span));
+                },
+            }
+        }
+    }
+
+    // The initializer of a variable that isn't a list, or a value in one
+    fn init(&mut self, ast: &Ast, t: &Type, e: ExprId) {
+        let Some(from) = self.expr(ast, e) else {
+            return;
+        };
+        match t.unqualified() {
+            // a string literal fills a char array
+            Type::Array { t, n: _ } if matches!(ast[e], Expr::StrN { .. }) && rank(t) == 1 => {},
+            Type::Array { .. } => {
+                self.error::<()>("array initializer must be an initializer list", e);
+            },
+            _ => {
+                self.convert(ast, e, &from, t, Conversion::Init);
+            },
+        }
+    }
+}
+
+impl Visitor for Checker<'_> {
+    fn visit_decl(&mut self, ast: &Ast, id: DeclId) {
+        match &ast[id] {
+            Decl::Func { t: Type::Function { ret, .. }, id: name, args: _, block: Some(_), spec: _ } => {
+                self.func = Some((name.to_string(), (**ret).clone()));
+                visit::walk_decl(self, ast, id);
+                self.func = None;
+            },
+            // the initializer comes last in the walk, so anything in the type is reported before it
+            Decl::Var { t, id: _, val: Some(val), spec: _ } => {
+                visit::walk_decl(self, ast, id);
+                self.initializer(ast, t, val, self.spans.decl(id));
+            },
+            _ => visit::walk_decl(self, ast, id),
+        }
+    }
+
+    fn visit_stmt(&mut self, ast: &Ast, id: StmtId) {
+        match &ast[id] {
+            Stmt::If { cond, .. } | Stmt::While { cond, .. } => self.condition(ast, *cond, false),
+            Stmt::Switch { cond, .. } => self.condition(ast, *cond, true),
+            Stmt::Return { e } => self.ret(ast, id, *e),
+            // the condition can use what the first clause declares
+            Stmt::For { var, cond, e, block } => {
+                match var {
+                    Some(ForInit::Decl { v }) => v.iter().for_each(|d| self.visit_decl(ast, *d)),
+                    Some(ForInit::Expr { e }) => self.visit_expr(ast, *e),
+                    None => {},
+                }
+                if let Some(cond) = cond {
+                    self.condition(ast, *cond, false);
+                }
+                if let Some(e) = e {
+                    self.visit_expr(ast, *e);
+                }
+                self.visit_stmt(ast, *block);
+                return;
+            },
+            _ => {},
+        }
+        visit::walk_stmt(self, ast, id)
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        self.expr(ast, id);
+    }
+
+    fn visit_type(&mut self, ast: &Ast, t: &Type) {
+        // array sizes in members are expressions too
+        if let Type::Struct { tag: _, id } | Type::Union { tag: _, id } = t {
+            if self.records.insert(*id) {
+                for m in ast.records[*id].members.iter().flatten() {
+                    self.visit_type(ast, &m.t);
+                }
+            }
+        }
+        visit::walk_type(self, ast, t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::lexer::line_col;
+    use crate::parse_source;
+    use crate::sema::resolve::resolve;
+
+    // Diagnostics as `line:col: message`
+    fn errors(src: &str) -> Vec<String> {
+        let tu = parse_source(src).unwrap();
+        check(&tu, &resolve(&tu)).diags.iter()
+            .map(|d| {
+                let (line, col) = line_col(src, d.span.lo);
+                format!("{}:{}: {}", line, col, d.msg)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn types() {
+        let src = "\
+char c; unsigned u; long l; unsigned long ul; long long ll; float f; double d;
+int a[3]; int *p; struct S { int x; const char *s; } st, *sp; const struct S cs;
+int g(int n, ...);
+void t(void) {
+    c + c; c * u; u + l; ll - ul; l + ul; f * 2; f + d; -c; c < 1 == 2;
+    a + 1; 1 + a; p - a; *a; a[1]; 2[a]; &a; p == 0; !p;
+    st.s; sp->x; cs.x; g; g(c); t(); sizeof a; 'x'; \"str\"; (char)l; c = 300; p++;
+}";
+        let tu = parse_source(src).unwrap();
+        let res = resolve(&tu);
+        let typing = check(&tu, &res);
+        assert!(typing.diags.is_empty(), "{:?}", typing.diags);
+        let body = match &tu.ast[*tu.items.last().unwrap()] {
+            Decl::Func { block: Some(b), .. } => match &tu.ast[*b] {
+                Stmt::Block { v } => v.clone(),
+                s => panic!("{:?}", s),
+            },
+            d => panic!("{:?}", d),
+        };
+        let types: Vec<_> = body.iter()
+            .map(|s| match &tu.ast[*s] {
+                Stmt::Expr { e } => type_name(&tu.ast, typing.type_of(*e).unwrap()),
+                s => panic!("{:?}", s),
+            })
+            .collect();
+        assert_eq!(types, vec![
+            "int", "unsigned int", "long", "unsigned long long", "unsigned long", "float", "double", "int", "int",
+            "int *", "int *", "long", "int", "int", "int", "int (*)[3]", "int", "int",
+            "const char *", "int", "const int", "int (int, ...)", "int", "void", "unsigned long", "int", "char[4]", "char", "char", "int *",
+        ]);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(arith_conv(&Type::Short, &Type::UChar), Type::Int);
+        assert_eq!(arith_conv(&Type::Int, &Type::UInt), Type::UInt);
+        assert_eq!(arith_conv(&Type::Long, &Type::UInt), Type::Long);
+        assert_eq!(arith_conv(&Type::LongLong, &Type::ULong), Type::ULongLong);
+        assert_eq!(arith_conv(&Type::ULong, &Type::Float), Type::Float);
+        assert_eq!(arith_conv(&Type::Bool, &Type::Bool), Type::Int);
+        let q = Quals { is_const: true, ..Quals::default() };
+        let a = Type::Array { t: Box::new(Type::Int), n: None }.qualify(q.clone());
+        assert_eq!(decay(&a), Type::Ptr { t: Box::new(Type::Int.qualify(q.clone())) });
+        assert_eq!(decay(&Type::Char.qualify(q)), Type::Char);
+    }
+
+    #[test]
+    fn invalid() {
+        let src = "\
+struct S { int x; } s;
+struct T;
+void f(void) {
+    float x; int i; int *p, *q; char *cp; const int k = 1; void *v; struct T *tp;
+    x % i;
+    p + q;
+    p - cp;
+    i = p;
+    3 = i;
+    k = 2;
+    *i;
+    &3;
+    s.y;
+    i.x;
+    tp->x;
+    i();
+    v + 1;
+    if (s) {}
+    switch (x) {}
+    return 1;
+}
+int g(int a, char *b) { return &a; }
+int h = g(1);
+int m = g(1, 2);
+int n[2] = 1;
+int r() { return; }
+int v(void), old();
+int w = v(1, 2, 3) + old(1, 2);
+int (*vp)(void) = g;
+int (*op)() = g;
+";
+        assert_eq!(errors(src), vec![
+            "5:5: invalid operands to binary expression ('float' and 'int')",
+            "6:5: invalid operands to binary expression ('int *' and 'int *')",
+            "7:5: 'int *' and 'char *' are not pointers to compatible types",
+            "8:9: assigning to 'int' from incompatible type 'int *'",
+            "9:5: expression is not assignable",
+            "10:5: cannot assign to an object of const-qualified type 'const int'",
+            "11:5: indirection requires pointer operand ('int' invalid)",
+            "12:5: cannot take the address of an rvalue of type 'int'",
+            "13:5: no member named 'y' in 'struct S'",
+            "14:5: member reference base type 'int' is not a structure or union",
+            "15:5: incomplete definition of type 'struct T'",
+            "16:5: called object type 'int' is not a function or function pointer",
+            "17:5: arithmetic on a pointer to void",
+            "18:9: statement requires expression of scalar type ('struct S' invalid)",
+            "19:13: statement requires expression of integer type ('float' invalid)",
+            "20:5: void function 'f' should not return a value",
+            "22:32: returning 'int *' from a function with incompatible result type 'int'",
+            "23:9: too few arguments to function call, expected 2, have 1",
+            "24:14: passing 'int' to parameter of incompatible type 'char *'",
+            "25:12: array initializer must be an initializer list",
+            "26:11: non-void function 'r' should return a value",
+            "28:9: too many arguments to function call, expected 0, have 3",
+            "29:19: initializing 'int (*)(void)' with an expression of incompatible type 'int (int, char *)'",
+        ]);
+    }
+
+    #[test]
+    fn initializer_lists() {
+        let src = "\
+int *p[1] = {5};
+struct S { int *q; } s = {1.5};
+struct A { int x; } a = {1, 2, 3};
+struct S t = {.nope = 1};
+int b[2] = {[5] = 1}, c[2] = {1, 2, 3};
+int d = {1, 2}, e = {.x = 1};
+struct P { int a[2]; double d; } pts[2] = {1, 2, 3, {4}, 5};
+void f(void) { (struct A){.y = 1}; (int[1]){[0][1] = 2}; }
+struct P list = {pts[1]};
+";
+        assert_eq!(errors(src), vec![
+            "1:14: initializing 'int *' with an expression of incompatible type 'int'",
+            "2:27: initializing 'int *' with an expression of incompatible type 'double'",
+            "3:29: excess elements in struct initializer",
+            "4:23: field designator 'nope' does not refer to any field in type 'struct S'",
+            "5:14: array designator index (5) exceeds array bounds (2)",
+            "5:37: excess elements in array initializer",
+            "6:13: excess elements in scalar initializer",
+            "6:27: field designator cannot initialize a non-struct, non-union type 'int'",
+            "7:58: excess elements in array initializer",
+            "8:32: field designator 'y' does not refer to any field in type 'struct A'",
+            "8:46: array designator cannot initialize non-array type 'int'",
+            "9:18: initializing 'int' with an expression of incompatible type 'struct P'",
+        ]);
+
+        // braces left out, designators into members, strings filling char arrays
+        let src = "\
+struct P { int a[2]; double d; } pt = {1, 2, 3.0}, pts[2] = {1, 2, 3, {4}}, q = {.a[1] = 1, 2.0}, r = {pt.a[0], {1}};
+struct Q { int k; union { int u; float f; }; } qq = {.f = 1.5}, qs[] = {1, {2}, 3, 4};
+int m[2][2] = {1, 2, 3, 4}, *ps[] = {0, &m[0][0]};
+char cs[2][4] = {\"abc\", \"de\"}, cz[] = {\"x\"};
+struct P copy = pt, list = {{1, 2}, pts[1].d};
+";
+        assert_eq!(errors(src), Vec::<String>::new());
+    }
+
+    #[test]
+    fn sizeof_incomplete_arrays() {
+        let src = "\
+extern int a[];
+int b[] = {1, 2};
+int f(void) {
+    sizeof a;
+    sizeof(int[]);
+    return sizeof b + sizeof \"abc\" + sizeof(\"a\" \"\\n\");
+}
+";
+        assert_eq!(errors(src), vec![
+            "4:5: invalid application of 'sizeof' to an incomplete type 'int[]'",
+            "5:5: invalid application of 'sizeof' to an incomplete type 'int[]'",
+        ]);
+        // a string literal is an array of its characters and the terminating null
+        let tu = parse_source(src).unwrap();
+        let typing = check(&tu, &resolve(&tu));
+        let strs: Vec<_> = tu.ast.exprs.iter().enumerate()
+            .filter(|(_, e)| matches!(e, Expr::StrN { .. }))
+            .map(|(i, _)| type_name(&tu.ast, typing.types[i].as_ref().unwrap()))
+            .collect();
+        assert_eq!(strs, ["char[4]", "char[3]"]);
+    }
+}
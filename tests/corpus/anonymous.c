@@ -0,0 +1,10 @@
+typedef struct { int x, y; } Point;
+typedef enum { LOW, HIGH = 10, TOP } Level;
+struct { int a; struct { char b; } in, *pin; } s1, s2[3];
+enum { ONE = 1, TWO } e1, *e2;
+Point origin = { 0, 0 };
+Level lv = HIGH;
+int area(Point a, const Point *b) {
+    Point c = { .x = a.x - b->x };
+    return c.x * (TOP - LOW) + sizeof(Point);
+}
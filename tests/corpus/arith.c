@@ -0,0 +1,5 @@
+unsigned long int a; long unsigned b; signed c; unsigned d; short e; unsigned short int f;
+long long g; unsigned long long int h; long int long i; long double j; double k; _Bool l;
+signed char m; unsigned char n; char o; int const long p;
+typedef int T;
+int main() { unsigned T; T = (unsigned long)x + sizeof(long long); }
@@ -0,0 +1,8 @@
+int main() {
+    x = (int)y + (char *)p;
+    z = (float)-x;
+    w = *(int (*)[10])q;
+    v = (void (*)(int))h;
+    u = (x);
+    t = (int)x++;
+}
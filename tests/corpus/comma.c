@@ -0,0 +1,6 @@
+int main() {
+    a = 1, b = 2;
+    for (i = 0, j = n; i < j && !done || x; i++, j--) f(a, (b, c), g());
+    x = a - b - c;
+    return a, b;
+}
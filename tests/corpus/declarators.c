@@ -0,0 +1,9 @@
+int *a[10], (*p)[10];
+void (*handler)(int);
+int main(int argc, char **argv) {
+    int x = 2, *y;
+    x += test(a) + 2;
+    return x*6;
+}
+int proto(int, char *[], float (*)(int));
+int (*getfn(int x))(char);
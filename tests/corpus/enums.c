@@ -0,0 +1,9 @@
+enum color { RED, GREEN = 5, BLUE, LAST = BLUE * 2 + (int)GREEN, };
+enum color c;
+int a[BLUE];
+int f(int RED) { return RED + GREEN; }
+int main() {
+    int x = BLUE;
+    { int GREEN; GREEN = 1; }
+    return GREEN;
+}
@@ -0,0 +1,14 @@
+struct point { int x; int y; };
+int a[] = {1, 2, 3,};
+int b[] = { [4] = 1, 2 };
+struct point p = { .x = 1, .y = 2 };
+struct point ps[2][2] = { { {1, 2}, {3, 4} }, [1][0].y = 5 };
+int main() {
+    struct point q = (struct point){1, 2};
+    int n = (struct point){ .y = 3 }.y;
+    int s = sizeof (int[]){1, 2, 3};
+    int *z = (int[2]){};
+    return q.x;
+}
+int m[][2] = {1, 2, 3};
+struct point pp[] = {1, 2, 3, 4, {5, 6}, [7].x = 1, 2};
@@ -0,0 +1,6 @@
+int main() {
+    a[i][j];
+    f(x)(y);
+    tbl[i].fn(x)->next++;
+    (*fp)(1, 2);
+}
@@ -0,0 +1,12 @@
+double d = 1.5;
+int g(int *p, int x, int y) {
+    x = (x + y) * 2 - (x - y) / (y % 3);
+    y = x - (y - 1) + -(-x) + - --x + !(x == y);
+    x = y = *p += 2;
+    (x = y) = 3;
+    x = a || b && c || (a || b) && c;
+    x = (a == b) == (c != d) <= (e < f);
+    y = -*p++ + (*p)++ + ++*p + &*p - *&x;
+    d = 2.0 * (1.25 + d) / 1000.0;
+    return (x, y), x;
+}
@@ -0,0 +1,15 @@
+const char *s;
+int *const p;
+char const * volatile * restrict q;
+static inline int f(register int x, const int y);
+extern _Thread_local int counter;
+typedef const int cint;
+const cint z;
+int static w;
+struct s { const int a; volatile char *b; };
+int main() {
+    static _Thread_local int t;
+    auto int a = (const char *)s;
+    register int r;
+    return 0;
+}
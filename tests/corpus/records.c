@@ -0,0 +1,11 @@
+struct node;
+struct node { int v; struct node *next; };
+struct point { int x, y; } origin, *pp;
+union val { int i; float f; };
+struct { struct inner { int a; } in; union { int i; char c; }; } anon;
+int main(struct point p) {
+    struct node { char c; } local;
+    struct node *n;
+    p.x = pp->y;
+    return n->c;
+}
@@ -0,0 +1,7 @@
+typedef struct node { int v; } node_t;
+int main() {
+    p = malloc(sizeof(node_t) * n);
+    a = sizeof x + sizeof(x) + sizeof (p)->v + sizeof *p + sizeof(int *[3]);
+    b = _Alignof(struct node) + sizeof sizeof x;
+    int _tmp = sizeof(arr)[0];
+}
@@ -0,0 +1,24 @@
+int f(int a, int b) {
+    if (a) if (b) a = 1; else b = 2;
+    if (a) { if (b) a = 1; } else b = 2;
+    if (a > b) {
+        a--;
+    } else if (a < b) {
+        b--;
+    } else {
+        return 0;
+    }
+    while (a) {
+        ;
+        {
+            int c = a;
+            a = c - 1;
+        }
+    }
+    for (;;) break;
+    for (a = 0; a < 10;) a++;
+    if (a) return 1; else if (b) return 2;
+    goto done;
+done:
+    return a;
+}
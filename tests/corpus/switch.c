@@ -0,0 +1,19 @@
+enum st { IDLE, RUN, STOP };
+int step(int s) {
+    switch (s) {
+    case IDLE:
+    case RUN + 10:
+        s = 1;
+    case 3: {
+        switch (x) { default: break; case 1: y; }
+        break;
+    }
+    default:
+        goto cleanup;
+    }
+    for (int i = 0; i; i++) if (i) continue; else if (s) break; else s;
+    for (;;) ;
+    while (s) s--;
+cleanup:
+    return;
+}
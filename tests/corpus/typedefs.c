@@ -0,0 +1,16 @@
+typedef struct node Node;
+typedef int size_t, *intp, (*fn_t)(size_t);
+struct node { Node *next; size_t n; };
+Node *head;
+int f(size_t n, fn_t cb, int (Node));
+int main() {
+    Node *n;
+    x = (size_t)y + (Node *)p;
+    {
+        int size_t;
+        size_t = 3;
+        size_t * n;
+    }
+    size_t * m;
+    return 0;
+}
@@ -0,0 +1,20 @@
+int printf(const char *fmt, ...);
+int noargs(void);
+void (*fp)(int, ...);
+int sum(int n, ...) {
+    va_list ap, aq;
+    int s = 0;
+    va_start(ap, n);
+    va_copy(aq, ap);
+    for (int i = 0; i < n; i++)
+        s += va_arg(ap, int);
+    va_end(aq);
+    va_end(ap);
+    return s;
+}
+char msg[] = "hi\n";
+int main(void) {
+    printf("%d " "sum\n", sum(3, 1, 2, 3));
+    char c = '\n';
+    return 0;
+}